@@ -0,0 +1,229 @@
+//! Swap backend of the Astroport router, enabled with the `astroport` feature.
+//!
+//! Swaps are sent to the router contract set as `swap_router` in the config, which resolves
+//! each hop to the Astroport pair of its two denoms through its factory. Routes are the same as
+//! for Osmosis, but only their denoms are used: the `pool_id` of each hop is ignored. Only
+//! native denoms are supported, as the contract holds the swapped funds in the bank module.
+//!
+//! The router does not return the output in its response data, so it is read from the
+//! `return_amount` of the last pair swap in the events of the reply.
+
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    to_binary, Addr, Coin, CosmosMsg, Decimal, QuerierWrapper, StdError, Storage, SubMsgResult,
+    Uint128, WasmMsg,
+};
+use osmosis_std::types::osmosis::poolmanager::v1beta1::SwapAmountInRoute;
+use serde::Deserialize;
+use std::str::FromStr;
+
+use crate::backend::SwapBackend;
+use crate::contract::AffiliateSwap;
+use crate::error::ContractError;
+
+// The pairs reject swaps above this spread, the min output of the swap is the actual bound
+const MAX_SPREAD_PERCENT: u64 = 50;
+
+#[cw_serde]
+pub enum AssetInfo {
+    Token { contract_addr: Addr },
+    NativeToken { denom: String },
+}
+
+#[cw_serde]
+pub enum SwapOperation {
+    AstroSwap {
+        offer_asset_info: AssetInfo,
+        ask_asset_info: AssetInfo,
+    },
+}
+
+#[cw_serde]
+pub enum RouterExecuteMsg {
+    ExecuteSwapOperations {
+        operations: Vec<SwapOperation>,
+        minimum_receive: Option<Uint128>,
+        to: Option<String>,
+        max_spread: Option<Decimal>,
+    },
+}
+
+#[cw_serde]
+pub enum RouterQueryMsg {
+    Config {},
+    SimulateSwapOperations {
+        offer_amount: Uint128,
+        operations: Vec<SwapOperation>,
+    },
+}
+
+#[cw_serde]
+pub enum FactoryQueryMsg {
+    Pair { asset_infos: Vec<AssetInfo> },
+}
+
+#[cw_serde]
+pub enum PairQueryMsg {
+    Pool {},
+}
+
+// The responses are only partially read, other fields are ignored
+#[derive(Deserialize)]
+struct RouterConfigResponse {
+    astroport_factory: Addr,
+}
+
+#[derive(Deserialize)]
+struct SimulateSwapOperationsResponse {
+    amount: Uint128,
+}
+
+#[derive(Deserialize)]
+struct PairInfo {
+    contract_addr: Addr,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    info: AssetInfo,
+    amount: Uint128,
+}
+
+#[derive(Deserialize)]
+struct PoolResponse {
+    assets: Vec<Asset>,
+}
+
+/// Swaps through `ExecuteSwapOperations` of the Astroport router
+pub struct AstroportBackend {
+    pub router: Addr,
+}
+
+impl AstroportBackend {
+    /// One `AstroSwap` operation per hop, from `denom_in`
+    pub fn operations(routes: &[SwapAmountInRoute], denom_in: &str) -> Vec<SwapOperation> {
+        let mut denom_in = denom_in.to_string();
+        routes
+            .iter()
+            .map(|route| {
+                let operation = SwapOperation::AstroSwap {
+                    offer_asset_info: AssetInfo::NativeToken {
+                        denom: denom_in.clone(),
+                    },
+                    ask_asset_info: AssetInfo::NativeToken {
+                        denom: route.token_out_denom.clone(),
+                    },
+                };
+                denom_in = route.token_out_denom.clone();
+                operation
+            })
+            .collect()
+    }
+}
+
+impl SwapBackend for AstroportBackend {
+    fn load(storage: &dyn Storage) -> Result<Self, ContractError> {
+        let config = AffiliateSwap::new().config.load(storage)?;
+        let router = config
+            .swap_router
+            .ok_or(ContractError::SwapRouterRequired {})?;
+        Ok(AstroportBackend { router })
+    }
+
+    fn swap_msg(
+        &self,
+        _sender: &Addr,
+        routes: Vec<SwapAmountInRoute>,
+        token_in: &Coin,
+        token_out_min_amount: Uint128,
+    ) -> Result<CosmosMsg, ContractError> {
+        // without a recipient, the router sends the output back to the contract
+        let msg = RouterExecuteMsg::ExecuteSwapOperations {
+            operations: Self::operations(&routes, &token_in.denom),
+            minimum_receive: Some(token_out_min_amount),
+            to: None,
+            max_spread: Some(Decimal::percent(MAX_SPREAD_PERCENT)),
+        };
+        Ok(WasmMsg::Execute {
+            contract_addr: self.router.to_string(),
+            msg: to_binary(&msg)?,
+            funds: vec![token_in.clone()],
+        }
+        .into())
+    }
+
+    fn swap_output(&self, result: SubMsgResult) -> Result<Uint128, ContractError> {
+        let response = result.into_result().map_err(StdError::generic_err)?;
+        // the last hop pays the output
+        let return_amount = response
+            .events
+            .iter()
+            .rev()
+            .filter(|event| event.ty == "wasm")
+            .filter(|event| {
+                event
+                    .attributes
+                    .iter()
+                    .any(|a| a.key == "action" && a.value == "swap")
+            })
+            .find_map(|event| event.attributes.iter().find(|a| a.key == "return_amount"))
+            .ok_or(ContractError::SwapOutputMissing {})?;
+        Ok(Uint128::from_str(&return_amount.value)?)
+    }
+
+    fn estimate_swap(
+        &self,
+        querier: &QuerierWrapper,
+        _sender: &Addr,
+        routes: &[SwapAmountInRoute],
+        token_in: &Coin,
+    ) -> Result<Uint128, ContractError> {
+        let simulation: SimulateSwapOperationsResponse = querier.query_wasm_smart(
+            &self.router,
+            &RouterQueryMsg::SimulateSwapOperations {
+                offer_amount: token_in.amount,
+                operations: Self::operations(routes, &token_in.denom),
+            },
+        )?;
+        Ok(simulation.amount)
+    }
+
+    /// The product of the reserve ratios of the pairs of the route. Exact for constant product
+    /// pairs, an approximation for stable ones
+    fn route_spot_price(
+        &self,
+        querier: &QuerierWrapper,
+        routes: &[SwapAmountInRoute],
+        denom_in: &str,
+    ) -> Result<Decimal, ContractError> {
+        let factory = querier
+            .query_wasm_smart::<RouterConfigResponse>(&self.router, &RouterQueryMsg::Config {})?
+            .astroport_factory;
+        let mut price = Decimal::one();
+        for operation in Self::operations(routes, denom_in) {
+            let SwapOperation::AstroSwap {
+                offer_asset_info,
+                ask_asset_info,
+            } = operation;
+            let pair: PairInfo = querier.query_wasm_smart(
+                &factory,
+                &FactoryQueryMsg::Pair {
+                    asset_infos: vec![offer_asset_info.clone(), ask_asset_info.clone()],
+                },
+            )?;
+            let pool: PoolResponse =
+                querier.query_wasm_smart(&pair.contract_addr, &PairQueryMsg::Pool {})?;
+            let reserve = |info: &AssetInfo| {
+                pool.assets
+                    .iter()
+                    .find(|asset| &asset.info == info)
+                    .map(|asset| asset.amount)
+                    .unwrap_or_default()
+            };
+            let hop_price =
+                Decimal::checked_from_ratio(reserve(&ask_asset_info), reserve(&offer_asset_info))?;
+            price = price.checked_mul(hop_price)?;
+        }
+        Ok(price)
+    }
+}
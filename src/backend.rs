@@ -0,0 +1,53 @@
+//! Swap backends: how swaps reach the DEX and how their results are read.
+//!
+//! The affiliate fee logic only needs to build a swap message, read the output from its reply,
+//! and estimate swaps and spot prices. A [`SwapBackend`] provides these for one DEX. The
+//! backend of a build is [`Backend`], the Osmosis pool manager by default, and other backends
+//! are enabled with cargo features.
+
+use cosmwasm_std::{
+    Addr, Coin, CosmosMsg, Decimal, QuerierWrapper, Storage, SubMsgResult, Uint128,
+};
+use osmosis_std::types::osmosis::poolmanager::v1beta1::SwapAmountInRoute;
+
+use crate::error::ContractError;
+
+#[cfg(feature = "astroport")]
+pub use crate::astroport::AstroportBackend as Backend;
+#[cfg(not(feature = "astroport"))]
+pub use crate::osmosis::OsmosisBackend as Backend;
+
+pub trait SwapBackend: Sized {
+    /// Loads the backend with its settings, if it has any
+    fn load(storage: &dyn Storage) -> Result<Self, ContractError>;
+
+    /// Message swapping `token_in` from `sender` along `routes`, for at least
+    /// `token_out_min_amount`
+    fn swap_msg(
+        &self,
+        sender: &Addr,
+        routes: Vec<SwapAmountInRoute>,
+        token_in: &Coin,
+        token_out_min_amount: Uint128,
+    ) -> Result<CosmosMsg, ContractError>;
+
+    /// Output amount of a swap message, from its successful reply
+    fn swap_output(&self, result: SubMsgResult) -> Result<Uint128, ContractError>;
+
+    /// Estimate of the output of swapping `token_in` along `routes` from `sender`
+    fn estimate_swap(
+        &self,
+        querier: &QuerierWrapper,
+        sender: &Addr,
+        routes: &[SwapAmountInRoute],
+        token_in: &Coin,
+    ) -> Result<Uint128, ContractError>;
+
+    /// Spot price of a route, in output per `denom_in`
+    fn route_spot_price(
+        &self,
+        querier: &QuerierWrapper,
+        routes: &[SwapAmountInRoute],
+        denom_in: &str,
+    ) -> Result<Decimal, ContractError>;
+}
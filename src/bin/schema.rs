@@ -0,0 +1,15 @@
+use cosmwasm_schema::write_api;
+
+use affiliate_swap::contract::{
+    ContractExecMsg, ContractQueryMsg, InstantiateMsg, MigrateMsg, SudoMsg,
+};
+
+fn main() {
+    write_api! {
+        instantiate: InstantiateMsg,
+        execute: ContractExecMsg,
+        query: ContractQueryMsg,
+        migrate: MigrateMsg,
+        sudo: SudoMsg,
+    }
+}
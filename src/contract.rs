@@ -1,45 +1,806 @@
+// instantiate and swap take many optional arguments, and so do the message constructors
+// sylvia generates
+#![allow(clippy::too_many_arguments)]
+
 use cosmwasm_schema::cw_serde;
 use cosmwasm_std::{
-    coins, to_binary, Addr, BankMsg, Coin, CosmosMsg, Decimal, Deps, DepsMut, Env, Event,
-    MessageInfo, Reply, Response, SubMsg, Uint128,
-};
-use cw_storage_plus::Item;
-use osmosis_std::types::osmosis::poolmanager::v1beta1::{
-    MsgSwapExactAmountIn, MsgSwapExactAmountInResponse, SwapAmountInRoute,
+    coin, to_binary, Addr, Api, BankMsg, Binary, Coin, Decimal, Deps, DepsMut, Env, Event,
+    MessageInfo, Order, OverflowError, OverflowOperation, QuerierWrapper, Reply, Response,
+    StdResult, Storage, SubMsg, SubMsgResponse, SubMsgResult, Timestamp, Uint128, WasmMsg,
 };
+use cw_storage_plus::{Bound, Index, IndexList, IndexedMap, Item, KeyDeserialize, Map, MultiIndex};
+use cw_utils::PaymentError;
+use osmosis_std::types::osmosis::poolmanager::v1beta1::SwapAmountInRoute;
+use std::collections::BTreeMap;
 use std::str::FromStr;
 use sylvia::contract;
 
-use crate::error::ContractError;
+use crate::backend::{Backend, SwapBackend};
+use crate::error::{ContractError, SwapContext, SwapFailure};
+use crate::fee_override::{self, FeeOverride};
+use crate::ibc_hooks::{self, IbcOrigin};
+use crate::interface::{affiliate_swap_interface, AffiliateSwapInterface};
+use crate::migrations;
+use crate::osmosis;
+use crate::quote::{self, RouteQuote};
+use crate::state_export::{self, ExportStateResponse, StateEntry, StateSection};
+use crate::vault::{VaultExecuteMsg, VaultInfoResponse, VaultQueryMsg};
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:affiliate_swap";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+// Interfaces declared through cw22 so routers can discover what this contract speaks.
+// The affiliate swap interface is versioned together with the contract.
+const SUPPORTED_INTERFACES: [(&str, &str); 3] = [
+    (CONTRACT_NAME, CONTRACT_VERSION),
+    ("crates.io:cw2", "1.0.1"),
+    ("crates.io:cw22", "0.2.0"),
+];
+
+// cw22 storage layout: interface name -> semver version, under the "supported_interfaces"
+// namespace so that other contracts can read it with a raw query.
+const SUPPORTED_INTERFACES_MAP: Map<&str, String> = Map::new("supported_interfaces");
+
 pub const DEFAULT_MAX_FEE: &str = "1.5";
 pub const TRUE_MAX_FEE: &str = "10";
 
-// Temporary storage of active swap
+// Number of blocks after which an active swap can be cleared by anyone
+pub const DEFAULT_STALE_SWAP_BLOCKS: u64 = 100;
+// Max number of hops in a route
+pub const DEFAULT_MAX_ROUTE_LENGTH: u32 = 5;
+// Max tip paid to the caller of a crank, in basis points of the processed amounts
+pub const MAX_CRANK_TIP_BPS: u64 = 100;
+// Slippage tolerated by fee settlement swaps below their estimate, in basis points
+pub const DEFAULT_SETTLEMENT_SLIPPAGE_BPS: u64 = 100;
+// Max number of stale swaps cleared in a single call to keep gas bounded
+const MAX_STALE_SWAPS_CLEARED: usize = 30;
+// Page sizes of the children listing
+const DEFAULT_CHILDREN_LIMIT: u32 = 10;
+const MAX_CHILDREN_LIMIT: u32 = 30;
+// Page sizes of the pair fees listing
+const DEFAULT_PAIR_FEES_LIMIT: u32 = 10;
+const MAX_PAIR_FEES_LIMIT: u32 = 30;
+// Page sizes of the daily stats listing
+const DEFAULT_STATS_LIMIT: u32 = 30;
+const MAX_STATS_LIMIT: u32 = 100;
+// Page sizes of the denied pools listing
+const DEFAULT_DENIED_POOLS_LIMIT: u32 = 30;
+const MAX_DENIED_POOLS_LIMIT: u32 = 100;
+const DEFAULT_ALLOWED_SENDERS_LIMIT: u32 = 30;
+const MAX_ALLOWED_SENDERS_LIMIT: u32 = 100;
+const DEFAULT_CALLBACK_TARGETS_LIMIT: u32 = 30;
+const MAX_CALLBACK_TARGETS_LIMIT: u32 = 100;
+// upper bound of the gas limit of a callback
+pub const MAX_CALLBACK_GAS_LIMIT: u64 = 2_000_000;
+const DEFAULT_FEE_HOLIDAYS_LIMIT: u32 = 30;
+const MAX_FEE_HOLIDAYS_LIMIT: u32 = 100;
+// Number of expired referrals removed per clear_expired_referrals call
+const DEFAULT_CLEAR_REFERRALS_LIMIT: u32 = 30;
+const MAX_CLEAR_REFERRALS_LIMIT: u32 = 100;
+// Length of a stats bucket
+const SECONDS_PER_DAY: u64 = 86_400;
+// Length of a rolling volume bucket
+const SECONDS_PER_HOUR: u64 = 3_600;
+// instantiate2 salts are at most 64 bytes
+const MAX_CHILD_LABEL_LENGTH: usize = 64;
+pub const MAX_CLIENT_ID_LENGTH: usize = 64;
+pub const MAX_CHANNEL_LENGTH: usize = 32;
+// Bounds of the display metadata of a denom
+pub const MAX_SYMBOL_LENGTH: usize = 32;
+pub const MAX_DENOM_DECIMALS: u32 = 18;
+// A client id cannot be reused by the same sender for this long after its swap
+pub const CLIENT_ID_RETENTION_SECONDS: u64 = SECONDS_PER_DAY;
+
+// Version of the attribute schema of the events emitted by this contract. It is attached to
+// every event as `event_version` so indexers can branch on it.
+//
+// Attribute naming policy:
+//  - attribute keys are snake_case and never change meaning once released
+//  - amounts with a denom are rendered as coin strings (e.g. `100uosmo`), bare amounts as integers
+//  - adding attributes is backwards compatible and doesn't require a bump
+//  - renaming, removing or changing the format of an attribute requires bumping EVENT_VERSION
+pub const EVENT_VERSION: &str = "3";
+
+// Storage key of the contract configuration. This is part of the public interface:
+// indexers and relayers read the config directly with `WasmQuery::Raw` on this key,
+// so it must not change between versions.
+pub const CONFIG_KEY: &str = "config";
+
+#[cw_serde]
+pub struct Config {
+    pub owner: Addr,
+    // max fee in basis points: 150 is a 1.5% max fee
+    pub max_fee_bps: u64,
+    // active swaps older than this many blocks can be cleared with ClearStaleSwap
+    #[serde(default = "default_stale_swap_blocks")]
+    pub stale_swap_blocks: u64,
+    // reject swaps whose fee collector is the sender
+    #[serde(default)]
+    pub reject_self_referral: bool,
+    // max number of hops in a route
+    #[serde(default = "default_max_route_length")]
+    pub max_route_length: u32,
+    // when set, swaps must carry a route quote signed by this secp256k1 public key
+    #[serde(default)]
+    pub router_pubkey: Option<Binary>,
+    // when set, every fee is sent to this address (white-label instances)
+    #[serde(default)]
+    pub fee_collector: Option<Addr>,
+    #[serde(default)]
+    pub branding: Option<Branding>,
+    // tip paid to the caller of permissionless cranks (clear_stale_swap) out of the amounts
+    // they process, in basis points
+    #[serde(default)]
+    pub crank_tip_bps: u64,
+    // share of each fee rebated to the swapper, in basis points of the fee
+    #[serde(default)]
+    pub rebate_bps: u64,
+    // how the output above the expected output of a swap is shared, if at all
+    #[serde(default)]
+    pub surplus_sharing: Option<SurplusSharing>,
+    // how long, in seconds, swaps without a fee collector are attributed to the first fee
+    // collector of their sender. 0 disables sticky referrals
+    #[serde(default)]
+    pub referral_duration: u64,
+    // when set, only allowlisted senders may swap
+    #[serde(default)]
+    pub sender_allowlist: bool,
+    // when set, the balances of the contract are checked against the output reported by the
+    // pools after each swap
+    #[serde(default)]
+    pub balance_checks: bool,
+    // router contract swaps go through, for backends swapping through one (astroport)
+    #[serde(default)]
+    pub swap_router: Option<Addr>,
+    // when set, swaps may carry a fee override signed by this secp256k1 public key, charging
+    // a fee above the max fee
+    #[serde(default)]
+    pub fee_override_pubkey: Option<Binary>,
+    // slippage tolerated by fee settlement swaps below their estimate, in basis points
+    #[serde(default = "default_settlement_slippage_bps")]
+    pub settlement_slippage_bps: u64,
+}
+
+/// Shares of the output above `expected_out` (positive slippage) taken from the swapper, in
+/// basis points of that surplus
+#[cw_serde]
+pub struct SurplusSharing {
+    pub fee_collector_bps: u64,
+    pub treasury_bps: u64,
+    pub treasury: Option<Addr>,
+}
+
+/// Bounds of the full input (fee included) of swaps from a denom
+#[cw_serde]
+#[derive(Default)]
+pub struct InputLimits {
+    #[serde(default)]
+    pub min_input: Option<Uint128>,
+    pub max_input: Option<Uint128>,
+    // max input of each sender over the last 24 hours
+    #[serde(default)]
+    pub max_daily_volume: Option<Uint128>,
+}
+
+/// First fee collector of a sender, attributed its later swaps without a fee collector
+#[cw_serde]
+pub struct Referral {
+    pub fee_collector: Addr,
+    pub referred_at: Timestamp,
+    // fixed when the referral is made: later changes of the duration do not extend it
+    pub expires_at: Timestamp,
+}
+
+/// What a swap may do when calling an allowlisted contract back
+#[cw_serde]
+pub struct CallbackPolicy {
+    // gas the callback may use. Running out fails the callback, not the swap
+    pub gas_limit: u64,
+    // whether the output of the swap may be sent with the callback
+    pub allow_funds: bool,
+}
+
+/// Display metadata of a denom, reported next to the raw denom in swap events and responses
+#[cw_serde]
+pub struct DenomMetadata {
+    // e.g. `OSMO` for `uosmo` or `ATOM` for its IBC denom
+    pub symbol: String,
+    // exponent of the display unit, e.g. 6 for `uosmo`
+    pub decimals: u32,
+}
+
+/// Time window during which swaps pay no fee, e.g. for a promotion
+#[cw_serde]
+pub struct FeeHoliday {
+    pub start: Timestamp,
+    // excluded: swaps pay their fee again from this block time on
+    pub end: Timestamp,
+}
+
+/// Display metadata of a white-label instance
+#[cw_serde]
+pub struct Branding {
+    pub name: String,
+    pub website: Option<String>,
+    pub logo_uri: Option<String>,
+}
+
+// White-label instance deployed by this contract with `create_child`
+#[cw_serde]
+pub struct ChildInfo {
+    pub label: String,
+    pub fee_collector: Addr,
+    pub branding: Branding,
+}
+
+/// Fee override of a `(token_in, token_out)` pair. It takes precedence over the max fee of
+/// the config.
+#[cw_serde]
+pub enum PairFee {
+    /// Caps the fee of the pair at this percentage instead of the max fee
+    Max { max_fee_percentage: Decimal },
+    /// Charges this percentage on every swap of the pair, whatever fee is requested
+    Fixed { fee_percentage: Decimal },
+}
+
+impl PairFee {
+    fn percentage(&self) -> Decimal {
+        match self {
+            PairFee::Max { max_fee_percentage } => *max_fee_percentage,
+            PairFee::Fixed { fee_percentage } => *fee_percentage,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ContractError> {
+        validate_max_fee(self.percentage())?;
+        // same precision as the max fee
+        percentage_to_bps(self.percentage())?;
+        Ok(())
+    }
+}
+
+/// Fee discount of senders whose lifetime volume in a denom reaches `min_volume`
+#[cw_serde]
+pub struct VolumeDiscount {
+    pub min_volume: Uint128,
+    // share of the fee waived: 0.25 waives a quarter of the fee
+    pub discount: Decimal,
+}
+
+// Totals of the successful swaps of a day from one input denom
+#[cw_serde]
+#[derive(Default)]
+pub struct Stats {
+    // full input, fee included
+    pub volume: Uint128,
+    pub fees: Uint128,
+    pub swap_count: u64,
+}
+
+/// Secondary indexes of the daily stats, keyed by (day, input denom)
+pub struct DailyStatsIndexes<'a> {
+    // by input denom, for the time series of one denom without going through the others
+    pub denom: MultiIndex<'a, String, Stats, (u64, &'a str)>,
+}
+
+impl<'a> IndexList<Stats> for DailyStatsIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Stats>> + '_> {
+        let indexes: Vec<&dyn Index<Stats>> = vec![&self.denom];
+        Box::new(indexes.into_iter())
+    }
+}
+
+/// Input denom of the daily stats with the primary key `pk`
+fn daily_stats_denom(pk: &[u8], _stats: &Stats) -> String {
+    // the primary keys are all written by the contract
+    <(u64, String)>::from_slice(pk)
+        .map(|(_, denom)| denom)
+        .unwrap_or_default()
+}
+
+/// Secondary indexes of the daily stats per fee collector, keyed by (day, fee collector, input
+/// denom). Leaderboards read a day (or range of days) from the primary keys
+pub struct CollectorStatsIndexes<'a> {
+    // by fee collector, for the time series of one collector without going through the others
+    pub fee_collector: MultiIndex<'a, Addr, Stats, (u64, &'a Addr, &'a str)>,
+}
+
+impl<'a> IndexList<Stats> for CollectorStatsIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Stats>> + '_> {
+        let indexes: Vec<&dyn Index<Stats>> = vec![&self.fee_collector];
+        Box::new(indexes.into_iter())
+    }
+}
+
+/// Fee collector of the collector stats with the primary key `pk`
+fn collector_stats_fee_collector(pk: &[u8], _stats: &Stats) -> Addr {
+    // the primary keys are all written by the contract
+    <(u64, Addr, String)>::from_slice(pk)
+        .map(|(_, fee_collector, _)| fee_collector)
+        .unwrap_or_else(|_| Addr::unchecked(""))
+}
+
+fn default_stale_swap_blocks() -> u64 {
+    DEFAULT_STALE_SWAP_BLOCKS
+}
+
+fn default_max_route_length() -> u32 {
+    DEFAULT_MAX_ROUTE_LENGTH
+}
+
+fn default_settlement_slippage_bps() -> u64 {
+    DEFAULT_SETTLEMENT_SLIPPAGE_BPS
+}
+
+impl Config {
+    pub fn max_fee_percentage(&self) -> Decimal {
+        bps_to_percentage(self.max_fee_bps)
+    }
+
+    pub fn crank_tip_percentage(&self) -> Decimal {
+        bps_to_percentage(self.crank_tip_bps)
+    }
+
+    pub fn rebate_percentage(&self) -> Decimal {
+        bps_to_percentage(self.rebate_bps)
+    }
+
+    pub fn settlement_slippage_tolerance(&self) -> Decimal {
+        bps_to_percentage(self.settlement_slippage_bps)
+    }
+
+    /// Checks the settings the way the setters do, for configs not built by them
+    pub fn validate(&self, api: &dyn Api) -> Result<(), ContractError> {
+        validate_max_fee(self.max_fee_percentage())?;
+        validate_max_route_length(self.max_route_length)?;
+        if let Some(router_pubkey) = &self.router_pubkey {
+            quote::validate_router_pubkey(router_pubkey)?;
+        }
+        if let Some(pubkey) = &self.fee_override_pubkey {
+            validate_fee_override_pubkey(pubkey)?;
+        }
+        validate_crank_tip_bps(self.crank_tip_bps)?;
+        validate_rebate_bps(self.rebate_bps)?;
+        validate_settlement_slippage_bps(self.settlement_slippage_bps)?;
+        if let Some(surplus_sharing) = &self.surplus_sharing {
+            surplus_sharing.validate()?;
+        }
+        let treasury = self
+            .surplus_sharing
+            .as_ref()
+            .and_then(|sharing| sharing.treasury.as_ref());
+        for address in [
+            self.fee_collector.as_ref(),
+            self.swap_router.as_ref(),
+            treasury,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            api.addr_validate(address.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+impl SurplusSharing {
+    pub fn validate(&self) -> Result<(), ContractError> {
+        if self.fee_collector_bps.saturating_add(self.treasury_bps) > 10_000
+            || (self.treasury_bps > 0 && self.treasury.is_none())
+        {
+            return Err(ContractError::InvalidSurplusSharing {});
+        }
+        Ok(())
+    }
+}
+
+impl InputLimits {
+    pub fn validate(&self) -> Result<(), ContractError> {
+        if let (Some(min_input), Some(max_input)) = (self.min_input, self.max_input) {
+            if min_input > max_input {
+                return Err(ContractError::InvalidInputLimits {});
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DenomMetadata {
+    pub fn validate(&self) -> Result<(), ContractError> {
+        if self.symbol.is_empty()
+            || self.symbol.len() > MAX_SYMBOL_LENGTH
+            || self.decimals > MAX_DENOM_DECIMALS
+        {
+            return Err(ContractError::InvalidDenomMetadata {
+                max_symbol_length: MAX_SYMBOL_LENGTH,
+                max_decimals: MAX_DENOM_DECIMALS,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Converts a percentage (1.5 for 1.5%) to basis points, rejecting percentages that are not a
+/// whole number of basis points. Callers are expected to have validated the range already.
+pub fn percentage_to_bps(percentage: Decimal) -> Result<u64, ContractError> {
+    let bps = percentage.checked_mul(Decimal::from_ratio(100u64, 1u64))?;
+    if bps.floor() != bps {
+        return Err(ContractError::InvalidFeePrecision {});
+    }
+    u64::try_from(bps.to_uint_floor().u128()).map_err(|_| ContractError::Unexpected {})
+}
+
+pub fn bps_to_percentage(bps: u64) -> Decimal {
+    Decimal::from_ratio(bps, 100u64)
+}
+
+/// Fee charged on `amount` for a fee percentage (1.5 is 1.5%), rounded down.
+pub fn fee_amount(amount: Uint128, fee_percentage: Decimal) -> Result<Uint128, ContractError> {
+    // amount * fee_percentage / 100, computed on the decimal atomics without intermediate overflow
+    let denominator = Decimal::one().atomics().u128() * 100;
+    amount
+        .checked_multiply_ratio(fee_percentage.atomics(), denominator)
+        .map_err(|_| OverflowError::new(OverflowOperation::Mul, amount, fee_percentage).into())
+}
+
+/// How much worse than `spot_price` (output per input) a swap of `token_in` for `token_out`
+/// executes, in percent (1.5 for 1.5%). Zero if it executes at or above the spot price.
+pub fn price_impact(
+    token_in: Uint128,
+    token_out: Uint128,
+    spot_price: Decimal,
+) -> Result<Decimal, ContractError> {
+    let execution_price = Decimal::checked_from_ratio(token_out, token_in)?;
+    if execution_price >= spot_price {
+        return Ok(Decimal::zero());
+    }
+    let ratio = execution_price.checked_div(spot_price)?;
+    Ok((Decimal::one() - ratio) * Decimal::from_ratio(100u64, 1u64))
+}
+
+/// Rejects a swap whose price `impact` is above `max`
+fn check_price_impact(impact: Decimal, max: Decimal) -> Result<(), ContractError> {
+    if impact > max {
+        return Err(ContractError::PriceImpactTooHigh {
+            impact: impact.to_string(),
+            max: max.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Min output of a swap. With a slippage tolerance, it is derived from the `estimate`, and
+/// the stricter of both applies when a min output is also `provided`
+fn min_output(
+    provided: Option<&Coin>,
+    slippage_tolerance: Option<Decimal>,
+    estimate: Option<Uint128>,
+) -> Result<Uint128, ContractError> {
+    let estimated_min = match (slippage_tolerance, estimate) {
+        (Some(tolerance), Some(estimate)) => Some(estimate - fee_amount(estimate, tolerance)?),
+        _ => None,
+    };
+    match (provided, estimated_min) {
+        (Some(provided), Some(estimated)) => Ok(provided.amount.max(estimated)),
+        (Some(provided), None) => Ok(provided.amount),
+        (None, Some(estimated)) => Ok(estimated),
+        (None, None) => Err(ContractError::MinOutputRequired {}),
+    }
+}
+
+/// Balances of the contract in the input and output denoms of a swap
+fn swap_balances(
+    querier: &QuerierWrapper,
+    env: &Env,
+    denom_in: &str,
+    denom_out: &str,
+) -> StdResult<Vec<Coin>> {
+    let mut denoms = vec![denom_in];
+    if denom_out != denom_in {
+        denoms.push(denom_out);
+    }
+    denoms
+        .into_iter()
+        .map(|denom| querier.query_balance(&env.contract.address, denom))
+        .collect()
+}
+
+/// Messages from the chain itself, e.g. pushed by governance
+#[cw_serde]
+pub enum SudoMsg {
+    /// Sets the max fee and whether swaps are paused, overriding the owner's settings, for
+    /// deployments whose safety bounds are owned by chain governance
+    SyncParams {
+        max_fee_percentage: Decimal,
+        paused: bool,
+    },
+}
+
+// Present only while the contract is paused
+#[cw_serde]
+pub struct PauseInfo {
+    pub paused_by: Addr,
+    pub paused_at: Timestamp,
+}
+
+// Temporary storage of an active swap, keyed by swap id in `active_swaps`.
+// Only what the reply needs is stored.
 #[cw_serde]
 pub struct ActiveSwap {
+    pub swap_id: u64,
+    // swaps stored before this was recorded are considered stale
+    #[serde(default)]
+    pub created_at_height: u64,
     pub original_sender: Addr,
     pub fee: Coin,
+    pub fee_percentage: Decimal,
+    pub fee_collector: Addr,
+    pub token_in: Coin,
+    pub token_out_denom: String,
+    // Only used to report the swap if it fails
+    #[serde(default)]
+    pub token_out_min_amount: Uint128,
+    // Only used to report the route in the response
+    pub route: Vec<SwapAmountInRoute>,
+    // Set when the swap should be refunded to this address instead of reverted on failure
+    pub refund_address: Option<Addr>,
+    // Recipient of the output, the original sender if not set
+    #[serde(default)]
+    pub receiver: Option<Addr>,
+    // Set for swaps received through ibc-hooks, once verified
+    #[serde(default)]
+    pub ibc_origin: Option<IbcOrigin>,
+    // Output the swapper expects, above which the surplus is shared
+    #[serde(default)]
+    pub expected_out: Option<Uint128>,
+    // Balances of the contract in the input and output denoms before the swap, recorded when
+    // balance checks are enabled
+    #[serde(default)]
+    pub balances_before: Vec<Coin>,
+    #[serde(default)]
+    pub response_data: ResponseData,
+    // Validated action to run with the output, if any
+    #[serde(default)]
+    pub after: Option<AfterSwap>,
+    // Idempotency key of the caller, released if the swap is refunded
+    #[serde(default)]
+    pub client_id: Option<String>,
+    // Integration surface the swap came from, for analytics
+    #[serde(default)]
+    pub channel: Option<String>,
+    // Whether `fee_percentage` comes from a signed fee override
+    #[serde(default)]
+    pub fee_overridden: bool,
+}
+
+impl ActiveSwap {
+    fn receiver(&self) -> &Addr {
+        self.receiver.as_ref().unwrap_or(&self.original_sender)
+    }
+
+    fn context(&self) -> SwapContext {
+        SwapContext {
+            route: route_to_string(&self.route),
+            token_in: self.token_in.clone(),
+            token_out_min: Coin {
+                denom: self.token_out_denom.clone(),
+                amount: self.token_out_min_amount,
+            },
+        }
+    }
+}
+
+// What `validate_swap` extracts from a swap message
+struct ValidatedSwap {
+    coin: Coin,
+    // attached coins other than the input, refunded to the sender
+    extra_funds: Vec<Coin>,
+    token_out_denom: String,
+    fee_collector: Addr,
+    after: Option<AfterSwap>,
+    // fee percentage of a verified fee override
+    fee_override: Option<Decimal>,
+}
+
+// Swap output being sent to the user, kept until the transfer reply so a failed transfer can
+// be escrowed for `recipient` instead of reverting the swap
+#[cw_serde]
+pub struct PendingOutput {
+    pub recipient: Addr,
+    pub amount: Coin,
+    // share of the fee rebated to the recipient, sent along with the output
+    #[serde(default)]
+    pub rebate: Option<Coin>,
+}
+
+// Fee being swapped into the settlement denom of its collector, kept until the settlement
+// reply so a failed swap can pay the fee as is
+#[cw_serde]
+pub struct PendingSettlement {
+    pub fee_collector: Addr,
+    pub fee: Coin,
+    pub settlement_denom: String,
+}
+
+// Fee being sent to its collector, kept until the transfer reply so a failed transfer (e.g. to a
+// module account or blocked address) can be escrowed for the collector instead of reverting the
+// swap
+#[cw_serde]
+pub struct PendingFee {
     pub fee_collector: Addr,
-    pub swap_msg: MsgSwapExactAmountIn,
+    pub fee: Coin,
+}
+
+// Share of a swap sent apart from the output (a surplus share, or the rebate of a swap with an
+// after action),
+// kept until the transfer reply so a failed transfer can be escrowed for `recipient` instead of
+// reverting the swap
+#[cw_serde]
+pub struct PendingShare {
+    pub share: String,
+    pub recipient: Addr,
+    pub amount: Coin,
+}
+
+impl PendingOutput {
+    /// Coins sent to the recipient, the rebate merged into the output when of the same denom
+    fn coins(&self) -> Result<Vec<Coin>, ContractError> {
+        let mut coins = vec![self.amount.clone()];
+        if let Some(rebate) = &self.rebate {
+            match coins.iter_mut().find(|c| c.denom == rebate.denom) {
+                Some(output) => output.amount = output.amount.checked_add(rebate.amount)?,
+                None => coins.push(rebate.clone()),
+            }
+        }
+        Ok(coins)
+    }
+}
+
+// What to do when the swap submessage fails
+#[cw_serde]
+pub enum OnFailure {
+    // Revert the whole transaction (default)
+    Revert,
+    // Send the swap input to `refund_address` (or the sender if not provided) and emit an
+    // `affiliate_swap_failed` event instead of erroring. Meant for ibc-hooks callers, for which a
+    // revert fails the packet with nothing actionable on the origin chain.
+    RefundAndAck { refund_address: Option<String> },
+}
+
+// What to do with the swap output instead of sending it to the receiver. If the action fails,
+// the output is escrowed for the receiver, as after a failed transfer
+#[cw_serde]
+pub enum AfterSwap {
+    // Executes `msg` on `contract` with the output attached. The contract must be a callback
+    // target allowing funds, and the execution is bounded by its gas limit
+    Execute { contract: String, msg: Binary },
+    // Deposits the output into a cw-vault-standard `vault`, whose shares are minted to the
+    // receiver. The vault must be a callback target allowing funds, with the output denom as
+    // base token
+    VaultDeposit { vault: String },
+}
+
+// What the swap sets as response data once it has succeeded
+#[cw_serde]
+#[derive(Default)]
+pub enum ResponseData {
+    // The JSON `SwapResponse` (default)
+    #[default]
+    Json,
+    // The protobuf `MsgSwapExactAmountInResponse` of the pool manager, for callers expecting
+    // the response of a direct swap
+    Proto,
+    // No data, only the events
+    None,
 }
 
 pub struct AffiliateSwap<'a> {
-    pub(crate) max_fee_percentage: Item<'a, Decimal>,
-    pub(crate) active_swap: Item<'a, ActiveSwap>,
+    pub(crate) config: Item<'a, Config>,
+    pub(crate) active_swaps: Map<'a, u64, ActiveSwap>,
+    pub(crate) swap_count: Item<'a, u64>,
+    pub(crate) pause_info: Item<'a, PauseInfo>,
+    pub(crate) pending_outputs: Map<'a, u64, PendingOutput>,
+    // keyed by swap id
+    pub(crate) pending_settlements: Map<'a, u64, PendingSettlement>,
+    pub(crate) pending_fees: Map<'a, u64, PendingFee>,
+    // keyed by (swap id, reply kind of the transfer)
+    pub(crate) pending_shares: Map<'a, (u64, u8), PendingShare>,
+    // swap outputs that could not be sent, claimable by their recipient
+    pub(crate) claimable: Map<'a, (&'a Addr, &'a str), Uint128>,
+    // addresses allowed to claim for a claimant, keyed by (claimant, operator)
+    pub(crate) claim_operators: Map<'a, (&'a Addr, &'a Addr), ()>,
+    pub(crate) child_count: Item<'a, u64>,
+    // children being instantiated, until the instantiate reply provides their address
+    pub(crate) pending_children: Map<'a, u64, ChildInfo>,
+    pub(crate) children: Map<'a, &'a Addr, ChildInfo>,
+    // fee overrides, keyed by (token in denom, token out denom)
+    pub(crate) pair_fees: Map<'a, (&'a str, &'a str), PairFee>,
+    // lifetime input volume of successful swaps, keyed by (sender, input denom)
+    pub(crate) volumes: Map<'a, (&'a Addr, &'a str), Uint128>,
+    // discount brackets of each input denom, by increasing min volume
+    pub(crate) volume_discounts: Map<'a, &'a str, Vec<VolumeDiscount>>,
+    // keyed by (day since the unix epoch, input denom)
+    pub(crate) daily_stats: IndexedMap<'a, (u64, &'a str), Stats, DailyStatsIndexes<'a>>,
+    // daily stats of the swaps tagged with a channel, keyed by (channel, day, denom)
+    pub(crate) channel_stats: Map<'a, (&'a str, u64, &'a str), Stats>,
+    pub(crate) collector_stats:
+        IndexedMap<'a, (u64, &'a Addr, &'a str), Stats, CollectorStatsIndexes<'a>>,
+    // sticky referrals, keyed by sender
+    pub(crate) referrals: Map<'a, &'a Addr, Referral>,
+    // referrals by expiry, keyed by (expiry in seconds, sender), for clear_expired_referrals
+    pub(crate) referral_expiries: Map<'a, (u64, &'a Addr), ()>,
+    // pools routes may not go through
+    pub(crate) denied_pools: Map<'a, u64, ()>,
+    // keyed by input denom
+    pub(crate) input_limits: Map<'a, &'a str, InputLimits>,
+    // input of the last 24 hours in hourly buckets, (hour since the unix epoch, input), keyed by
+    // (sender, input denom). Only tracked for denoms with a max daily volume
+    pub(crate) rolling_volumes: Map<'a, (&'a Addr, &'a str), Vec<(u64, Uint128)>>,
+    // senders allowed to swap while the sender allowlist is enabled
+    pub(crate) allowed_senders: Map<'a, &'a Addr, ()>,
+    // non-overlapping fee holidays, keyed by start time in nanoseconds
+    pub(crate) fee_holidays: Map<'a, u64, FeeHoliday>,
+    // contracts swaps may call back, with what they may do
+    pub(crate) callback_targets: Map<'a, &'a Addr, CallbackPolicy>,
+    // routes fees are swapped along before being paid, keyed by (fee collector, fee denom)
+    pub(crate) settlement_routes: Map<'a, (&'a Addr, &'a str), Vec<SwapAmountInRoute>>,
+    // time of the last swap of each (sender, client id), to reject duplicates
+    pub(crate) client_ids: Map<'a, (&'a Addr, &'a str), Timestamp>,
+    // display metadata seeded by the owner, keyed by denom
+    pub(crate) denom_metadata: Map<'a, &'a str, DenomMetadata>,
 }
 
 #[contract(error=ContractError)]
+#[messages(affiliate_swap_interface as AffiliateSwapInterface)]
 impl<'a> AffiliateSwap<'a> {
     /// Create an AffiliateSwap instance.
-    pub const fn new() -> Self {
+    pub fn new() -> Self {
         Self {
-            max_fee_percentage: Item::new("max_fee"),
-            active_swap: Item::new("active_swap"),
+            config: Item::new(CONFIG_KEY),
+            active_swaps: Map::new("active_swaps"),
+            swap_count: Item::new("swap_count"),
+            pause_info: Item::new("pause_info"),
+            pending_outputs: Map::new("pending_outputs"),
+            pending_settlements: Map::new("pending_settlements"),
+            pending_fees: Map::new("pending_fees"),
+            pending_shares: Map::new("pending_shares"),
+            claimable: Map::new("claimable"),
+            claim_operators: Map::new("claim_operators"),
+            child_count: Item::new("child_count"),
+            pending_children: Map::new("pending_children"),
+            children: Map::new("children"),
+            pair_fees: Map::new("pair_fees"),
+            volumes: Map::new("volumes"),
+            volume_discounts: Map::new("volume_discounts"),
+            daily_stats: IndexedMap::new(
+                "daily_stats",
+                DailyStatsIndexes {
+                    denom: MultiIndex::new(daily_stats_denom, "daily_stats", "daily_stats__denom"),
+                },
+            ),
+            channel_stats: Map::new("channel_stats"),
+            collector_stats: IndexedMap::new(
+                "collector_stats",
+                CollectorStatsIndexes {
+                    fee_collector: MultiIndex::new(
+                        collector_stats_fee_collector,
+                        "collector_stats",
+                        "collector_stats__fee_collector",
+                    ),
+                },
+            ),
+            referrals: Map::new("referrals"),
+            referral_expiries: Map::new("referral_expiries"),
+            denied_pools: Map::new("denied_pools"),
+            input_limits: Map::new("input_limits"),
+            rolling_volumes: Map::new("rolling_volumes"),
+            allowed_senders: Map::new("allowed_senders"),
+            fee_holidays: Map::new("fee_holidays"),
+            callback_targets: Map::new("callback_targets"),
+            settlement_routes: Map::new("settlement_routes"),
+            client_ids: Map::new("client_ids"),
+            denom_metadata: Map::new("denom_metadata"),
         }
     }
 
@@ -49,184 +810,3765 @@ impl<'a> AffiliateSwap<'a> {
         &self,
         ctx: (DepsMut, Env, MessageInfo),
         max_fee_percentage: Option<Decimal>,
+        owner: Option<String>,
+        stale_swap_blocks: Option<u64>,
+        reject_self_referral: Option<bool>,
+        max_route_length: Option<u32>,
+        router_pubkey: Option<Binary>,
+        fee_collector: Option<String>,
+        branding: Option<Branding>,
     ) -> Result<Response, ContractError> {
-        let (deps, _env, _info) = ctx;
+        let (deps, _env, info) = ctx;
 
         // store contract version for migration info
         cw2::set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+        store_supported_interfaces(deps.storage)?;
 
-        let max_fee = max_fee_percentage.unwrap_or(Decimal::from_str(DEFAULT_MAX_FEE)?);
-        if max_fee < Decimal::zero() || max_fee > Decimal::from_str(TRUE_MAX_FEE)? {
-            return Err(ContractError::InvalidMaxFeePercentage {
-                true_max_fee: TRUE_MAX_FEE.to_string(),
-            });
+        let max_fee =
+            validate_max_fee(max_fee_percentage.unwrap_or(Decimal::from_str(DEFAULT_MAX_FEE)?))?;
+
+        // the owner defaults to the instantiator
+        let owner = match owner {
+            Some(owner) => deps.api.addr_validate(&owner)?,
+            None => info.sender,
+        };
+
+        if let Some(router_pubkey) = &router_pubkey {
+            quote::validate_router_pubkey(router_pubkey)?;
         }
+        let fee_collector = fee_collector
+            .map(|fee_collector| deps.api.addr_validate(&fee_collector))
+            .transpose()?;
 
-        // set the max fee
-        self.max_fee_percentage.save(deps.storage, &max_fee)?;
+        // store the config
+        let config = Config {
+            owner,
+            max_fee_bps: percentage_to_bps(max_fee)?,
+            stale_swap_blocks: stale_swap_blocks.unwrap_or(DEFAULT_STALE_SWAP_BLOCKS),
+            reject_self_referral: reject_self_referral.unwrap_or_default(),
+            max_route_length: validate_max_route_length(
+                max_route_length.unwrap_or(DEFAULT_MAX_ROUTE_LENGTH),
+            )?,
+            router_pubkey,
+            fee_collector,
+            branding,
+            crank_tip_bps: 0,
+            rebate_bps: 0,
+            surplus_sharing: None,
+            referral_duration: 0,
+            sender_allowlist: false,
+            balance_checks: false,
+            swap_router: None,
+            fee_override_pubkey: None,
+            settlement_slippage_bps: DEFAULT_SETTLEMENT_SLIPPAGE_BPS,
+        };
+        self.config.save(deps.storage, &config)?;
 
         Ok(Response::new()
+            .add_event(config_event(&config))
             .add_attribute("method", "instantiate")
             .add_attribute("contract_name", CONTRACT_NAME)
             .add_attribute("contract_version", CONTRACT_VERSION))
     }
 
-    /// Executes a swap and charges the affiliate fee.
-    /// The affiliate fee is deducted from the swap amount and sent to the affiliate address.
+    /// Updates the settings provided, leaving the others unchanged. Only callable by the owner.
     #[msg(exec)]
-    pub fn swap(
+    pub fn update_config(
         &self,
         ctx: (DepsMut, Env, MessageInfo),
-        routes: Vec<SwapAmountInRoute>,
-        token_out_min_amount: Coin,
-        fee_percentage: Option<Decimal>,
-        fee_collector: String,
+        stale_swap_blocks: Option<u64>,
+        reject_self_referral: Option<bool>,
+        max_route_length: Option<u32>,
+        crank_tip_percentage: Option<Decimal>,
+        rebate_percentage: Option<Decimal>,
+        referral_duration: Option<u64>,
+        sender_allowlist: Option<bool>,
+        balance_checks: Option<bool>,
+        swap_router: Option<String>,
+        settlement_slippage_tolerance: Option<Decimal>,
     ) -> Result<Response, ContractError> {
-        let (deps, env, info) = ctx;
+        let (deps, _env, info) = ctx;
+        self.ensure_owner(deps.as_ref(), &info.sender)?;
 
-        // Safety check: No active swap
-        if self.active_swap.may_load(deps.storage)?.is_some() {
-            // This should never happen as long as the contract isn't called concurrently
-            return Err(ContractError::ActiveSwapExists {});
+        let mut config = self.config.load(deps.storage)?;
+        if let Some(stale_swap_blocks) = stale_swap_blocks {
+            config.stale_swap_blocks = stale_swap_blocks;
+        }
+        if let Some(reject_self_referral) = reject_self_referral {
+            config.reject_self_referral = reject_self_referral;
+        }
+        if let Some(max_route_length) = max_route_length {
+            config.max_route_length = validate_max_route_length(max_route_length)?;
+        }
+        if let Some(crank_tip_percentage) = crank_tip_percentage {
+            config.crank_tip_bps =
+                validate_crank_tip_bps(percentage_to_bps(crank_tip_percentage)?)?;
+        }
+        if let Some(rebate_percentage) = rebate_percentage {
+            config.rebate_bps = validate_rebate_bps(percentage_to_bps(rebate_percentage)?)?;
         }
+        if let Some(referral_duration) = referral_duration {
+            config.referral_duration = referral_duration;
+        }
+        if let Some(sender_allowlist) = sender_allowlist {
+            config.sender_allowlist = sender_allowlist;
+        }
+        if let Some(balance_checks) = balance_checks {
+            config.balance_checks = balance_checks;
+        }
+        if let Some(swap_router) = swap_router {
+            config.swap_router = Some(deps.api.addr_validate(&swap_router)?);
+        }
+        if let Some(tolerance) = settlement_slippage_tolerance {
+            config.settlement_slippage_bps =
+                validate_settlement_slippage_bps(percentage_to_bps(tolerance)?)?;
+        }
+        self.config.save(deps.storage, &config)?;
 
-        // ensure funds not empty
-        let coin = cw_utils::one_coin(&info)?;
+        Ok(Response::new()
+            .add_event(config_event(&config))
+            .add_attribute("method", "update_config"))
+    }
 
-        // validate fee collector address
-        let fee_collector = deps.api.addr_validate(&fee_collector)?;
+    /// Sets the public key of the router whose signed quotes swaps must carry, or stops
+    /// requiring quotes when `None`. Only callable by the owner.
+    #[msg(exec)]
+    pub fn set_router_pubkey(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        router_pubkey: Option<Binary>,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env, info) = ctx;
+        self.ensure_owner(deps.as_ref(), &info.sender)?;
 
-        let max_fee_percentage = self.max_fee_percentage.load(deps.storage)?;
+        if let Some(router_pubkey) = &router_pubkey {
+            quote::validate_router_pubkey(router_pubkey)?;
+        }
+        let mut config = self.config.load(deps.storage)?;
+        config.router_pubkey = router_pubkey;
+        self.config.save(deps.storage, &config)?;
 
-        // Ensure the provided fee percentage is >=0
-        // If it is None, default to zero
-        let fee_percentage = fee_percentage
-            .unwrap_or(Decimal::zero())
-            .max(Decimal::zero());
+        Ok(Response::new()
+            .add_event(config_event(&config))
+            .add_attribute("method", "set_router_pubkey"))
+    }
 
-        // Ensure the provided fee percentage is <= max_fee_percentage
-        // If it is higher, default to max_fee_percentage
-        let fee_percentage = std::cmp::min(max_fee_percentage, fee_percentage);
+    /// Sets the public key whose signed fee overrides swaps may carry, or disables fee
+    /// overrides when `None`. Only callable by the owner.
+    #[msg(exec)]
+    pub fn set_fee_override_pubkey(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        fee_override_pubkey: Option<Binary>,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env, info) = ctx;
+        self.ensure_owner(deps.as_ref(), &info.sender)?;
 
-        // calculate the fee to deduct
-        let fee = coin.amount * fee_percentage.checked_div(Decimal::from_str("100")?)?;
+        if let Some(pubkey) = &fee_override_pubkey {
+            validate_fee_override_pubkey(pubkey)?;
+        }
+        let mut config = self.config.load(deps.storage)?;
+        config.fee_override_pubkey = fee_override_pubkey;
+        self.config.save(deps.storage, &config)?;
 
-        // Add the messages but skip the fee transfer if it is zero
-        let mut msgs = vec![];
+        Ok(Response::new()
+            .add_event(config_event(&config))
+            .add_attribute("method", "set_fee_override_pubkey"))
+    }
 
-        if !fee.is_zero() {
-            let send_msg: CosmosMsg = BankMsg::Send {
-                to_address: fee_collector.to_string(),
-                amount: vec![Coin {
-                    denom: coin.denom.clone(),
-                    amount: fee,
-                }],
-            }
-            .into();
-            msgs.push(SubMsg::new(send_msg));
-        }
+    /// Shares the output above the `expected_out` of swaps between the fee collector and
+    /// `treasury`, the swapper keeping the rest, or stops sharing it when both shares are
+    /// `None`. Shares are percentages of the surplus. Only callable by the owner.
+    #[msg(exec)]
+    pub fn set_surplus_sharing(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        fee_collector_share: Option<Decimal>,
+        treasury_share: Option<Decimal>,
+        treasury: Option<String>,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env, info) = ctx;
+        self.ensure_owner(deps.as_ref(), &info.sender)?;
 
-        let swap_msg = MsgSwapExactAmountIn {
-            sender: env.contract.address.to_string(),
-            routes,
-            token_in: Some(
-                Coin {
-                    denom: coin.denom.clone(),
-                    amount: coin.amount.checked_sub(fee)?,
-                }
-                .into(),
-            ),
-            token_out_min_amount: token_out_min_amount.amount.to_string(),
+        let fee_collector_bps = percentage_to_bps(fee_collector_share.unwrap_or_default())?;
+        let treasury_bps = percentage_to_bps(treasury_share.unwrap_or_default())?;
+        let treasury = treasury
+            .map(|treasury| deps.api.addr_validate(&treasury))
+            .transpose()?;
+        let surplus_sharing = SurplusSharing {
+            fee_collector_bps,
+            treasury_bps,
+            treasury,
         };
+        surplus_sharing.validate()?;
+
+        let mut config = self.config.load(deps.storage)?;
+        config.surplus_sharing =
+            (fee_collector_bps > 0 || treasury_bps > 0).then_some(surplus_sharing);
+        self.config.save(deps.storage, &config)?;
+
+        Ok(Response::new()
+            .add_event(config_event(&config))
+            .add_attribute("method", "set_surplus_sharing"))
+    }
+
+    /// Seeds a new deployment with entries exported from a previous instance (see
+    /// `export_state`), in as many calls as needed. The escrowed amounts imported must be sent
+    /// along. Only callable by the owner, before the first swap.
+    #[msg(exec)]
+    pub fn import_state(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        entries: Vec<StateEntry>,
+    ) -> Result<Response, ContractError> {
+        let (deps, env, info) = ctx;
+        self.ensure_owner(deps.as_ref(), &info.sender)?;
+        if self.swap_count.may_load(deps.storage)?.is_some() {
+            return Err(ContractError::ImportClosed {});
+        }
+
+        let imported = entries.len();
+        state_export::import_state(self, deps.storage, deps.api, &env, entries, &info.funds)?;
+
+        let config = self.config.load(deps.storage)?;
+        Ok(Response::new()
+            .add_event(config_event(&config))
+            .add_attribute("method", "import_state")
+            .add_attribute("entries", imported.to_string()))
+    }
+
+    /// Deploys a white-label instance of this contract whose fees all go to `fee_collector`.
+    /// The address is derived from the factory address and `label` with instantiate2, so it
+    /// is known before deploying. Only callable by the owner.
+    #[msg(exec)]
+    pub fn create_child(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        label: String,
+        fee_collector: String,
+        branding: Branding,
+        max_fee_percentage: Option<Decimal>,
+        owner: Option<String>,
+    ) -> Result<Response, ContractError> {
+        let (deps, env, info) = ctx;
+        let config = self.config.load(deps.storage)?;
+        self.ensure_owner(deps.as_ref(), &info.sender)?;
 
-        // hardcoding the reply_id here because there is only one submessage in this contract
-        msgs.push(SubMsg::reply_always(swap_msg.clone(), 1));
+        if label.is_empty() || label.len() > MAX_CHILD_LABEL_LENGTH {
+            return Err(ContractError::InvalidChildLabel {});
+        }
+        let fee_collector = deps.api.addr_validate(&fee_collector)?;
+        // the child runs the same code as the factory
+        let code_id = deps
+            .querier
+            .query_wasm_contract_info(&env.contract.address)?
+            .code_id;
 
-        self.active_swap.save(
+        let child_id = self.child_count.may_load(deps.storage)?.unwrap_or_default() + 1;
+        self.child_count.save(deps.storage, &child_id)?;
+        let msg = InstantiateMsg {
+            max_fee_percentage,
+            owner: Some(owner.unwrap_or_else(|| config.owner.to_string())),
+            stale_swap_blocks: None,
+            reject_self_referral: None,
+            max_route_length: None,
+            router_pubkey: None,
+            fee_collector: Some(fee_collector.to_string()),
+            branding: Some(branding.clone()),
+        };
+        let instantiate = WasmMsg::Instantiate2 {
+            // the factory owner can migrate children
+            admin: Some(config.owner.into_string()),
+            code_id,
+            label: label.clone(),
+            msg: to_binary(&msg)?,
+            funds: vec![],
+            salt: Binary::from(label.as_bytes()),
+        };
+        self.pending_children.save(
             deps.storage,
-            &ActiveSwap {
-                original_sender: info.sender,
+            child_id,
+            &ChildInfo {
+                label,
                 fee_collector,
-                fee: Coin {
-                    denom: coin.denom,
-                    amount: fee,
-                },
-                swap_msg,
+                branding,
             },
         )?;
 
         Ok(Response::new()
-            .add_submessages(msgs)
-            .add_attribute("method", "swap"))
+            .add_submessage(SubMsg::reply_on_success(
+                instantiate,
+                ReplyKind::ChildInstantiate.reply_id(child_id),
+            ))
+            .add_attribute("method", "create_child"))
     }
 
-    #[msg(query)]
-    pub fn get_max_fee_percentage(
+    /// Sets the fee override of swaps from `token_in_denom` to `token_out_denom`, or removes it
+    /// when `pair_fee` is `None`. Only callable by the owner.
+    #[msg(exec)]
+    pub fn set_pair_fee(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        token_in_denom: String,
+        token_out_denom: String,
+        pair_fee: Option<PairFee>,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env, info) = ctx;
+        self.ensure_owner(deps.as_ref(), &info.sender)?;
+
+        let key = (token_in_denom.as_str(), token_out_denom.as_str());
+        let response = Response::new()
+            .add_attribute("method", "set_pair_fee")
+            .add_attribute("token_in_denom", &token_in_denom)
+            .add_attribute("token_out_denom", &token_out_denom);
+        let Some(pair_fee) = pair_fee else {
+            self.pair_fees.remove(deps.storage, key);
+            return Ok(config_changed(
+                response.add_attribute("pair_fee", "none"),
+                "pair_fee",
+            ));
+        };
+
+        pair_fee.validate()?;
+        let percentage = pair_fee.percentage();
+        self.pair_fees.save(deps.storage, key, &pair_fee)?;
+
+        let pair_fee = match pair_fee {
+            PairFee::Max { .. } => "max",
+            PairFee::Fixed { .. } => "fixed",
+        };
+        Ok(config_changed(
+            response
+                .add_attribute("pair_fee", pair_fee)
+                .add_attribute("fee_percentage", percentage.to_string()),
+            "pair_fee",
+        ))
+    }
+
+    /// Denies or allows again routing swaps through `pool_id`, e.g. when a pool is exploited
+    /// or deprecated. Only callable by the owner.
+    #[msg(exec)]
+    pub fn set_pool_denied(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        pool_id: u64,
+        denied: bool,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env, info) = ctx;
+        self.ensure_owner(deps.as_ref(), &info.sender)?;
+
+        if denied {
+            self.denied_pools.save(deps.storage, pool_id, &())?;
+        } else {
+            self.denied_pools.remove(deps.storage, pool_id);
+        }
+
+        Ok(config_changed(
+            Response::new()
+                .add_attribute("method", "set_pool_denied")
+                .add_attribute("pool_id", pool_id.to_string())
+                .add_attribute("denied", denied.to_string()),
+            "denied_pool",
+        ))
+    }
+
+    /// Adds `address` to the senders allowed to swap while the sender allowlist is enabled, or
+    /// removes it with `allowed: false`. Only callable by the owner.
+    #[msg(exec)]
+    pub fn set_sender_allowed(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        address: String,
+        allowed: bool,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env, info) = ctx;
+        self.ensure_owner(deps.as_ref(), &info.sender)?;
+
+        let address = deps.api.addr_validate(&address)?;
+        if allowed {
+            self.allowed_senders.save(deps.storage, &address, &())?;
+        } else {
+            self.allowed_senders.remove(deps.storage, &address);
+        }
+
+        Ok(config_changed(
+            Response::new()
+                .add_attribute("method", "set_sender_allowed")
+                .add_attribute("address", address)
+                .add_attribute("allowed", allowed.to_string()),
+            "allowed_sender",
+        ))
+    }
+
+    /// Swaps the fees in `denom` paid to the sender along `routes` before paying them, so they
+    /// are received in a single settlement denom, or stops swapping them when `None`. A fee
+    /// whose swap fails is paid as is.
+    #[msg(exec)]
+    pub fn set_settlement_route(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        denom: String,
+        routes: Option<Vec<SwapAmountInRoute>>,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env, info) = ctx;
+        let response = Response::new()
+            .add_attribute("method", "set_settlement_route")
+            .add_attribute("fee_collector", &info.sender)
+            .add_attribute("denom", &denom);
+        let Some(routes) = routes else {
+            self.settlement_routes
+                .remove(deps.storage, (&info.sender, &denom));
+            return Ok(response.add_attribute("route", ""));
+        };
+
+        self.validate_settlement_route(deps.storage, &denom, &routes)?;
+        self.settlement_routes
+            .save(deps.storage, (&info.sender, &denom), &routes)?;
+
+        Ok(response.add_attribute("route", route_to_string(&routes)))
+    }
+
+    /// Allows swaps to call `address` back under `policy`, or disallows it when `None`. Only
+    /// callable by the owner.
+    #[msg(exec)]
+    pub fn set_callback_target(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        address: String,
+        policy: Option<CallbackPolicy>,
+    ) -> Result<Response, ContractError> {
+        let (deps, env, info) = ctx;
+        self.ensure_owner(deps.as_ref(), &info.sender)?;
+
+        let address = deps.api.addr_validate(&address)?;
+        let response = Response::new()
+            .add_attribute("method", "set_callback_target")
+            .add_attribute("address", &address);
+        let Some(policy) = policy else {
+            self.callback_targets.remove(deps.storage, &address);
+            return Ok(config_changed(
+                response.add_attribute("allowed", "false"),
+                "callback_target",
+            ));
+        };
+
+        validate_callback_target(&env, &address, &policy)?;
+        self.callback_targets
+            .save(deps.storage, &address, &policy)?;
+
+        Ok(config_changed(
+            response
+                .add_attribute("allowed", "true")
+                .add_attribute("gas_limit", policy.gas_limit.to_string())
+                .add_attribute("allow_funds", policy.allow_funds.to_string()),
+            "callback_target",
+        ))
+    }
+
+    /// Sets the display metadata of `denom` reported by swaps, or removes it when `None`. Only
+    /// callable by the owner.
+    #[msg(exec)]
+    pub fn set_denom_metadata(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        denom: String,
+        metadata: Option<DenomMetadata>,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env, info) = ctx;
+        self.ensure_owner(deps.as_ref(), &info.sender)?;
+
+        let response = Response::new()
+            .add_attribute("method", "set_denom_metadata")
+            .add_attribute("denom", &denom);
+        let Some(metadata) = metadata else {
+            self.denom_metadata.remove(deps.storage, &denom);
+            return Ok(config_changed(
+                response
+                    .add_attribute("symbol", "")
+                    .add_attribute("decimals", ""),
+                "denom_metadata",
+            ));
+        };
+
+        metadata.validate()?;
+        self.denom_metadata.save(deps.storage, &denom, &metadata)?;
+
+        Ok(config_changed(
+            response
+                .add_attribute("symbol", metadata.symbol)
+                .add_attribute("decimals", metadata.decimals.to_string()),
+            "denom_metadata",
+        ))
+    }
+
+    /// Schedules a window from `start` to `end` (excluded) during which swaps pay no fee,
+    /// whatever their fee percentage or pair fee. It cannot overlap another fee holiday. Only
+    /// callable by the owner.
+    #[msg(exec)]
+    pub fn schedule_fee_holiday(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<Response, ContractError> {
+        let (deps, env, info) = ctx;
+        self.ensure_owner(deps.as_ref(), &info.sender)?;
+
+        if end <= env.block.time {
+            return Err(ContractError::InvalidFeeHoliday {});
+        }
+        let holiday = FeeHoliday { start, end };
+        self.validate_fee_holiday(deps.storage, &holiday)?;
+        self.fee_holidays
+            .save(deps.storage, start.nanos(), &holiday)?;
+
+        Ok(config_changed(
+            Response::new()
+                .add_attribute("method", "schedule_fee_holiday")
+                .add_attribute("start", start.to_string())
+                .add_attribute("end", end.to_string()),
+            "fee_holiday",
+        ))
+    }
+
+    /// Cancels the fee holiday starting at `start`, or removes it once over. Only callable by
+    /// the owner.
+    #[msg(exec)]
+    pub fn cancel_fee_holiday(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        start: Timestamp,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env, info) = ctx;
+        self.ensure_owner(deps.as_ref(), &info.sender)?;
+
+        self.fee_holidays.remove(deps.storage, start.nanos());
+
+        Ok(config_changed(
+            Response::new()
+                .add_attribute("method", "cancel_fee_holiday")
+                .add_attribute("start", start.to_string()),
+            "fee_holiday",
+        ))
+    }
+
+    /// Sets the min and max input of swaps from `denom`, fee included, and the max input of
+    /// each sender over the last 24 hours. A `None` bound is removed. Only callable by the owner.
+    #[msg(exec)]
+    pub fn set_input_limits(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        denom: String,
+        min_input: Option<Uint128>,
+        max_input: Option<Uint128>,
+        max_daily_volume: Option<Uint128>,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env, info) = ctx;
+        self.ensure_owner(deps.as_ref(), &info.sender)?;
+
+        let limits = InputLimits {
+            min_input,
+            max_input,
+            max_daily_volume,
+        };
+        limits.validate()?;
+        if limits == InputLimits::default() {
+            self.input_limits.remove(deps.storage, &denom);
+        } else {
+            self.input_limits.save(deps.storage, &denom, &limits)?;
+        }
+
+        Ok(config_changed(
+            Response::new()
+                .add_attribute("method", "set_input_limits")
+                .add_attribute("denom", denom)
+                .add_attribute(
+                    "min_input",
+                    limits
+                        .min_input
+                        .map(|min| min.to_string())
+                        .unwrap_or_default(),
+                )
+                .add_attribute(
+                    "max_input",
+                    limits
+                        .max_input
+                        .map(|max| max.to_string())
+                        .unwrap_or_default(),
+                )
+                .add_attribute(
+                    "max_daily_volume",
+                    limits
+                        .max_daily_volume
+                        .map(|max| max.to_string())
+                        .unwrap_or_default(),
+                ),
+            "input_limits",
+        ))
+    }
+
+    /// Sets the fee discounts of senders by lifetime volume in `denom`, replacing the previous
+    /// ones. An empty list removes them. Only callable by the owner.
+    #[msg(exec)]
+    pub fn set_volume_discounts(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        denom: String,
+        discounts: Vec<VolumeDiscount>,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env, info) = ctx;
+        self.ensure_owner(deps.as_ref(), &info.sender)?;
+
+        validate_volume_discounts(&discounts)?;
+        if discounts.is_empty() {
+            self.volume_discounts.remove(deps.storage, &denom);
+        } else {
+            self.volume_discounts
+                .save(deps.storage, &denom, &discounts)?;
+        }
+
+        Ok(config_changed(
+            Response::new()
+                .add_attribute("method", "set_volume_discounts")
+                .add_attribute("denom", denom)
+                .add_attribute("brackets", discounts.len().to_string()),
+            "volume_discounts",
+        ))
+    }
+
+    /// Pauses swaps. Only callable by the owner.
+    #[msg(exec)]
+    pub fn pause(&self, ctx: (DepsMut, Env, MessageInfo)) -> Result<Response, ContractError> {
+        let (deps, env, info) = ctx;
+        self.ensure_owner(deps.as_ref(), &info.sender)?;
+
+        if self.pause_info.may_load(deps.storage)?.is_some() {
+            return Err(ContractError::Paused {});
+        }
+
+        self.pause_info.save(
+            deps.storage,
+            &PauseInfo {
+                paused_by: info.sender,
+                paused_at: env.block.time,
+            },
+        )?;
+
+        Ok(config_changed(
+            Response::new()
+                .add_attribute("method", "pause")
+                .add_attribute("paused", "true"),
+            "paused",
+        ))
+    }
+
+    /// Resumes swaps. Only callable by the owner.
+    #[msg(exec)]
+    pub fn unpause(&self, ctx: (DepsMut, Env, MessageInfo)) -> Result<Response, ContractError> {
+        let (deps, _env, info) = ctx;
+        self.ensure_owner(deps.as_ref(), &info.sender)?;
+
+        if self.pause_info.may_load(deps.storage)?.is_none() {
+            return Err(ContractError::NotPaused {});
+        }
+
+        self.pause_info.remove(deps.storage);
+
+        Ok(config_changed(
+            Response::new()
+                .add_attribute("method", "unpause")
+                .add_attribute("paused", "false"),
+            "paused",
+        ))
+    }
+
+    /// Migrates the contract state to the current version.
+    /// `owner` replaces the current owner. It is required when migrating from versions that
+    /// predate the owner.
+    #[msg(migrate)]
+    pub fn migrate(
+        &self,
+        ctx: (DepsMut, Env),
+        owner: Option<String>,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env) = ctx;
+
+        let owner = owner
+            .map(|owner| deps.api.addr_validate(&owner))
+            .transpose()?;
+        migrations::migrate_config(deps.storage, &self.config, owner)?;
+        migrations::remove_legacy_active_swap(deps.storage);
+
+        cw2::set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+        store_supported_interfaces(deps.storage)?;
+
+        let config = self.config.load(deps.storage)?;
+        Ok(Response::new()
+            .add_event(config_event(&config))
+            .add_attribute("method", "migrate")
+            .add_attribute("contract_name", CONTRACT_NAME)
+            .add_attribute("contract_version", CONTRACT_VERSION))
+    }
+
+    /// White-label instances created with `create_child`, ordered by address
+    #[msg(query)]
+    pub fn list_children(
+        &self,
+        ctx: (Deps, Env),
+        start_after: Option<String>,
+        limit: Option<u32>,
+    ) -> Result<ChildrenResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let start_after = start_after
+            .map(|address| deps.api.addr_validate(&address))
+            .transpose()?;
+        let limit = limit
+            .unwrap_or(DEFAULT_CHILDREN_LIMIT)
+            .min(MAX_CHILDREN_LIMIT) as usize;
+        let children = self
+            .children
+            .range(
+                deps.storage,
+                start_after.as_ref().map(Bound::exclusive),
+                None,
+                Order::Ascending,
+            )
+            .take(limit)
+            .map(|item| {
+                item.map(|(address, child)| ChildResponse {
+                    address,
+                    label: child.label,
+                    fee_collector: child.fee_collector,
+                    branding: child.branding,
+                })
+            })
+            .collect::<StdResult<_>>()?;
+        Ok(ChildrenResponse { children })
+    }
+
+    /// Fee override of swaps from `token_in_denom` to `token_out_denom`, if any
+    #[msg(query)]
+    pub fn get_pair_fee(
+        &self,
+        ctx: (Deps, Env),
+        token_in_denom: String,
+        token_out_denom: String,
+    ) -> Result<PairFeeResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let pair_fee = self
+            .pair_fees
+            .may_load(deps.storage, (&token_in_denom, &token_out_denom))?;
+        Ok(PairFeeResponse { pair_fee })
+    }
+
+    /// Fee overrides, ordered by input then output denom
+    #[msg(query)]
+    pub fn list_pair_fees(
+        &self,
+        ctx: (Deps, Env),
+        start_after: Option<(String, String)>,
+        limit: Option<u32>,
+    ) -> Result<PairFeesResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let limit = limit
+            .unwrap_or(DEFAULT_PAIR_FEES_LIMIT)
+            .min(MAX_PAIR_FEES_LIMIT) as usize;
+        let start_after = start_after
+            .as_ref()
+            .map(|(token_in, token_out)| Bound::exclusive((token_in.as_str(), token_out.as_str())));
+        let pair_fees = self
+            .pair_fees
+            .range(deps.storage, start_after, None, Order::Ascending)
+            .take(limit)
+            .map(|item| {
+                item.map(
+                    |((token_in_denom, token_out_denom), pair_fee)| PairFeeEntry {
+                        token_in_denom,
+                        token_out_denom,
+                        pair_fee,
+                    },
+                )
+            })
+            .collect::<StdResult<_>>()?;
+        Ok(PairFeesResponse { pair_fees })
+    }
+
+    /// Route the fees in `denom` paid to `fee_collector` are swapped along, if any
+    #[msg(query)]
+    pub fn get_settlement_route(
+        &self,
+        ctx: (Deps, Env),
+        fee_collector: String,
+        denom: String,
+    ) -> Result<SettlementRouteResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let fee_collector = deps.api.addr_validate(&fee_collector)?;
+        let routes = self
+            .settlement_routes
+            .may_load(deps.storage, (&fee_collector, &denom))?;
+        Ok(SettlementRouteResponse { routes })
+    }
+
+    /// Policy of the callbacks of swaps to `address`, if allowed
+    #[msg(query)]
+    pub fn get_callback_target(
+        &self,
+        ctx: (Deps, Env),
+        address: String,
+    ) -> Result<CallbackTargetResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let address = deps.api.addr_validate(&address)?;
+        let policy = self.callback_targets.may_load(deps.storage, &address)?;
+        Ok(CallbackTargetResponse { policy })
+    }
+
+    /// Contracts swaps may call back, ordered by address
+    #[msg(query)]
+    pub fn list_callback_targets(
+        &self,
+        ctx: (Deps, Env),
+        start_after: Option<String>,
+        limit: Option<u32>,
+    ) -> Result<CallbackTargetsResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let limit = limit
+            .unwrap_or(DEFAULT_CALLBACK_TARGETS_LIMIT)
+            .min(MAX_CALLBACK_TARGETS_LIMIT) as usize;
+        let start_after = start_after
+            .map(|address| deps.api.addr_validate(&address))
+            .transpose()?;
+        let targets = self
+            .callback_targets
+            .range(
+                deps.storage,
+                start_after.as_ref().map(Bound::exclusive),
+                None,
+                Order::Ascending,
+            )
+            .take(limit)
+            .map(|item| item.map(|(address, policy)| CallbackTargetEntry { address, policy }))
+            .collect::<StdResult<_>>()?;
+        Ok(CallbackTargetsResponse { targets })
+    }
+
+    /// Display metadata of `denom`, if set
+    #[msg(query)]
+    pub fn get_denom_metadata(
+        &self,
+        ctx: (Deps, Env),
+        denom: String,
+    ) -> Result<DenomMetadataResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let metadata = self.denom_metadata.may_load(deps.storage, &denom)?;
+        Ok(DenomMetadataResponse { metadata })
+    }
+
+    /// Pools routes may not go through, by increasing id
+    #[msg(query)]
+    pub fn list_denied_pools(
+        &self,
+        ctx: (Deps, Env),
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    ) -> Result<DeniedPoolsResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let limit = limit
+            .unwrap_or(DEFAULT_DENIED_POOLS_LIMIT)
+            .min(MAX_DENIED_POOLS_LIMIT) as usize;
+        let pool_ids = self
+            .denied_pools
+            .keys(
+                deps.storage,
+                start_after.map(Bound::exclusive),
+                None,
+                Order::Ascending,
+            )
+            .take(limit)
+            .collect::<StdResult<_>>()?;
+        Ok(DeniedPoolsResponse { pool_ids })
+    }
+
+    /// Whether `address` is on the sender allowlist, which only restricts swaps while enabled
+    #[msg(query)]
+    pub fn get_sender_allowed(
+        &self,
+        ctx: (Deps, Env),
+        address: String,
+    ) -> Result<SenderAllowedResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let address = deps.api.addr_validate(&address)?;
+        Ok(SenderAllowedResponse {
+            allowed: self.allowed_senders.has(deps.storage, &address),
+        })
+    }
+
+    /// Senders on the allowlist, ordered by address
+    #[msg(query)]
+    pub fn list_allowed_senders(
+        &self,
+        ctx: (Deps, Env),
+        start_after: Option<String>,
+        limit: Option<u32>,
+    ) -> Result<AllowedSendersResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let limit = limit
+            .unwrap_or(DEFAULT_ALLOWED_SENDERS_LIMIT)
+            .min(MAX_ALLOWED_SENDERS_LIMIT) as usize;
+        let start_after = start_after
+            .map(|address| deps.api.addr_validate(&address))
+            .transpose()?;
+        let addresses = self
+            .allowed_senders
+            .keys(
+                deps.storage,
+                start_after.as_ref().map(Bound::exclusive),
+                None,
+                Order::Ascending,
+            )
+            .take(limit)
+            .collect::<StdResult<_>>()?;
+        Ok(AllowedSendersResponse { addresses })
+    }
+
+    /// Fee holidays, past ones included until cancelled, ordered by start time
+    #[msg(query)]
+    pub fn list_fee_holidays(
+        &self,
+        ctx: (Deps, Env),
+        start_after: Option<Timestamp>,
+        limit: Option<u32>,
+    ) -> Result<FeeHolidaysResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let limit = limit
+            .unwrap_or(DEFAULT_FEE_HOLIDAYS_LIMIT)
+            .min(MAX_FEE_HOLIDAYS_LIMIT) as usize;
+        let fee_holidays = self
+            .fee_holidays
+            .range(
+                deps.storage,
+                start_after.map(|start| Bound::exclusive(start.nanos())),
+                None,
+                Order::Ascending,
+            )
+            .take(limit)
+            .map(|item| item.map(|(_, holiday)| holiday))
+            .collect::<StdResult<_>>()?;
+        Ok(FeeHolidaysResponse { fee_holidays })
+    }
+
+    /// The fee holiday in progress at the block time, if any
+    fn active_fee_holiday(
+        &self,
+        storage: &dyn Storage,
+        env: &Env,
+    ) -> StdResult<Option<FeeHoliday>> {
+        let now = env.block.time;
+        let latest = self
+            .fee_holidays
+            .range(
+                storage,
+                None,
+                Some(Bound::inclusive(now.nanos())),
+                Order::Descending,
+            )
+            .next()
+            .transpose()?;
+        Ok(latest
+            .map(|(_, holiday)| holiday)
+            .filter(|holiday| now < holiday.end))
+    }
+
+    /// Pool manager estimate of swapping `token_in` along `routes`, with the price impact of
+    /// each hop and of the whole route against their spot prices. `token_in` is what reaches
+    /// the pools, after the fee.
+    #[msg(query)]
+    pub fn get_swap_estimate(
+        &self,
+        ctx: (Deps, Env),
+        routes: Vec<SwapAmountInRoute>,
+        token_in: Coin,
+    ) -> Result<SwapEstimateResponse, ContractError> {
+        let (deps, env) = ctx;
+        if routes.is_empty() {
+            return Err(ContractError::EmptyRoutes {});
+        }
+        let backend = Backend::load(deps.storage)?;
+
+        // each hop is estimated on the output of the previous one
+        let mut hops = Vec::with_capacity(routes.len());
+        let mut hop_in = token_in.clone();
+        for route in &routes {
+            let hop = std::slice::from_ref(route);
+            let spot_price = backend.route_spot_price(&deps.querier, hop, &hop_in.denom)?;
+            let amount =
+                backend.estimate_swap(&deps.querier, &env.contract.address, hop, &hop_in)?;
+            let hop_out = Coin::new(amount.u128(), &route.token_out_denom);
+            hops.push(HopEstimate {
+                pool_id: route.pool_id,
+                price_impact: price_impact(hop_in.amount, hop_out.amount, spot_price)?,
+                token_out: hop_out.clone(),
+                spot_price,
+            });
+            hop_in = hop_out;
+        }
+
+        let spot_price = backend.route_spot_price(&deps.querier, &routes, &token_in.denom)?;
+        Ok(SwapEstimateResponse {
+            price_impact: price_impact(token_in.amount, hop_in.amount, spot_price)?,
+            token_out: hop_in,
+            spot_price,
+            hops,
+        })
+    }
+
+    /// Fee and rebate of a swap of `amount` `denom` by `sender`, as `swap` would charge them
+    /// at the block time with the same `fee_percentage`, `fee_collector` and output denom. The
+    /// fee collector is `None` when the swap would require one.
+    #[msg(query)]
+    pub fn get_effective_fee(
+        &self,
+        ctx: (Deps, Env),
+        sender: String,
+        amount: Uint128,
+        denom: String,
+        token_out_denom: Option<String>,
+        fee_percentage: Option<Decimal>,
+        fee_collector: Option<String>,
+    ) -> Result<EffectiveFeeResponse, ContractError> {
+        let (deps, env) = ctx;
+        let sender = deps.api.addr_validate(&sender)?;
+        let config = self.config.load(deps.storage)?;
+        let fee_percentage = self.effective_fee_percentage(
+            deps.storage,
+            &env,
+            &config,
+            &sender,
+            &denom,
+            token_out_denom.as_deref(),
+            fee_percentage,
+            None,
+        )?;
+        let fee = fee_amount(amount, fee_percentage)?;
+        let rebate = fee_amount(fee, config.rebate_percentage())?;
+        let fee_collector =
+            self.resolve_fee_collector(deps, &env, &config, &sender, fee_collector)?;
+        Ok(EffectiveFeeResponse {
+            fee_percentage,
+            fee: Coin::new(fee.u128(), &denom),
+            rebate: Coin::new(rebate.u128(), denom),
+            fee_collector,
+        })
+    }
+
+    /// Preview of `msg`, a `swap` message as is, sent by `sender` with `funds`: the fee, the
+    /// input reaching the pools, the estimated output and price impact, and whether the min
+    /// output would be met at the block time. Fails where the swap would fail before reaching
+    /// the pools.
+    #[msg(query)]
+    pub fn get_swap_simulation(
+        &self,
+        ctx: (Deps, Env),
+        sender: String,
+        funds: Vec<Coin>,
+        msg: affiliate_swap_interface::ExecMsg,
+    ) -> Result<SwapSimulationResponse, ContractError> {
+        let (deps, env) = ctx;
+        let affiliate_swap_interface::ExecMsg::Swap {
+            routes,
+            token_out_min_amount,
+            fee_percentage,
+            fee_collector,
+            token_in_denom,
+            allow_cyclic,
+            quote,
+            slippage_tolerance,
+            max_price_impact,
+            after,
+            client_id,
+            channel,
+            fee_override,
+            ..
+        } = msg
+        else {
+            return Err(ContractError::NotASwap {});
+        };
+        let info = MessageInfo {
+            sender: deps.api.addr_validate(&sender)?,
+            funds,
+        };
+        let config = self.config.load(deps.storage)?;
+        let ValidatedSwap {
+            coin,
+            token_out_denom,
+            fee_collector,
+            fee_override,
+            ..
+        } = self.validate_swap(
+            deps,
+            &env,
+            &config,
+            &info,
+            &routes,
+            token_out_min_amount.as_ref(),
+            fee_collector,
+            token_in_denom,
+            allow_cyclic,
+            quote.as_ref(),
+            slippage_tolerance,
+            after,
+            client_id.as_deref(),
+            channel.as_deref(),
+            fee_override.as_ref(),
+        )?;
+
+        let fee_percentage = self.effective_fee_percentage(
+            deps.storage,
+            &env,
+            &config,
+            &info.sender,
+            &coin.denom,
+            Some(&token_out_denom),
+            fee_percentage,
+            fee_override,
+        )?;
+        let fee = fee_amount(coin.amount, fee_percentage)?;
+        let rebate = fee_amount(fee, config.rebate_percentage())?;
+        let token_in = Coin::new(coin.amount.checked_sub(fee)?.u128(), &coin.denom);
+
+        let backend = Backend::load(deps.storage)?;
+        let estimate =
+            backend.estimate_swap(&deps.querier, &env.contract.address, &routes, &token_in)?;
+        let spot_price = backend.route_spot_price(&deps.querier, &routes, &token_in.denom)?;
+        let price_impact = price_impact(token_in.amount, estimate, spot_price)?;
+        if let Some(max) = max_price_impact {
+            check_price_impact(price_impact, max)?;
+        }
+        let token_out_min_amount = min_output(
+            token_out_min_amount.as_ref(),
+            slippage_tolerance,
+            Some(estimate),
+        )?;
+
+        Ok(SwapSimulationResponse {
+            fee_percentage,
+            fee: Coin::new(fee.u128(), &coin.denom),
+            rebate: Coin::new(rebate.u128(), &coin.denom),
+            fee_collector,
+            token_in,
+            token_out: Coin::new(estimate.u128(), &token_out_denom),
+            price_impact,
+            token_out_min: Coin::new(token_out_min_amount.u128(), token_out_denom),
+            min_output_met: estimate >= token_out_min_amount,
+        })
+    }
+
+    /// Bounds of the input of swaps from `denom`
+    #[msg(query)]
+    pub fn get_input_limits(
+        &self,
+        ctx: (Deps, Env),
+        denom: String,
+    ) -> Result<InputLimitsResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let limits = self
+            .input_limits
+            .may_load(deps.storage, &denom)?
+            .unwrap_or_default();
+        Ok(InputLimitsResponse {
+            min_input: limits.min_input,
+            max_input: limits.max_input,
+            max_daily_volume: limits.max_daily_volume,
+        })
+    }
+
+    /// Input of `address` in `denom` over the last 24 hours, as counted against the max
+    /// daily volume. Only tracked while `denom` has one.
+    #[msg(query)]
+    pub fn get_rolling_volume(
+        &self,
+        ctx: (Deps, Env),
+        address: String,
+        denom: String,
+    ) -> Result<RollingVolumeResponse, ContractError> {
+        let (deps, env) = ctx;
+        let address = deps.api.addr_validate(&address)?;
+        let buckets = self.rolling_buckets(deps.storage, &env, &address, &denom)?;
+        let volume = buckets
+            .iter()
+            .try_fold(Uint128::zero(), |volume, (_, amount)| {
+                volume.checked_add(*amount)
+            })?;
+        let max_daily_volume = self
+            .input_limits
+            .may_load(deps.storage, &denom)?
+            .and_then(|limits| limits.max_daily_volume);
+        Ok(RollingVolumeResponse {
+            volume,
+            max_daily_volume,
+        })
+    }
+
+    /// Checks everything `swap` checks before reaching the pools, without saving anything.
+    /// Returns the input, the funds to refund, the output denom and the fee collector.
+    fn validate_swap(
+        &self,
+        deps: Deps,
+        env: &Env,
+        config: &Config,
+        info: &MessageInfo,
+        routes: &[SwapAmountInRoute],
+        token_out_min_amount: Option<&Coin>,
+        fee_collector: Option<String>,
+        token_in_denom: Option<String>,
+        allow_cyclic: Option<bool>,
+        quote: Option<&RouteQuote>,
+        slippage_tolerance: Option<Decimal>,
+        after: Option<AfterSwap>,
+        client_id: Option<&str>,
+        channel: Option<&str>,
+        fee_override: Option<&FeeOverride>,
+    ) -> Result<ValidatedSwap, ContractError> {
+        if self.pause_info.may_load(deps.storage)?.is_some() {
+            return Err(ContractError::Paused {});
+        }
+        if config.sender_allowlist && !self.allowed_senders.has(deps.storage, &info.sender) {
+            return Err(ContractError::SenderNotAllowed {});
+        }
+
+        // With an explicit input denom, any other attached coin is refunded to the sender.
+        // Otherwise exactly one coin must be sent
+        let (coin, extra_funds) = match token_in_denom {
+            Some(denom) => {
+                let (matching, extra_funds): (Vec<_>, Vec<_>) =
+                    info.funds.iter().cloned().partition(|c| c.denom == denom);
+                let coin = matching
+                    .into_iter()
+                    .next()
+                    .ok_or(PaymentError::MissingDenom(denom))?;
+                if coin.amount.is_zero() {
+                    return Err(PaymentError::NoFunds {}.into());
+                }
+                (coin, extra_funds)
+            }
+            None => (cw_utils::one_coin(info)?, vec![]),
+        };
+
+        // the output denom is the one produced by the last hop
+        let token_out_denom = routes
+            .last()
+            .ok_or(ContractError::EmptyRoutes {})?
+            .token_out_denom
+            .clone();
+        if routes.len() > config.max_route_length as usize {
+            return Err(ContractError::RouteTooLong {
+                max: config.max_route_length,
+            });
+        }
+        // dust swaps pay no fee, and bounding the input bounds the worst case loss of a swap
+        let limits = self
+            .input_limits
+            .may_load(deps.storage, &coin.denom)?
+            .unwrap_or_default();
+        if let Some(min_input) = limits.min_input {
+            if coin.amount < min_input {
+                return Err(ContractError::InputTooSmall {
+                    min: format!("{min_input}{}", coin.denom),
+                });
+            }
+        }
+        if let Some(max_input) = limits.max_input {
+            if coin.amount > max_input {
+                return Err(ContractError::InputTooLarge {
+                    max: format!("{max_input}{}", coin.denom),
+                });
+            }
+        }
+        if let Some(max_daily_volume) = limits.max_daily_volume {
+            self.check_rolling_volume(deps.storage, env, &info.sender, &coin, max_daily_volume)?;
+        }
+        for route in routes {
+            if self.denied_pools.has(deps.storage, route.pool_id) {
+                return Err(ContractError::PoolDenied {
+                    pool_id: route.pool_id,
+                });
+            }
+        }
+        // a route ending in the input denom is usually a mistake, unless done on purpose (arbitrage)
+        if token_out_denom == coin.denom && !allow_cyclic.unwrap_or_default() {
+            return Err(ContractError::CyclicRoute {
+                denom: token_out_denom,
+            });
+        }
+        if let Some(client_id) = client_id {
+            self.check_client_id(deps.storage, env, &info.sender, client_id)?;
+        }
+        if let Some(channel) = channel {
+            if channel.is_empty() || channel.len() > MAX_CHANNEL_LENGTH {
+                return Err(ContractError::InvalidChannel {
+                    max: MAX_CHANNEL_LENGTH,
+                });
+            }
+        }
+        if let Some(tolerance) = slippage_tolerance {
+            if tolerance >= Decimal::from_ratio(100u64, 1u64) {
+                return Err(ContractError::InvalidSlippageTolerance {});
+            }
+        }
+        // only routes quoted by the operator's router are executed, when one is configured
+        if let Some(router_pubkey) = &config.router_pubkey {
+            let quote = quote.ok_or(ContractError::QuoteRequired {})?;
+            // the signed quote covers the min output, so it cannot be derived here
+            let token_out_min_amount =
+                token_out_min_amount.ok_or(ContractError::MinOutputRequired {})?;
+            quote::verify_quote(
+                deps.api,
+                env,
+                router_pubkey,
+                quote,
+                routes,
+                &coin,
+                token_out_min_amount,
+            )?;
+        }
+
+        let fee_collector = self
+            .resolve_fee_collector(deps, env, config, &info.sender, fee_collector)?
+            .ok_or(ContractError::FeeCollectorRequired {})?;
+        // fees sent to the contract itself would be stranded forever
+        if fee_collector == env.contract.address {
+            return Err(ContractError::FeeCollectorIsContract {});
+        }
+        if config.reject_self_referral && fee_collector == info.sender {
+            return Err(ContractError::SelfReferral {});
+        }
+        if let Some(expected) = &config.fee_collector {
+            if fee_collector != expected {
+                return Err(ContractError::FeeCollectorMismatch {
+                    expected: expected.to_string(),
+                });
+            }
+        }
+
+        // negotiated fees above the max fee are signed for their fee collector
+        let fee_override = match fee_override {
+            Some(fee_override) => {
+                let pubkey = config
+                    .fee_override_pubkey
+                    .as_ref()
+                    .ok_or(ContractError::FeeOverrideNotEnabled {})?;
+                Some(fee_override::verify_fee_override(
+                    deps.api,
+                    env,
+                    pubkey,
+                    fee_override,
+                    &fee_collector,
+                )?)
+            }
+            None => None,
+        };
+
+        let after = match after {
+            Some(AfterSwap::Execute { contract, msg }) => {
+                let contract = deps.api.addr_validate(&contract)?;
+                self.funded_callback_target(deps.storage, &contract)?;
+                Some(AfterSwap::Execute {
+                    contract: contract.into_string(),
+                    msg,
+                })
+            }
+            Some(AfterSwap::VaultDeposit { vault }) => {
+                let vault = deps.api.addr_validate(&vault)?;
+                self.funded_callback_target(deps.storage, &vault)?;
+                let info: VaultInfoResponse = deps
+                    .querier
+                    .query_wasm_smart(&vault, &VaultQueryMsg::Info {})?;
+                if info.base_token != token_out_denom {
+                    return Err(ContractError::VaultDenomMismatch {
+                        base_token: info.base_token,
+                    });
+                }
+                Some(AfterSwap::VaultDeposit {
+                    vault: vault.into_string(),
+                })
+            }
+            None => None,
+        };
+
+        Ok(ValidatedSwap {
+            coin,
+            extra_funds,
+            token_out_denom,
+            fee_collector,
+            after,
+            fee_override,
+        })
+    }
+
+    /// Rejects a client id that is malformed or was used by `sender` within the retention window
+    fn check_client_id(
+        &self,
+        storage: &dyn Storage,
+        env: &Env,
+        sender: &Addr,
+        client_id: &str,
+    ) -> Result<(), ContractError> {
+        if client_id.is_empty() || client_id.len() > MAX_CLIENT_ID_LENGTH {
+            return Err(ContractError::InvalidClientId {
+                max: MAX_CLIENT_ID_LENGTH,
+            });
+        }
+        if let Some(used_at) = self.client_ids.may_load(storage, (sender, client_id))? {
+            if used_at.plus_seconds(CLIENT_ID_RETENTION_SECONDS) > env.block.time {
+                return Err(ContractError::DuplicateClientId {
+                    client_id: client_id.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Policy of the callback target `address`, which must allow funds
+    fn funded_callback_target(
+        &self,
+        storage: &dyn Storage,
+        address: &Addr,
+    ) -> Result<CallbackPolicy, ContractError> {
+        self.callback_targets
+            .may_load(storage, address)?
+            .filter(|policy| policy.allow_funds)
+            .ok_or_else(|| ContractError::CallbackNotAllowed {
+                address: address.to_string(),
+            })
+    }
+
+    /// Rolling volume buckets of `sender` in `denom`, without those older than 24 hours
+    fn rolling_buckets(
+        &self,
+        storage: &dyn Storage,
+        env: &Env,
+        sender: &Addr,
+        denom: &str,
+    ) -> StdResult<Vec<(u64, Uint128)>> {
+        let hour = env.block.time.seconds() / SECONDS_PER_HOUR;
+        let mut buckets = self
+            .rolling_volumes
+            .may_load(storage, (sender, denom))?
+            .unwrap_or_default();
+        buckets.retain(|(bucket, _)| bucket + 24 > hour);
+        Ok(buckets)
+    }
+
+    /// Rejects `input` when it takes the rolling volume of `sender` above `max_daily_volume`
+    fn check_rolling_volume(
+        &self,
+        storage: &dyn Storage,
+        env: &Env,
+        sender: &Addr,
+        input: &Coin,
+        max_daily_volume: Uint128,
+    ) -> Result<(), ContractError> {
+        let volume = self
+            .rolling_buckets(storage, env, sender, &input.denom)?
+            .iter()
+            .try_fold(Uint128::zero(), |volume, (_, amount)| {
+                volume.checked_add(*amount)
+            })?;
+        if volume.checked_add(input.amount)? > max_daily_volume {
+            return Err(ContractError::DailyVolumeExceeded {
+                remaining: format!("{}{}", max_daily_volume.saturating_sub(volume), input.denom),
+            });
+        }
+        Ok(())
+    }
+
+    /// Adds `input` to the rolling volume of `sender`
+    fn add_rolling_volume(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        sender: &Addr,
+        input: &Coin,
+    ) -> Result<(), ContractError> {
+        let mut buckets = self.rolling_buckets(storage, env, sender, &input.denom)?;
+        let hour = env.block.time.seconds() / SECONDS_PER_HOUR;
+        match buckets.last_mut() {
+            Some((bucket, amount)) if *bucket == hour => {
+                *amount = amount.checked_add(input.amount)?
+            }
+            _ => buckets.push((hour, input.amount)),
+        }
+        self.rolling_volumes
+            .save(storage, (sender, &input.denom), &buckets)?;
+        Ok(())
+    }
+
+    /// Lifetime volume of the successful swaps of `address`, per input denom
+    #[msg(query)]
+    pub fn get_volume(
+        &self,
+        ctx: (Deps, Env),
+        address: String,
+    ) -> Result<VolumeResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let address = deps.api.addr_validate(&address)?;
+        let volume = self
+            .volumes
+            .prefix(&address)
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| item.map(|(denom, amount)| Coin { denom, amount }))
+            .collect::<StdResult<_>>()?;
+        Ok(VolumeResponse { volume })
+    }
+
+    /// Volume discounts of swaps from `denom`
+    #[msg(query)]
+    pub fn get_volume_discounts(
+        &self,
+        ctx: (Deps, Env),
+        denom: String,
+    ) -> Result<VolumeDiscountsResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let discounts = self
+            .volume_discounts
+            .may_load(deps.storage, &denom)?
+            .unwrap_or_default();
+        Ok(VolumeDiscountsResponse { discounts })
+    }
+
+    /// Swap totals per day (since the unix epoch, in UTC) and input denom, ordered by day then
+    /// denom, from `from_day` to `to_day` included, only from `denom` if set. Only recorded with
+    /// the `stats` feature
+    #[msg(query)]
+    pub fn list_daily_stats(
+        &self,
+        ctx: (Deps, Env),
+        from_day: Option<u64>,
+        to_day: Option<u64>,
+        denom: Option<String>,
+        start_after: Option<(u64, String)>,
+        limit: Option<u32>,
+    ) -> Result<DailyStatsResponse, ContractError> {
+        let (deps, _env) = ctx;
+        if !cfg!(feature = "stats") {
+            return Err(ContractError::FeatureDisabled {
+                feature: "stats".to_string(),
+            });
+        }
+        let limit = limit.unwrap_or(DEFAULT_STATS_LIMIT).min(MAX_STATS_LIMIT) as usize;
+        let (min, max) = stats_bounds(from_day, to_day, &start_after);
+        let range = match denom {
+            Some(denom) => self.daily_stats.idx.denom.prefix(denom).range(
+                deps.storage,
+                min,
+                max,
+                Order::Ascending,
+            ),
+            None => self
+                .daily_stats
+                .range(deps.storage, min, max, Order::Ascending),
+        };
+        let stats = range
+            .take(limit)
+            .map(|item| item.map(|((day, denom), stats)| DailyStats::new(day, denom, stats)))
+            .collect::<StdResult<_>>()?;
+        Ok(DailyStatsResponse { stats })
+    }
+
+    /// Daily stats of the swaps tagged with `channel`, like `list_daily_stats`. Only recorded
+    /// with the `stats` feature
+    #[msg(query)]
+    pub fn list_channel_stats(
+        &self,
+        ctx: (Deps, Env),
+        channel: String,
+        from_day: Option<u64>,
+        to_day: Option<u64>,
+        start_after: Option<(u64, String)>,
+        limit: Option<u32>,
+    ) -> Result<DailyStatsResponse, ContractError> {
+        let (deps, _env) = ctx;
+        if !cfg!(feature = "stats") {
+            return Err(ContractError::FeatureDisabled {
+                feature: "stats".to_string(),
+            });
+        }
+        let limit = limit.unwrap_or(DEFAULT_STATS_LIMIT).min(MAX_STATS_LIMIT) as usize;
+        let (min, max) = stats_bounds(from_day, to_day, &start_after);
+        let stats = self
+            .channel_stats
+            .sub_prefix(&channel)
+            .range(deps.storage, min, max, Order::Ascending)
+            .take(limit)
+            .map(|item| item.map(|((day, denom), stats)| DailyStats::new(day, denom, stats)))
+            .collect::<StdResult<_>>()?;
+        Ok(DailyStatsResponse { stats })
+    }
+
+    /// Daily stats per fee collector, ordered by day then storage key, with the day filters of
+    /// `list_daily_stats`, only of `fee_collector` if set. Only recorded with the `stats`
+    /// feature
+    #[msg(query)]
+    pub fn list_collector_stats(
+        &self,
+        ctx: (Deps, Env),
+        from_day: Option<u64>,
+        to_day: Option<u64>,
+        fee_collector: Option<String>,
+        start_after: Option<(u64, String, String)>,
+        limit: Option<u32>,
+    ) -> Result<CollectorStatsResponse, ContractError> {
+        let (deps, _env) = ctx;
+        if !cfg!(feature = "stats") {
+            return Err(ContractError::FeatureDisabled {
+                feature: "stats".to_string(),
+            });
+        }
+        let limit = limit.unwrap_or(DEFAULT_STATS_LIMIT).min(MAX_STATS_LIMIT) as usize;
+        let start_after = start_after
+            .map(|(day, fee_collector, denom)| (day, Addr::unchecked(fee_collector), denom));
+        let first = Addr::unchecked("");
+        let min = match &start_after {
+            Some((day, fee_collector, denom)) => {
+                Some(Bound::exclusive((*day, fee_collector, denom.as_str())))
+            }
+            None => from_day.map(|day| Bound::inclusive((day, &first, ""))),
+        };
+        let max = to_day
+            .and_then(|day| day.checked_add(1))
+            .map(|day| Bound::exclusive((day, &first, "")));
+        let range = match fee_collector {
+            Some(fee_collector) => self
+                .collector_stats
+                .idx
+                .fee_collector
+                .prefix(deps.api.addr_validate(&fee_collector)?)
+                .range(deps.storage, min, max, Order::Ascending),
+            None => self
+                .collector_stats
+                .range(deps.storage, min, max, Order::Ascending),
+        };
+        let stats = range
+            .take(limit)
+            .map(|item| {
+                item.map(|((day, fee_collector, denom), stats)| CollectorStats {
+                    day,
+                    fee_collector,
+                    denom,
+                    volume: stats.volume,
+                    fees: stats.fees,
+                    swap_count: stats.swap_count,
+                })
+            })
+            .collect::<StdResult<_>>()?;
+        Ok(CollectorStatsResponse { stats })
+    }
+
+    /// Dumps one section of the state, in pages ordered by storage key. `start_after` is the
+    /// `next_start_after` of the previous page.
+    #[msg(query)]
+    pub fn export_state(
+        &self,
+        ctx: (Deps, Env),
+        section: StateSection,
+        start_after: Option<Binary>,
+        limit: Option<u32>,
+    ) -> Result<ExportStateResponse, ContractError> {
+        let (deps, _env) = ctx;
+        Ok(state_export::export_state(
+            self,
+            deps.storage,
+            section,
+            start_after,
+            limit,
+        )?)
+    }
+
+    /// Compares the balances of the contract with what it owes, per denom: the full input of
+    /// active swaps and the escrowed outputs and fees claimable by their recipients. Anything
+    /// else held is reported as surplus. Iterates over all active swaps and escrows, so it is meant for
+    /// monitoring, not for other contracts.
+    #[msg(query)]
+    pub fn get_solvency(&self, ctx: (Deps, Env)) -> Result<SolvencyResponse, ContractError> {
+        let (deps, env) = ctx;
+        let mut liabilities = BTreeMap::<String, Uint128>::new();
+        for item in self
+            .active_swaps
+            .range(deps.storage, None, None, Order::Ascending)
+        {
+            let (_, swap) = item?;
+            for owed in [swap.token_in, swap.fee] {
+                let total = liabilities.entry(owed.denom).or_default();
+                *total = total.checked_add(owed.amount)?;
+            }
+        }
+        for item in self
+            .claimable
+            .range(deps.storage, None, None, Order::Ascending)
+        {
+            let ((_, denom), amount) = item?;
+            let total = liabilities.entry(denom).or_default();
+            *total = total.checked_add(amount)?;
+        }
+
+        let mut balances: BTreeMap<_, _> = deps
+            .querier
+            .query_all_balances(&env.contract.address)?
+            .into_iter()
+            .map(|c| (c.denom, c.amount))
+            .collect();
+        for denom in liabilities.keys() {
+            balances.entry(denom.clone()).or_default();
+        }
+        let denoms = balances
+            .into_iter()
+            .map(|(denom, balance)| {
+                let liabilities = liabilities.get(&denom).copied().unwrap_or_default();
+                DenomSolvency {
+                    surplus: balance.saturating_sub(liabilities),
+                    deficit: liabilities.saturating_sub(balance),
+                    denom,
+                    balance,
+                    liabilities,
+                }
+            })
+            .collect::<Vec<_>>();
+        Ok(SolvencyResponse {
+            solvent: denoms.iter().all(|d| d.deficit.is_zero()),
+            denoms,
+        })
+    }
+
+    /// Fee collector attributed the swaps of `address` without one, and until when
+    #[msg(query)]
+    pub fn get_referral(
+        &self,
+        ctx: (Deps, Env),
+        address: String,
+    ) -> Result<ReferralResponse, ContractError> {
+        let (deps, env) = ctx;
+        let address = deps.api.addr_validate(&address)?;
+        let config = self.config.load(deps.storage)?;
+        let referral = self.referral(deps.storage, &env, &config, &address)?;
+        Ok(ReferralResponse {
+            fee_collector: referral.as_ref().map(|r| r.fee_collector.clone()),
+            expires_at: referral.map(|r| r.expires_at),
+        })
+    }
+
+    /// Referral of `sender`, if sticky referrals are enabled and it has not expired. Expired
+    /// referrals are ignored until replaced or cleared.
+    fn referral(
+        &self,
+        storage: &dyn Storage,
+        env: &Env,
+        config: &Config,
+        sender: &Addr,
+    ) -> Result<Option<Referral>, ContractError> {
+        if config.referral_duration == 0 {
+            return Ok(None);
+        }
+        Ok(self
+            .referrals
+            .may_load(storage, sender)?
+            .filter(|referral| env.block.time < referral.expires_at))
+    }
+
+    /// Saves the referral of `sender`, replacing any expired one
+    pub(crate) fn save_referral(
+        &self,
+        storage: &mut dyn Storage,
+        sender: &Addr,
+        referral: &Referral,
+    ) -> StdResult<()> {
+        self.remove_referral(storage, sender)?;
+        self.referrals.save(storage, sender, referral)?;
+        self.referral_expiries
+            .save(storage, (referral.expires_at.seconds(), sender), &())
+    }
+
+    fn remove_referral(&self, storage: &mut dyn Storage, sender: &Addr) -> StdResult<()> {
+        if let Some(old) = self.referrals.may_load(storage, sender)? {
+            self.referrals.remove(storage, sender);
+            self.referral_expiries
+                .remove(storage, (old.expires_at.seconds(), sender));
+        }
+        Ok(())
+    }
+
+    /// Removes up to `limit` expired referrals, oldest first. Expired referrals are already
+    /// ignored, this only frees their storage. Anyone can call it.
+    #[msg(exec)]
+    pub fn clear_expired_referrals(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        limit: Option<u32>,
+    ) -> Result<Response, ContractError> {
+        let (deps, env, _info) = ctx;
+        let limit = limit
+            .unwrap_or(DEFAULT_CLEAR_REFERRALS_LIMIT)
+            .min(MAX_CLEAR_REFERRALS_LIMIT) as usize;
+        // expired before the current block time. Expiries are indexed in whole seconds, so
+        // referrals expiring within the current second wait for the next one
+        let no_sender = Addr::unchecked("");
+        let max = Bound::exclusive((env.block.time.seconds(), &no_sender));
+        let expired = self
+            .referral_expiries
+            .keys(deps.storage, None, Some(max), Order::Ascending)
+            .take(limit)
+            .map(|item| item.map(|(_, sender)| sender))
+            .collect::<StdResult<Vec<_>>>()?;
+        for sender in &expired {
+            self.remove_referral(deps.storage, sender)?;
+        }
+
+        Ok(Response::new()
+            .add_attribute("method", "clear_expired_referrals")
+            .add_attribute("cleared", expired.len().to_string()))
+    }
+
+    /// Discount of the highest bracket of `denom` reached by `sender`, zero if none
+    fn volume_discount(
+        &self,
+        storage: &dyn Storage,
+        sender: &Addr,
+        denom: &str,
+    ) -> Result<Decimal, ContractError> {
+        let Some(discounts) = self.volume_discounts.may_load(storage, denom)? else {
+            return Ok(Decimal::zero());
+        };
+        let volume = self
+            .volumes
+            .may_load(storage, (sender, denom))?
+            .unwrap_or_default();
+        Ok(discounts
+            .iter()
+            .rev()
+            .find(|bracket| volume >= bracket.min_volume)
+            .map(|bracket| bracket.discount)
+            .unwrap_or_default())
+    }
+
+    /// Fee percentage of a swap from `denom` by `sender` asking for `fee_percentage`, as
+    /// charged by `swap`. Pair fees only apply with a `token_out_denom`.
+    fn effective_fee_percentage(
+        &self,
+        storage: &dyn Storage,
+        env: &Env,
+        config: &Config,
+        sender: &Addr,
+        denom: &str,
+        token_out_denom: Option<&str>,
+        fee_percentage: Option<Decimal>,
+        fee_override: Option<Decimal>,
+    ) -> Result<Decimal, ContractError> {
+        // Ensure the provided fee percentage is >=0
+        // If it is None, default to zero
+        let fee_percentage = fee_percentage
+            .unwrap_or(Decimal::zero())
+            .max(Decimal::zero());
+
+        // Ensure the provided fee percentage is <= the max fee of the pair, or of the config.
+        // If it is higher, default to that max. A fixed pair fee replaces the provided one
+        let pair_fee = match token_out_denom {
+            Some(token_out_denom) => self.pair_fees.may_load(storage, (denom, token_out_denom))?,
+            None => None,
+        };
+        // A signed fee override replaces both, it is only bound by the hard cap of the max fee
+        let fee_percentage = match (fee_override, pair_fee) {
+            (Some(fee_override), _) => fee_override,
+            (None, Some(PairFee::Max { max_fee_percentage })) => {
+                std::cmp::min(max_fee_percentage, fee_percentage)
+            }
+            (None, Some(PairFee::Fixed { fee_percentage })) => fee_percentage,
+            (None, None) => std::cmp::min(config.max_fee_percentage(), fee_percentage),
+        };
+        if self.active_fee_holiday(storage, env)?.is_some() {
+            return Ok(Decimal::zero());
+        }
+        // loyalty discount from the volume of the sender before this swap
+        let discount = self.volume_discount(storage, sender, denom)?;
+        Ok(fee_percentage * (Decimal::one() - discount))
+    }
+
+    /// Settlement route of the fees in `denom` paid to `fee_collector`, unless it is no longer
+    /// valid for swaps: too long, or through a denied pool
+    fn settlement_route(
+        &self,
+        storage: &dyn Storage,
+        config: &Config,
+        fee_collector: &Addr,
+        denom: &str,
+    ) -> StdResult<Option<Vec<SwapAmountInRoute>>> {
+        let Some(routes) = self
+            .settlement_routes
+            .may_load(storage, (fee_collector, denom))?
+        else {
+            return Ok(None);
+        };
+        let denied = routes
+            .iter()
+            .any(|route| self.denied_pools.has(storage, route.pool_id));
+        if denied || routes.len() > config.max_route_length as usize {
+            return Ok(None);
+        }
+        Ok(Some(routes))
+    }
+
+    /// Validates the fee collector of a swap. Without one, the swap is attributed to the
+    /// referrer of the sender, or to the fee collector of the instance, if any
+    fn resolve_fee_collector(
+        &self,
+        deps: Deps,
+        env: &Env,
+        config: &Config,
+        sender: &Addr,
+        fee_collector: Option<String>,
+    ) -> Result<Option<Addr>, ContractError> {
+        Ok(match fee_collector {
+            Some(fee_collector) => Some(deps.api.addr_validate(&fee_collector)?),
+            None => self
+                .referral(deps.storage, env, config, sender)?
+                .map(|referral| referral.fee_collector)
+                .or_else(|| config.fee_collector.clone()),
+        })
+    }
+
+    fn ensure_owner(&self, deps: Deps, sender: &Addr) -> Result<(), ContractError> {
+        if self.config.load(deps.storage)?.owner != sender {
+            return Err(ContractError::Unauthorized {});
+        }
+        Ok(())
+    }
+
+    /// Rejects an empty fee holiday or one overlapping a scheduled fee holiday
+    pub(crate) fn validate_fee_holiday(
+        &self,
+        storage: &dyn Storage,
+        holiday: &FeeHoliday,
+    ) -> Result<(), ContractError> {
+        let FeeHoliday { start, end } = holiday.clone();
+        if end <= start {
+            return Err(ContractError::InvalidFeeHoliday {});
+        }
+        // the holidays do not overlap, so only the closest ones can overlap the new one
+        let previous = self
+            .fee_holidays
+            .range(
+                storage,
+                None,
+                Some(Bound::inclusive(start.nanos())),
+                Order::Descending,
+            )
+            .next()
+            .transpose()?;
+        let next = self
+            .fee_holidays
+            .range(
+                storage,
+                Some(Bound::exclusive(start.nanos())),
+                None,
+                Order::Ascending,
+            )
+            .next()
+            .transpose()?;
+        if previous.is_some_and(|(_, holiday)| holiday.end > start)
+            || next.is_some_and(|(_, holiday)| holiday.start < end)
+        {
+            return Err(ContractError::FeeHolidayOverlap {});
+        }
+        Ok(())
+    }
+
+    /// Rejects a settlement route of `denom` that is empty, cyclic, too long or goes through a
+    /// denied pool
+    pub(crate) fn validate_settlement_route(
+        &self,
+        storage: &dyn Storage,
+        denom: &str,
+        routes: &[SwapAmountInRoute],
+    ) -> Result<(), ContractError> {
+        let config = self.config.load(storage)?;
+        let settlement_denom = &routes
+            .last()
+            .ok_or(ContractError::EmptyRoutes {})?
+            .token_out_denom;
+        if settlement_denom == denom {
+            return Err(ContractError::CyclicSettlementRoute {});
+        }
+        if routes.len() > config.max_route_length as usize {
+            return Err(ContractError::RouteTooLong {
+                max: config.max_route_length,
+            });
+        }
+        for route in routes {
+            if self.denied_pools.has(storage, route.pool_id) {
+                return Err(ContractError::PoolDenied {
+                    pool_id: route.pool_id,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the full input back to the refund address after a failed swap. The fee is only
+    /// transferred on success, so it is refunded as well.
+    fn refund_failed_swap(
+        &self,
+        storage: &mut dyn Storage,
+        active_swap: ActiveSwap,
+        refund_address: Addr,
+        reason: String,
+    ) -> Result<Response, ContractError> {
+        let context = active_swap.context();
+        // nothing was swapped, the caller may retry with the same client id
+        if let Some(client_id) = &active_swap.client_id {
+            self.client_ids
+                .remove(storage, (&active_swap.original_sender, client_id));
+        }
+        let refund = Coin {
+            denom: active_swap.token_in.denom,
+            amount: active_swap
+                .token_in
+                .amount
+                .checked_add(active_swap.fee.amount)?,
+        };
+
+        let mut event = new_event("affiliate_swap_failed");
+        if let Some(client_id) = active_swap.client_id {
+            event = event.add_attribute("client_id", client_id);
+        }
+        if let Some(channel) = active_swap.channel {
+            event = event.add_attribute("channel", channel);
+        }
+        Ok(Response::new()
+            .add_message(BankMsg::Send {
+                to_address: refund_address.to_string(),
+                amount: vec![refund.clone()],
+            })
+            .add_event(
+                event
+                    .add_attribute("sender", active_swap.original_sender)
+                    .add_attribute("swap_id", active_swap.swap_id.to_string())
+                    .add_attribute("refund_address", refund_address)
+                    .add_attribute("refund", refund.to_string())
+                    .add_attribute("route", context.route)
+                    .add_attribute("token_in", context.token_in.to_string())
+                    .add_attribute("token_out_min", context.token_out_min.to_string())
+                    .add_attribute("failure", SwapFailure::classify(&reason).as_str())
+                    .add_attribute("reason", reason),
+            ))
+    }
+
+    pub fn reply(&self, ctx: (DepsMut, Env), msg: Reply) -> Result<Response, ContractError> {
+        ctx.0.api.debug(&format!("Reply: {:?}", msg));
+        match ReplyKind::parse(msg.id)? {
+            (ReplyKind::Swap, swap_id) => self.swap_reply(ctx, swap_id, msg.result),
+            (ReplyKind::OutputTransfer, swap_id) => {
+                self.output_transfer_reply(ctx, swap_id, msg.result)
+            }
+            (ReplyKind::ChildInstantiate, child_id) => {
+                self.child_instantiate_reply(ctx, child_id, msg.result)
+            }
+            (ReplyKind::FeeSettlement, swap_id) => {
+                self.fee_settlement_reply(ctx, swap_id, msg.result)
+            }
+            (ReplyKind::FeeTransfer, swap_id) => self.fee_transfer_reply(ctx, swap_id, msg.result),
+            (
+                kind @ (ReplyKind::SurplusTransfer
+                | ReplyKind::TreasuryTransfer
+                | ReplyKind::RebateTransfer),
+                swap_id,
+            ) => self.share_transfer_reply(ctx, kind, swap_id, msg.result),
+        }
+    }
+
+    pub fn sudo(&self, ctx: (DepsMut, Env), msg: SudoMsg) -> Result<Response, ContractError> {
+        let (deps, env) = ctx;
+        match msg {
+            SudoMsg::SyncParams {
+                max_fee_percentage,
+                paused,
+            } => {
+                validate_max_fee(max_fee_percentage)?;
+                let mut config = self.config.load(deps.storage)?;
+                config.max_fee_bps = percentage_to_bps(max_fee_percentage)?;
+                self.config.save(deps.storage, &config)?;
+
+                // an existing pause is kept as is, paused by governance or by the owner
+                if !paused {
+                    self.pause_info.remove(deps.storage);
+                } else if self.pause_info.may_load(deps.storage)?.is_none() {
+                    self.pause_info.save(
+                        deps.storage,
+                        &PauseInfo {
+                            paused_by: env.contract.address,
+                            paused_at: env.block.time,
+                        },
+                    )?;
+                }
+
+                Ok(Response::new()
+                    .add_event(config_event(&config))
+                    .add_attribute("method", "sync_params")
+                    .add_attribute("paused", paused.to_string()))
+            }
+        }
+    }
+
+    fn swap_reply(
+        &self,
+        ctx: (DepsMut, Env),
+        swap_id: u64,
+        result: SubMsgResult,
+    ) -> Result<Response, ContractError> {
+        let (deps, env) = ctx;
+        let active_swap = self.active_swaps.load(deps.storage, swap_id)?;
+        self.active_swaps.remove(deps.storage, swap_id);
+
+        let amount = match result {
+            SubMsgResult::Err(reason) => {
+                return match active_swap.refund_address.clone() {
+                    Some(refund_address) => {
+                        self.refund_failed_swap(deps.storage, active_swap, refund_address, reason)
+                    }
+                    None => {
+                        Err(SwapFailure::classify(&reason)
+                            .into_error(reason, active_swap.context()))
+                    }
+                }
+            }
+            result => Backend::load(deps.storage)?.swap_output(result)?,
+        };
+
+        // Success
+        let token_out_denom = &active_swap.token_out_denom;
+        let balance_events = self.check_swap_balances(&deps.querier, &env, &active_swap, amount)?;
+
+        // the full input, fee included, counts towards the volume of the sender
+        let input = active_swap
+            .token_in
+            .amount
+            .checked_add(active_swap.fee.amount)?;
+        self.volumes.update(
+            deps.storage,
+            (&active_swap.original_sender, &active_swap.fee.denom),
+            |volume| -> StdResult<_> { Ok(volume.unwrap_or_default().checked_add(input)?) },
+        )?;
+        // only tracked while capped. Refunded swaps are not counted
+        let capped = self
+            .input_limits
+            .may_load(deps.storage, &active_swap.fee.denom)?
+            .is_some_and(|limits| limits.max_daily_volume.is_some());
+        if capped {
+            self.add_rolling_volume(
+                deps.storage,
+                &env,
+                &active_swap.original_sender,
+                &coin(input.u128(), &active_swap.fee.denom),
+            )?;
+        }
+        if cfg!(feature = "stats") {
+            let day = env.block.time.seconds() / SECONDS_PER_DAY;
+            let add_swap = |stats: Option<Stats>| -> StdResult<_> {
+                let stats = stats.unwrap_or_default();
+                Ok(Stats {
+                    volume: stats.volume.checked_add(input)?,
+                    fees: stats.fees.checked_add(active_swap.fee.amount)?,
+                    swap_count: stats.swap_count + 1,
+                })
+            };
+            self.daily_stats
+                .update(deps.storage, (day, &active_swap.fee.denom), add_swap)?;
+            self.collector_stats.update(
+                deps.storage,
+                (day, &active_swap.fee_collector, &active_swap.fee.denom),
+                add_swap,
+            )?;
+            if let Some(channel) = &active_swap.channel {
+                self.channel_stats.update(
+                    deps.storage,
+                    (channel, day, &active_swap.fee.denom),
+                    add_swap,
+                )?;
+            }
+        }
+
+        // The swap succeeded: the configured share of the fee is rebated to the swapper along
+        // with the output, and the rest is paid to the affiliate
+        let config = self.config.load(deps.storage)?;
+        // the first fee collector of the sender is attributed its later swaps without one
+        let sender = &active_swap.original_sender;
+        if config.referral_duration > 0
+            && self
+                .referral(deps.storage, &env, &config, sender)?
+                .is_none()
+        {
+            self.save_referral(
+                deps.storage,
+                sender,
+                &Referral {
+                    fee_collector: active_swap.fee_collector.clone(),
+                    referred_at: env.block.time,
+                    expires_at: env.block.time.plus_seconds(config.referral_duration),
+                },
+            )?;
+        }
+        let rebate = coin(
+            fee_amount(active_swap.fee.amount, config.rebate_percentage())?.u128(),
+            &active_swap.fee.denom,
+        );
+
+        // The output above the expected output is shared when configured, the swapper keeping
+        // the rest. Like the fee, a share that cannot be sent is escrowed for its recipient
+        let mut surplus_msgs = vec![];
+        let mut surplus_attributes = vec![];
+        let mut surplus_shares = vec![];
+        let mut output_amount = amount;
+        if let (Some(expected_out), Some(sharing)) =
+            (active_swap.expected_out, &config.surplus_sharing)
+        {
+            let surplus = amount.saturating_sub(expected_out);
+            let shares = [
+                (
+                    "surplus_fee_collector",
+                    ReplyKind::SurplusTransfer,
+                    Some(&active_swap.fee_collector),
+                    sharing.fee_collector_bps,
+                ),
+                (
+                    "surplus_treasury",
+                    ReplyKind::TreasuryTransfer,
+                    sharing.treasury.as_ref(),
+                    sharing.treasury_bps,
+                ),
+            ];
+            for (key, kind, recipient, bps) in shares {
+                let share = surplus.multiply_ratio(bps, 10_000u64);
+                let Some(recipient) = recipient.filter(|_| !share.is_zero()) else {
+                    continue;
+                };
+                output_amount = output_amount.checked_sub(share)?;
+                let share = coin(share.u128(), token_out_denom);
+                surplus_attributes.push((key, share.to_string()));
+                surplus_shares.push((key, recipient.clone(), share.clone()));
+                surplus_msgs.push(self.share_transfer_submsg(
+                    deps.storage,
+                    swap_id,
+                    kind,
+                    key,
+                    recipient,
+                    share,
+                )?);
+            }
+        }
+        let affiliate_fee = coin(
+            active_swap.fee.amount.checked_sub(rebate.amount)?.u128(),
+            &active_swap.fee.denom,
+        );
+
+        // A failed transfer (e.g. to a blocked address) must not revert the swap: the output is
+        // escrowed in the transfer reply instead
+        let mut output = PendingOutput {
+            recipient: active_swap.receiver().clone(),
+            amount: coin(output_amount.u128(), token_out_denom),
+            rebate: (!rebate.amount.is_zero()).then(|| rebate.clone()),
+        };
+        let mut rebate_msgs = vec![];
+        let output_submsg = match &active_swap.after {
+            None => SubMsg::reply_always(
+                BankMsg::Send {
+                    to_address: output.recipient.to_string(),
+                    amount: output.coins()?,
+                },
+                ReplyKind::OutputTransfer.reply_id(swap_id),
+            ),
+            Some(after) => {
+                // the action only gets the output, the rebate is sent to the receiver apart
+                if let Some(rebate) = output.rebate.take() {
+                    rebate_msgs.push(self.share_transfer_submsg(
+                        deps.storage,
+                        swap_id,
+                        ReplyKind::RebateTransfer,
+                        "rebate",
+                        &output.recipient,
+                        rebate,
+                    )?);
+                }
+                self.after_swap_submsg(deps.storage, after, &output, swap_id)?
+            }
+        };
+        self.pending_outputs.save(deps.storage, swap_id, &output)?;
+
+        // Pay the affiliate, skipping the transfer if the fee is zero. A fee with a settlement
+        // route is swapped first and paid in the settlement reply. Like the output, a fee that
+        // cannot be sent is escrowed for the collector in the transfer reply
+        let mut fee_msgs = vec![];
+        let mut fee_events = vec![];
+        if !affiliate_fee.amount.is_zero() {
+            let backend = Backend::load(deps.storage)?;
+            let settlement = match self.settlement_route(
+                deps.storage,
+                &config,
+                &active_swap.fee_collector,
+                &affiliate_fee.denom,
+            )? {
+                // the fee is paid as is when the settlement swap cannot be estimated
+                Some(routes) => backend
+                    .estimate_swap(
+                        &deps.querier,
+                        &env.contract.address,
+                        &routes,
+                        &affiliate_fee,
+                    )
+                    .ok()
+                    .map(|estimate| (routes, estimate)),
+                None => None,
+            };
+            let fee_msg = match settlement {
+                Some((routes, estimate)) => {
+                    self.pending_settlements.save(
+                        deps.storage,
+                        swap_id,
+                        &PendingSettlement {
+                            fee_collector: active_swap.fee_collector.clone(),
+                            fee: affiliate_fee.clone(),
+                            settlement_denom: routes[routes.len() - 1].token_out_denom.clone(),
+                        },
+                    )?;
+                    // a settlement swap that would get less fails, and the fee is paid as is
+                    let min_out =
+                        estimate - fee_amount(estimate, config.settlement_slippage_tolerance())?;
+                    let settlement_msg = backend.swap_msg(
+                        &env.contract.address,
+                        routes,
+                        &affiliate_fee,
+                        min_out.max(Uint128::one()),
+                    )?;
+                    SubMsg::reply_always(settlement_msg, ReplyKind::FeeSettlement.reply_id(swap_id))
+                }
+                None => self.fee_transfer_submsg(
+                    deps.storage,
+                    swap_id,
+                    &active_swap.fee_collector,
+                    affiliate_fee.clone(),
+                )?,
+            };
+            fee_msgs.push(fee_msg);
+        }
+
+        // When the fee is rebated or the surplus shared, each recipient gets its own event so
+        // the split is not inferred from the bank transfers
+        let mut shares = vec![];
+        if !affiliate_fee.amount.is_zero() {
+            shares.push((
+                "fee_collector",
+                active_swap.fee_collector.clone(),
+                affiliate_fee,
+            ));
+        }
+        if !rebate.amount.is_zero() {
+            shares.push(("rebate", output.recipient.clone(), rebate.clone()));
+        }
+        shares.extend(surplus_shares);
+        if shares.iter().any(|(share, _, _)| *share != "fee_collector") {
+            fee_events.extend(shares.into_iter().map(|(share, recipient, amount)| {
+                new_event("affiliate_fee_share")
+                    .add_attribute("swap_id", active_swap.swap_id.to_string())
+                    .add_attribute("share", share)
+                    .add_attribute("recipient", recipient)
+                    .add_attribute("amount", amount.to_string())
+            }));
+        }
+
+        let token_in = active_swap.token_in;
+        let mut swap_event = new_event("affiliate_swap");
+        if !rebate.amount.is_zero() {
+            swap_event = swap_event.add_attribute("rebate", rebate.to_string());
+        }
+        swap_event = swap_event.add_attributes(surplus_attributes);
+        if let Some(ibc_origin) = active_swap.ibc_origin {
+            swap_event = swap_event
+                .add_attribute("origin_channel", ibc_origin.channel)
+                .add_attribute("origin_sender", ibc_origin.sender);
+        }
+        match &active_swap.after {
+            Some(AfterSwap::Execute { contract, .. }) => {
+                swap_event = swap_event.add_attribute("after_execute", contract);
+            }
+            Some(AfterSwap::VaultDeposit { vault }) => {
+                swap_event = swap_event.add_attribute("after_vault_deposit", vault);
+            }
+            None => {}
+        }
+        if let Some(client_id) = &active_swap.client_id {
+            swap_event = swap_event.add_attribute("client_id", client_id);
+        }
+        if let Some(channel) = &active_swap.channel {
+            swap_event = swap_event.add_attribute("channel", channel);
+        }
+        if active_swap.fee_overridden {
+            swap_event = swap_event.add_attribute("fee_override", "true");
+        }
+        // symbols and decimals next to the raw denoms, for the denoms with metadata
+        let swap_in_metadata = self
+            .denom_metadata
+            .may_load(deps.storage, &token_in.denom)?;
+        let token_out_metadata = self
+            .denom_metadata
+            .may_load(deps.storage, token_out_denom)?;
+        for (prefix, metadata) in [
+            ("swap_token_in", &swap_in_metadata),
+            ("token_out", &token_out_metadata),
+        ] {
+            if let Some(metadata) = metadata {
+                swap_event = swap_event
+                    .add_attribute(format!("{prefix}_symbol"), &metadata.symbol)
+                    .add_attribute(format!("{prefix}_decimals"), metadata.decimals.to_string());
+            }
+        }
+
+        // price of the input token expressed in the output token
+        let execution_price = Decimal::checked_from_ratio(amount, token_in.amount)?;
+
+        let response = SwapResponse {
+            swap_id: active_swap.swap_id,
+            original_sender: active_swap.original_sender.into_string(),
+            fee: active_swap.fee.amount,
+            fee_collector: active_swap.fee_collector,
+            swap_in_amount: token_in.amount,
+            swap_in_denom: token_in.clone().denom,
+            token_out_denom: token_out_denom.to_string(),
+            token_out_amount: amount,
+            route: active_swap.route,
+            client_id: active_swap.client_id,
+            swap_in_metadata,
+            token_out_metadata,
+        };
+
+        let data = match active_swap.response_data {
+            ResponseData::Json => Some(to_binary(&response)?),
+            ResponseData::Proto => Some(osmosis::swap_response_data(amount)),
+            ResponseData::None => None,
+        };
+
+        let mut reply_response = Response::new();
+        if let Some(data) = data {
+            reply_response = reply_response.set_data(data);
+        }
+        Ok(reply_response
+            .add_submessages(fee_msgs)
+            .add_submessages(surplus_msgs)
+            .add_submessages(rebate_msgs)
+            .add_submessage(output_submsg)
+            .add_events(fee_events)
+            .add_events(balance_events)
+            .add_event(
+                swap_event
+                    .add_attribute("sender", response.original_sender)
+                    .add_attribute("receiver", output.recipient)
+                    .add_attribute("swap_token_in", token_in.to_string())
+                    .add_attribute("fee", active_swap.fee.to_string())
+                    .add_attribute("fee_percentage", active_swap.fee_percentage.to_string())
+                    .add_attribute("fee_collector", response.fee_collector)
+                    .add_attribute("route", route_to_string(&response.route))
+                    .add_attribute("swap_id", response.swap_id.to_string())
+                    .add_attribute(
+                        "token_out",
+                        Coin {
+                            denom: token_out_denom.to_string(),
+                            amount,
+                        }
+                        .to_string(),
+                    )
+                    .add_attribute("execution_price", execution_price.to_string()),
+            ))
+    }
+
+    /// Checks that the balances of the contract moved by the input and the output `amount`
+    /// reported by the pools. A shortfall reverts the swap, as paying the output would take
+    /// from other swaps or the escrow, while a surplus is only reported.
+    fn check_swap_balances(
+        &self,
+        querier: &QuerierWrapper,
+        env: &Env,
+        active_swap: &ActiveSwap,
+        amount: Uint128,
+    ) -> Result<Vec<Event>, ContractError> {
+        let mut events = vec![];
+        for before in &active_swap.balances_before {
+            let mut expected = before.amount;
+            if before.denom == active_swap.token_out_denom {
+                expected = expected.checked_add(amount)?;
+            }
+            if before.denom == active_swap.token_in.denom {
+                expected = expected.checked_sub(active_swap.token_in.amount)?;
+            }
+            let actual = querier.query_balance(&env.contract.address, &before.denom)?;
+            let expected = coin(expected.u128(), &before.denom);
+            if actual.amount < expected.amount {
+                return Err(ContractError::BalanceMismatch {
+                    expected: expected.to_string(),
+                    actual: actual.to_string(),
+                });
+            }
+            if actual.amount > expected.amount {
+                events.push(
+                    new_event("affiliate_swap_balance_surplus")
+                        .add_attribute("swap_id", active_swap.swap_id.to_string())
+                        .add_attribute("expected", expected.to_string())
+                        .add_attribute("actual", actual.to_string()),
+                );
+            }
+        }
+        Ok(events)
+    }
+
+    /// Submessage running `after` with the swap output, replied to like the output transfer
+    fn after_swap_submsg(
+        &self,
+        storage: &dyn Storage,
+        after: &AfterSwap,
+        output: &PendingOutput,
+        swap_id: u64,
+    ) -> Result<SubMsg, ContractError> {
+        let (contract, msg) = match after {
+            AfterSwap::Execute { contract, msg } => (contract, msg.clone()),
+            AfterSwap::VaultDeposit { vault } => (
+                vault,
+                to_binary(&VaultExecuteMsg::Deposit {
+                    amount: output.amount.amount,
+                    recipient: Some(output.recipient.to_string()),
+                })?,
+            ),
+        };
+        let contract = Addr::unchecked(contract);
+        let policy = self.funded_callback_target(storage, &contract)?;
+        Ok(SubMsg::reply_always(
+            WasmMsg::Execute {
+                contract_addr: contract.into_string(),
+                msg,
+                funds: vec![output.amount.clone()],
+            },
+            ReplyKind::OutputTransfer.reply_id(swap_id),
+        )
+        .with_gas_limit(policy.gas_limit))
+    }
+
+    fn output_transfer_reply(
+        &self,
+        ctx: (DepsMut, Env),
+        swap_id: u64,
+        result: SubMsgResult,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env) = ctx;
+        let output = self.pending_outputs.load(deps.storage, swap_id)?;
+        self.pending_outputs.remove(deps.storage, swap_id);
+
+        let reason = match result {
+            SubMsgResult::Ok(_) => return Ok(Response::new()),
+            SubMsgResult::Err(reason) => reason,
+        };
+
+        let coins = output.coins()?;
+        for escrowed in &coins {
+            self.claimable.update(
+                deps.storage,
+                (&output.recipient, escrowed.denom.as_str()),
+                |claimable| -> StdResult<_> {
+                    Ok(claimable.unwrap_or_default().checked_add(escrowed.amount)?)
+                },
+            )?;
+        }
+
+        Ok(Response::new().add_event(
+            new_event("affiliate_swap_output_escrowed")
+                .add_attribute("swap_id", swap_id.to_string())
+                .add_attribute("recipient", output.recipient)
+                .add_attribute("amount", coins_to_string(&coins))
+                .add_attribute("reason", reason),
+        ))
+    }
+
+    /// Pays the fee swapped into the settlement denom of its collector, or the fee as is if
+    /// the swap failed, e.g. for lack of liquidity
+    fn fee_settlement_reply(
+        &self,
+        ctx: (DepsMut, Env),
+        swap_id: u64,
+        result: SubMsgResult,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env) = ctx;
+        let settlement = self.pending_settlements.load(deps.storage, swap_id)?;
+        self.pending_settlements.remove(deps.storage, swap_id);
+
+        let event = new_event("affiliate_fee_settled")
+            .add_attribute("swap_id", swap_id.to_string())
+            .add_attribute("fee_collector", &settlement.fee_collector)
+            .add_attribute("fee", settlement.fee.to_string());
+        let (amount, event) = match result {
+            SubMsgResult::Ok(_) => {
+                let settled = Coin::new(
+                    Backend::load(deps.storage)?.swap_output(result)?.u128(),
+                    settlement.settlement_denom,
+                );
+                let event = event.add_attribute("settled", settled.to_string());
+                (settled, event)
+            }
+            SubMsgResult::Err(reason) => (
+                settlement.fee,
+                event
+                    .add_attribute("settled", "")
+                    .add_attribute("reason", reason),
+            ),
+        };
+
+        Ok(Response::new()
+            .add_submessage(self.fee_transfer_submsg(
+                deps.storage,
+                swap_id,
+                &settlement.fee_collector,
+                amount,
+            )?)
+            .add_event(event))
+    }
+
+    /// Submessage paying `fee` to `fee_collector`, replied to so a failed transfer is escrowed
+    fn fee_transfer_submsg(
+        &self,
+        storage: &mut dyn Storage,
+        swap_id: u64,
+        fee_collector: &Addr,
+        fee: Coin,
+    ) -> Result<SubMsg, ContractError> {
+        let msg = BankMsg::Send {
+            to_address: fee_collector.to_string(),
+            amount: vec![fee.clone()],
+        };
+        self.pending_fees.save(
+            storage,
+            swap_id,
+            &PendingFee {
+                fee_collector: fee_collector.clone(),
+                fee,
+            },
+        )?;
+        Ok(SubMsg::reply_always(
+            msg,
+            ReplyKind::FeeTransfer.reply_id(swap_id),
+        ))
+    }
+
+    /// Escrows the fee of a failed transfer for its collector, to be claimed with `claim`
+    fn fee_transfer_reply(
+        &self,
+        ctx: (DepsMut, Env),
+        swap_id: u64,
+        result: SubMsgResult,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env) = ctx;
+        let pending = self.pending_fees.load(deps.storage, swap_id)?;
+        self.pending_fees.remove(deps.storage, swap_id);
+
+        let reason = match result {
+            SubMsgResult::Ok(_) => {
+                return Ok(Response::new().add_event(
+                    new_event("affiliate_fee_paid")
+                        .add_attribute("fee_collector", pending.fee_collector)
+                        .add_attribute("denom", pending.fee.denom)
+                        .add_attribute("amount", pending.fee.amount)
+                        .add_attribute("swap_id", swap_id.to_string()),
+                ))
+            }
+            SubMsgResult::Err(reason) => reason,
+        };
+
+        self.claimable.update(
+            deps.storage,
+            (&pending.fee_collector, pending.fee.denom.as_str()),
+            |claimable| -> StdResult<_> {
+                Ok(claimable
+                    .unwrap_or_default()
+                    .checked_add(pending.fee.amount)?)
+            },
+        )?;
+
+        Ok(Response::new().add_event(
+            new_event("affiliate_fee_escrowed")
+                .add_attribute("swap_id", swap_id.to_string())
+                .add_attribute("fee_collector", pending.fee_collector)
+                .add_attribute("amount", pending.fee.to_string())
+                .add_attribute("reason", reason),
+        ))
+    }
+
+    /// Submessage sending a `share` of the swap output to `recipient`, replied to so a failed
+    /// transfer is escrowed
+    fn share_transfer_submsg(
+        &self,
+        storage: &mut dyn Storage,
+        swap_id: u64,
+        kind: ReplyKind,
+        share: &str,
+        recipient: &Addr,
+        amount: Coin,
+    ) -> Result<SubMsg, ContractError> {
+        let msg = BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount: vec![amount.clone()],
+        };
+        self.pending_shares.save(
+            storage,
+            (swap_id, kind as u8),
+            &PendingShare {
+                share: share.to_string(),
+                recipient: recipient.clone(),
+                amount,
+            },
+        )?;
+        Ok(SubMsg::reply_always(msg, kind.reply_id(swap_id)))
+    }
+
+    /// Escrows the share of a failed transfer for its recipient, to be claimed with `claim`
+    fn share_transfer_reply(
+        &self,
+        ctx: (DepsMut, Env),
+        kind: ReplyKind,
+        swap_id: u64,
+        result: SubMsgResult,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env) = ctx;
+        let pending = self
+            .pending_shares
+            .load(deps.storage, (swap_id, kind as u8))?;
+        self.pending_shares
+            .remove(deps.storage, (swap_id, kind as u8));
+
+        let reason = match result {
+            SubMsgResult::Ok(_) => return Ok(Response::new()),
+            SubMsgResult::Err(reason) => reason,
+        };
+
+        self.claimable.update(
+            deps.storage,
+            (&pending.recipient, pending.amount.denom.as_str()),
+            |claimable| -> StdResult<_> {
+                Ok(claimable
+                    .unwrap_or_default()
+                    .checked_add(pending.amount.amount)?)
+            },
+        )?;
+
+        Ok(Response::new().add_event(
+            new_event("affiliate_fee_share_escrowed")
+                .add_attribute("swap_id", swap_id.to_string())
+                .add_attribute("share", pending.share)
+                .add_attribute("recipient", pending.recipient)
+                .add_attribute("amount", pending.amount.to_string())
+                .add_attribute("reason", reason),
+        ))
+    }
+
+    fn child_instantiate_reply(
+        &self,
+        ctx: (DepsMut, Env),
+        child_id: u64,
+        result: SubMsgResult,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env) = ctx;
+        let child = self.pending_children.load(deps.storage, child_id)?;
+        self.pending_children.remove(deps.storage, child_id);
+
+        // only successful instantiations are replied to
+        let data = match result {
+            SubMsgResult::Ok(SubMsgResponse {
+                data: Some(data), ..
+            }) => data,
+            _ => return Err(ContractError::Unexpected {}),
+        };
+        let res = cw_utils::parse_instantiate_response_data(&data)?;
+        let address = deps.api.addr_validate(&res.contract_address)?;
+        self.children.save(deps.storage, &address, &child)?;
+
+        Ok(Response::new().add_event(
+            new_event("affiliate_swap_child_created")
+                .add_attribute("address", address)
+                .add_attribute("label", child.label)
+                .add_attribute("fee_collector", child.fee_collector)
+                .add_attribute("name", child.branding.name),
+        ))
+    }
+}
+
+#[contract]
+impl AffiliateSwapInterface for AffiliateSwap<'_> {
+    type Error = ContractError;
+
+    #[msg(exec)]
+    fn swap(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        routes: Vec<SwapAmountInRoute>,
+        token_out_min_amount: Option<Coin>,
+        fee_percentage: Option<Decimal>,
+        // Named `affiliate_address` in earlier versions. The old name is still accepted but
+        // deprecated, and will be removed in the next breaking release.
+        #[serde(alias = "affiliate_address")] fee_collector: Option<String>,
+        on_failure: Option<OnFailure>,
+        token_in_denom: Option<String>,
+        allow_cyclic: Option<bool>,
+        receiver: Option<String>,
+        ibc_origin: Option<IbcOrigin>,
+        quote: Option<RouteQuote>,
+        expected_out: Option<Uint128>,
+        slippage_tolerance: Option<Decimal>,
+        max_price_impact: Option<Decimal>,
+        response_data: Option<ResponseData>,
+        with_data: Option<bool>,
+        after: Option<AfterSwap>,
+        client_id: Option<String>,
+        channel: Option<String>,
+        fee_override: Option<FeeOverride>,
+    ) -> Result<Response, ContractError> {
+        let (deps, env, info) = ctx;
+        let config = self.config.load(deps.storage)?;
+        let ValidatedSwap {
+            coin,
+            extra_funds,
+            token_out_denom,
+            fee_collector,
+            after,
+            fee_override,
+        } = self.validate_swap(
+            deps.as_ref(),
+            &env,
+            &config,
+            &info,
+            &routes,
+            token_out_min_amount.as_ref(),
+            fee_collector,
+            token_in_denom,
+            allow_cyclic,
+            quote.as_ref(),
+            slippage_tolerance,
+            after,
+            client_id.as_deref(),
+            channel.as_deref(),
+            fee_override.as_ref(),
+        )?;
+        if let Some(client_id) = &client_id {
+            self.client_ids
+                .save(deps.storage, (&info.sender, client_id), &env.block.time)?;
+        }
+        // assign the swap a monotonically increasing id
+        let swap_id = self.swap_count.may_load(deps.storage)?.unwrap_or_default() + 1;
+        self.swap_count.save(deps.storage, &swap_id)?;
+
+        // Safety check: the swap id is unique
+        if self.active_swaps.has(deps.storage, swap_id) {
+            // This should never happen as ids are never reused
+            return Err(ContractError::ActiveSwapExists {});
+        }
+
+        let receiver = receiver
+            .map(|receiver| deps.api.addr_validate(&receiver))
+            .transpose()?;
+
+        // Swaps received through ibc-hooks are sent by an address nobody controls: the output
+        // needs an explicit receiver, and failures are refunded to it instead of failing the
+        // packet by default
+        let default_on_failure = match &ibc_origin {
+            Some(ibc_origin) => {
+                ibc_hooks::verify_origin(deps.api, &info.sender, ibc_origin)?;
+                if receiver.is_none() {
+                    return Err(ContractError::IbcReceiverRequired {});
+                }
+                OnFailure::RefundAndAck {
+                    refund_address: None,
+                }
+            }
+            None => OnFailure::Revert,
+        };
+        let refund_address = match on_failure.unwrap_or(default_on_failure) {
+            OnFailure::Revert => None,
+            OnFailure::RefundAndAck {
+                refund_address: Some(refund_address),
+            } => Some(deps.api.addr_validate(&refund_address)?),
+            OnFailure::RefundAndAck {
+                refund_address: None,
+            } => Some(receiver.clone().unwrap_or_else(|| info.sender.clone())),
+        };
+
+        let fee_percentage = self.effective_fee_percentage(
+            deps.storage,
+            &env,
+            &config,
+            &info.sender,
+            &coin.denom,
+            Some(&token_out_denom),
+            fee_percentage,
+            fee_override,
+        )?;
+
+        // calculate the fee to deduct
+        let fee = fee_amount(coin.amount, fee_percentage)?;
+
+        let token_in = Coin {
+            denom: coin.denom.clone(),
+            amount: coin.amount.checked_sub(fee)?,
+        };
+
+        // the estimate at execution time, when a slippage tolerance or a max price impact needs
+        // it
+        let backend = Backend::load(deps.storage)?;
+        let estimate = if slippage_tolerance.is_some() || max_price_impact.is_some() {
+            Some(backend.estimate_swap(&deps.querier, &env.contract.address, &routes, &token_in)?)
+        } else {
+            None
+        };
+        if let (Some(max), Some(estimate)) = (max_price_impact, estimate) {
+            let spot_price = backend.route_spot_price(&deps.querier, &routes, &token_in.denom)?;
+            check_price_impact(price_impact(token_in.amount, estimate, spot_price)?, max)?;
+        }
+        let token_out_min_amount =
+            min_output(token_out_min_amount.as_ref(), slippage_tolerance, estimate)?;
+
+        let swap_msg = backend.swap_msg(
+            &env.contract.address,
+            routes.clone(),
+            &token_in,
+            token_out_min_amount,
+        )?;
+
+        // the swap id is part of the reply id so several swaps can be in flight at the same time
+        let swap_submsg = SubMsg::reply_always(swap_msg, ReplyKind::Swap.reply_id(swap_id));
+
+        let balances_before = if config.balance_checks {
+            swap_balances(&deps.querier, &env, &token_in.denom, &token_out_denom)?
+        } else {
+            vec![]
+        };
+
+        // `with_data: false` overrides any requested format
+        let response_data = match with_data {
+            Some(false) => ResponseData::None,
+            _ => response_data.unwrap_or_default(),
+        };
+        self.active_swaps.save(
+            deps.storage,
+            swap_id,
+            &ActiveSwap {
+                swap_id,
+                created_at_height: env.block.height,
+                original_sender: info.sender.clone(),
+                fee_collector,
+                fee: Coin {
+                    denom: coin.denom,
+                    amount: fee,
+                },
+                fee_percentage,
+                token_in,
+                token_out_denom,
+                token_out_min_amount,
+                route: routes,
+                refund_address,
+                receiver,
+                ibc_origin,
+                expected_out,
+                balances_before,
+                response_data,
+                after,
+                client_id,
+                channel,
+                fee_overridden: fee_override.is_some(),
+            },
+        )?;
+
+        let mut response = Response::new()
+            .add_submessage(swap_submsg)
+            .add_attribute("method", "swap");
+        if !extra_funds.is_empty() {
+            response = response.add_message(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: extra_funds,
+            });
+        }
+
+        Ok(response)
+    }
+
+    #[msg(exec)]
+    fn clear_stale_swap(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+    ) -> Result<Response, ContractError> {
+        let (deps, env, info) = ctx;
+        let config = self.config.load(deps.storage)?;
+        let stale_swap_blocks = config.stale_swap_blocks;
+
+        // ids are assigned in order, so the oldest swaps come first
+        let stale_swaps = self
+            .active_swaps
+            .range(deps.storage, None, None, Order::Ascending)
+            .take_while(|item| match item {
+                Ok((_, active_swap)) => {
+                    active_swap
+                        .created_at_height
+                        .saturating_add(stale_swap_blocks)
+                        < env.block.height
+                }
+                Err(_) => true,
+            })
+            .take(MAX_STALE_SWAPS_CLEARED)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if stale_swaps.is_empty() {
+            return Err(ContractError::NoStaleSwap {});
+        }
+
+        let mut response = Response::new().add_attribute("method", "clear_stale_swap");
+        let mut tips: Vec<Coin> = vec![];
+        for (swap_id, active_swap) in stale_swaps {
+            self.active_swaps.remove(deps.storage, swap_id);
+            if let Some(client_id) = &active_swap.client_id {
+                self.client_ids
+                    .remove(deps.storage, (&active_swap.original_sender, client_id));
+            }
+
+            let refund_address = active_swap
+                .refund_address
+                .clone()
+                .unwrap_or_else(|| active_swap.receiver().clone());
+            // the fee is only paid on success, so it is refunded as well, minus the tip of
+            // the caller
+            let input = active_swap
+                .token_in
+                .amount
+                .checked_add(active_swap.fee.amount)?;
+            let tip = fee_amount(input, config.crank_tip_percentage())?;
+            let refund = Coin {
+                denom: active_swap.token_in.denom,
+                amount: input.checked_sub(tip)?,
+            };
+            if !tip.is_zero() {
+                match tips.iter_mut().find(|c| c.denom == refund.denom) {
+                    Some(total) => total.amount = total.amount.checked_add(tip)?,
+                    None => tips.push(coin(tip.u128(), &refund.denom)),
+                }
+            }
+            response = response
+                .add_message(BankMsg::Send {
+                    to_address: refund_address.to_string(),
+                    amount: vec![refund.clone()],
+                })
+                .add_event(
+                    new_event("affiliate_swap_cleared")
+                        .add_attribute("swap_id", swap_id.to_string())
+                        .add_attribute("sender", active_swap.original_sender)
+                        .add_attribute("refund_address", refund_address)
+                        .add_attribute("refund", refund.to_string())
+                        .add_attribute("tip", tip),
+                );
+        }
+        if !tips.is_empty() {
+            response = response
+                .add_attribute("tip", coins_to_string(&tips))
+                .add_message(BankMsg::Send {
+                    to_address: info.sender.to_string(),
+                    amount: tips,
+                });
+        }
+
+        Ok(response)
+    }
+
+    #[msg(exec)]
+    fn claim(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        recipient: Option<String>,
+        claimant: Option<String>,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env, info) = ctx;
+        // an operator claims for its claimant, who gets the balance
+        let (claimant, recipient) = match (claimant, recipient) {
+            (Some(claimant), None) => {
+                let claimant = deps.api.addr_validate(&claimant)?;
+                if !self
+                    .claim_operators
+                    .has(deps.storage, (&claimant, &info.sender))
+                {
+                    return Err(ContractError::NotClaimOperator {});
+                }
+                (claimant.clone(), claimant)
+            }
+            (Some(_), Some(_)) => return Err(ContractError::OperatorClaimRecipient {}),
+            (None, Some(recipient)) => (info.sender.clone(), deps.api.addr_validate(&recipient)?),
+            (None, None) => (info.sender.clone(), info.sender.clone()),
+        };
+
+        let claimable = self
+            .claimable
+            .prefix(&claimant)
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| item.map(|(denom, amount)| Coin { denom, amount }))
+            .collect::<StdResult<Vec<_>>>()?;
+        if claimable.is_empty() {
+            return Err(ContractError::NothingToClaim {});
+        }
+        for coin in &claimable {
+            self.claimable
+                .remove(deps.storage, (&claimant, coin.denom.as_str()));
+        }
+
+        let mut event = new_event("affiliate_swap_escrow_claimed")
+            .add_attribute("claimant", &claimant)
+            .add_attribute("recipient", &recipient)
+            .add_attribute("amount", coins_to_string(&claimable));
+        if claimant != info.sender {
+            event = event.add_attribute("operator", info.sender);
+        }
+
+        Ok(Response::new()
+            .add_message(BankMsg::Send {
+                to_address: recipient.to_string(),
+                amount: claimable,
+            })
+            .add_attribute("method", "claim")
+            .add_event(event))
+    }
+
+    #[msg(exec)]
+    fn set_claim_operator(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        operator: String,
+        allowed: bool,
+    ) -> Result<Response, ContractError> {
+        let (deps, _env, info) = ctx;
+        let operator = deps.api.addr_validate(&operator)?;
+        if allowed {
+            self.claim_operators
+                .save(deps.storage, (&info.sender, &operator), &())?;
+        } else {
+            self.claim_operators
+                .remove(deps.storage, (&info.sender, &operator));
+        }
+
+        Ok(Response::new()
+            .add_attribute("method", "set_claim_operator")
+            .add_event(
+                new_event("affiliate_swap_claim_operator_set")
+                    .add_attribute("claimant", info.sender)
+                    .add_attribute("operator", operator)
+                    .add_attribute("allowed", allowed.to_string()),
+            ))
+    }
+
+    #[msg(query)]
+    fn get_max_fee_percentage(
         &self,
         ctx: (Deps, Env),
     ) -> Result<MaxFeePercentageResponse, ContractError> {
-        let (deps, _env) = ctx;
-        let max_fee_percentage = self.max_fee_percentage.load(deps.storage)?;
+        let (deps, env) = ctx;
+        // no fee can be charged during a fee holiday
+        let max_fee_percentage = match self.active_fee_holiday(deps.storage, &env)? {
+            Some(_) => Decimal::zero(),
+            None => self.config.load(deps.storage)?.max_fee_percentage(),
+        };
         Ok(MaxFeePercentageResponse { max_fee_percentage })
     }
 
-    pub fn reply(&self, ctx: (DepsMut, Env), msg: Reply) -> Result<Response, ContractError> {
+    #[msg(query)]
+    fn get_config(&self, ctx: (Deps, Env)) -> Result<ConfigResponse, ContractError> {
         let (deps, _env) = ctx;
-        let active_swap = self.active_swap.load(deps.storage)?;
-        self.active_swap.remove(deps.storage);
+        let config = self.config.load(deps.storage)?;
+        Ok(ConfigResponse {
+            max_fee_percentage: config.max_fee_percentage(),
+            crank_tip_percentage: config.crank_tip_percentage(),
+            rebate_percentage: config.rebate_percentage(),
+            settlement_slippage_tolerance: config.settlement_slippage_tolerance(),
+            owner: config.owner,
+            stale_swap_blocks: config.stale_swap_blocks,
+            reject_self_referral: config.reject_self_referral,
+            max_route_length: config.max_route_length,
+            router_pubkey: config.router_pubkey,
+            fee_collector: config.fee_collector,
+            branding: config.branding,
+            surplus_sharing: config.surplus_sharing,
+            referral_duration: config.referral_duration,
+            sender_allowlist: config.sender_allowlist,
+            balance_checks: config.balance_checks,
+            swap_router: config.swap_router,
+            fee_override_pubkey: config.fee_override_pubkey,
+        })
+    }
 
-        deps.api.debug(&format!("Reply: {:?}", msg));
-        // if the message result is an error this will fail
-        let res: MsgSwapExactAmountInResponse = msg.result.try_into()?;
+    #[msg(query)]
+    fn get_claimable(
+        &self,
+        ctx: (Deps, Env),
+        address: String,
+    ) -> Result<ClaimableResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let address = deps.api.addr_validate(&address)?;
+        let coins = self
+            .claimable
+            .prefix(&address)
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| item.map(|(denom, amount)| Coin { denom, amount }))
+            .collect::<StdResult<_>>()?;
+        Ok(ClaimableResponse { coins })
+    }
 
-        // Success
-        let amount = Uint128::from_str(&res.token_out_amount)?;
-        let token_out_denom = &active_swap
-            .swap_msg
-            .routes
-            .last()
-            .ok_or(ContractError::Unexpected {})?
-            .token_out_denom;
+    #[msg(query)]
+    fn get_claim_operators(
+        &self,
+        ctx: (Deps, Env),
+        claimant: String,
+    ) -> Result<ClaimOperatorsResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let claimant = deps.api.addr_validate(&claimant)?;
+        let operators = self
+            .claim_operators
+            .prefix(&claimant)
+            .keys(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+        Ok(ClaimOperatorsResponse { operators })
+    }
 
-        let bank_msg = BankMsg::Send {
-            to_address: active_swap.original_sender.to_string(),
-            amount: coins(amount.u128(), token_out_denom.clone()),
-        };
+    #[msg(query)]
+    fn is_paused(&self, ctx: (Deps, Env)) -> Result<IsPausedResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let pause_info = self.pause_info.may_load(deps.storage)?;
+        Ok(IsPausedResponse {
+            paused: pause_info.is_some(),
+            paused_by: pause_info.as_ref().map(|p| p.paused_by.clone()),
+            paused_at: pause_info.map(|p| p.paused_at),
+        })
+    }
 
-        let token_in: Coin = active_swap
-            .swap_msg
-            .token_in
-            .ok_or(ContractError::Unexpected {})?
-            .try_into()?;
+    #[msg(query)]
+    fn get_supported_interfaces(
+        &self,
+        ctx: (Deps, Env),
+    ) -> Result<SupportedInterfacesResponse, ContractError> {
+        let (deps, _env) = ctx;
+        let supported_interfaces = SUPPORTED_INTERFACES_MAP
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| {
+                let (supported_interface, version) = item?;
+                Ok(ContractSupportedInterface {
+                    supported_interface,
+                    version,
+                })
+            })
+            .collect::<Result<_, ContractError>>()?;
+        Ok(SupportedInterfacesResponse {
+            supported_interfaces,
+        })
+    }
+}
 
-        let response = SwapResponse {
-            original_sender: active_swap.original_sender.into_string(),
-            fee: active_swap.fee.amount,
-            fee_collector: active_swap.fee_collector,
-            swap_in_amount: token_in.amount,
-            swap_in_denom: token_in.clone().denom,
-            token_out_denom: token_out_denom.to_string(),
-            token_out_amount: amount,
-        };
+/// Kinds of submessages the contract handles replies for. The reply id carries the kind in its
+/// top byte and the id of the swap (or child) the submessage belongs to in the remaining bits,
+/// so replies of different kinds and of different swaps never collide.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplyKind {
+    Swap = 1,
+    OutputTransfer = 2,
+    ChildInstantiate = 3,
+    FeeSettlement = 4,
+    FeeTransfer = 5,
+    SurplusTransfer = 6,
+    TreasuryTransfer = 7,
+    RebateTransfer = 8,
+}
 
-        return Ok(Response::new()
-            .add_message(bank_msg)
-            .set_data(to_binary(&response)?)
-            .add_event(
-                Event::new("affiliate_swap")
-                    .add_attribute("sender", response.original_sender)
-                    .add_attribute("swap_token_in", token_in.to_string())
-                    .add_attribute("fee", active_swap.fee.to_string())
-                    .add_attribute(
-                        "token_out",
-                        Coin {
-                            denom: token_out_denom.to_string(),
-                            amount,
-                        }
-                        .to_string(),
-                    ),
-            ));
+impl ReplyKind {
+    const SHIFT: u32 = 56;
+
+    pub fn reply_id(self, swap_id: u64) -> u64 {
+        (self as u64) << Self::SHIFT | swap_id
+    }
+
+    /// Splits a reply id into its kind and swap id
+    pub fn parse(id: u64) -> Result<(ReplyKind, u64), ContractError> {
+        let swap_id = id & ((1 << Self::SHIFT) - 1);
+        match id >> Self::SHIFT {
+            1 => Ok((ReplyKind::Swap, swap_id)),
+            2 => Ok((ReplyKind::OutputTransfer, swap_id)),
+            3 => Ok((ReplyKind::ChildInstantiate, swap_id)),
+            4 => Ok((ReplyKind::FeeSettlement, swap_id)),
+            5 => Ok((ReplyKind::FeeTransfer, swap_id)),
+            6 => Ok((ReplyKind::SurplusTransfer, swap_id)),
+            7 => Ok((ReplyKind::TreasuryTransfer, swap_id)),
+            8 => Ok((ReplyKind::RebateTransfer, swap_id)),
+            _ => Err(ContractError::UnknownReplyId { id }),
+        }
+    }
+}
+
+// Bound of a range of stats keyed by (day, denom)
+type StatsBound<'a> = Option<Bound<'a, (u64, &'a str)>>;
+
+/// Range of the stats from `from_day` to `to_day` included, or after `start_after`
+fn stats_bounds(
+    from_day: Option<u64>,
+    to_day: Option<u64>,
+    start_after: &Option<(u64, String)>,
+) -> (StatsBound<'_>, StatsBound<'_>) {
+    let min = match start_after {
+        Some((day, denom)) => Some(Bound::exclusive((*day, denom.as_str()))),
+        None => from_day.map(|day| Bound::inclusive((day, ""))),
+    };
+    let max = to_day
+        .and_then(|day| day.checked_add(1))
+        .map(|day| Bound::exclusive((day, "")));
+    (min, max)
+}
+
+/// Adds an event with the attributes of `response` to the response of a configuration change
+/// stored outside of `Config` (e.g. a pair fee), `key` naming the changed setting, so indexers
+/// can track these changes from events alone too.
+fn config_changed(response: Response, key: &str) -> Response {
+    let event = new_event("affiliate_swap_config_changed")
+        .add_attribute("key", key)
+        .add_attributes(
+            response
+                .attributes
+                .iter()
+                .filter(|attribute| attribute.key != "method")
+                .cloned(),
+        );
+    response.add_event(event)
+}
+
+/// Event carrying every configuration parameter. Emitted at instantiate and whenever the
+/// config changes so indexers can track the current configuration from events alone.
+fn config_event(config: &Config) -> Event {
+    new_event("affiliate_swap_config")
+        .add_attribute("owner", config.owner.to_string())
+        .add_attribute(
+            "max_fee_percentage",
+            config.max_fee_percentage().to_string(),
+        )
+        .add_attribute("stale_swap_blocks", config.stale_swap_blocks.to_string())
+        .add_attribute(
+            "reject_self_referral",
+            config.reject_self_referral.to_string(),
+        )
+        .add_attribute("max_route_length", config.max_route_length.to_string())
+        .add_attribute(
+            "router_pubkey",
+            config
+                .router_pubkey
+                .as_ref()
+                .map(Binary::to_base64)
+                .unwrap_or_default(),
+        )
+        .add_attribute(
+            "crank_tip_percentage",
+            config.crank_tip_percentage().to_string(),
+        )
+        .add_attribute("rebate_percentage", config.rebate_percentage().to_string())
+        .add_attribute(
+            "surplus_sharing",
+            config
+                .surplus_sharing
+                .as_ref()
+                .map(|sharing| {
+                    format!(
+                        "fee_collector:{},treasury:{}",
+                        bps_to_percentage(sharing.fee_collector_bps),
+                        bps_to_percentage(sharing.treasury_bps)
+                    )
+                })
+                .unwrap_or_default(),
+        )
+        .add_attribute(
+            "treasury",
+            config
+                .surplus_sharing
+                .as_ref()
+                .and_then(|sharing| sharing.treasury.as_ref())
+                .map(Addr::to_string)
+                .unwrap_or_default(),
+        )
+        .add_attribute("referral_duration", config.referral_duration.to_string())
+        .add_attribute("sender_allowlist", config.sender_allowlist.to_string())
+        .add_attribute("balance_checks", config.balance_checks.to_string())
+        .add_attribute(
+            "swap_router",
+            config
+                .swap_router
+                .as_ref()
+                .map(Addr::to_string)
+                .unwrap_or_default(),
+        )
+        .add_attribute(
+            "fee_override_pubkey",
+            config
+                .fee_override_pubkey
+                .as_ref()
+                .map(Binary::to_base64)
+                .unwrap_or_default(),
+        )
+        .add_attribute(
+            "settlement_slippage_tolerance",
+            config.settlement_slippage_tolerance().to_string(),
+        )
+}
+
+fn validate_max_fee(max_fee: Decimal) -> Result<Decimal, ContractError> {
+    if max_fee > Decimal::from_str(TRUE_MAX_FEE)? {
+        return Err(ContractError::InvalidMaxFeePercentage {
+            true_max_fee: TRUE_MAX_FEE.to_string(),
+        });
+    }
+    Ok(max_fee)
+}
+
+fn validate_max_route_length(max_route_length: u32) -> Result<u32, ContractError> {
+    if max_route_length == 0 {
+        return Err(ContractError::InvalidMaxRouteLength {});
+    }
+    Ok(max_route_length)
+}
+
+fn validate_crank_tip_bps(crank_tip_bps: u64) -> Result<u64, ContractError> {
+    if crank_tip_bps > MAX_CRANK_TIP_BPS {
+        return Err(ContractError::CrankTipTooHigh {
+            max: bps_to_percentage(MAX_CRANK_TIP_BPS).to_string(),
+        });
+    }
+    Ok(crank_tip_bps)
+}
+
+fn validate_rebate_bps(rebate_bps: u64) -> Result<u64, ContractError> {
+    if rebate_bps > 10_000 {
+        return Err(ContractError::InvalidRebatePercentage {});
+    }
+    Ok(rebate_bps)
+}
+
+fn validate_settlement_slippage_bps(settlement_slippage_bps: u64) -> Result<u64, ContractError> {
+    if settlement_slippage_bps > 10_000 {
+        return Err(ContractError::InvalidSettlementSlippage {});
+    }
+    Ok(settlement_slippage_bps)
+}
+
+fn validate_fee_override_pubkey(pubkey: &Binary) -> Result<(), ContractError> {
+    if !quote::is_secp256k1_pubkey(pubkey) {
+        return Err(ContractError::InvalidFeeOverridePubkey {});
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_volume_discounts(discounts: &[VolumeDiscount]) -> Result<(), ContractError> {
+    let increasing = discounts
+        .windows(2)
+        .all(|pair| pair[0].min_volume < pair[1].min_volume);
+    if !increasing || discounts.iter().any(|d| d.discount > Decimal::one()) {
+        return Err(ContractError::InvalidVolumeDiscounts {});
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_callback_target(
+    env: &Env,
+    address: &Addr,
+    policy: &CallbackPolicy,
+) -> Result<(), ContractError> {
+    // calling the contract itself would bypass its own checks
+    if *address == env.contract.address {
+        return Err(ContractError::InvalidCallbackTarget {});
+    }
+    if policy.gas_limit == 0 || policy.gas_limit > MAX_CALLBACK_GAS_LIMIT {
+        return Err(ContractError::InvalidCallbackGasLimit {
+            max: MAX_CALLBACK_GAS_LIMIT,
+        });
     }
+    Ok(())
+}
+
+/// Compact representation of a route: `pool_id:token_out_denom` for each hop, comma separated.
+/// e.g. `1:uosmo,2:uion`
+pub fn route_to_string(routes: &[SwapAmountInRoute]) -> String {
+    routes
+        .iter()
+        .map(|hop| format!("{}:{}", hop.pool_id, hop.token_out_denom))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// cw22 requires the interfaces to be stored at instantiate and after every migration
+fn store_supported_interfaces(storage: &mut dyn Storage) -> Result<(), ContractError> {
+    for (interface, version) in SUPPORTED_INTERFACES {
+        SUPPORTED_INTERFACES_MAP.save(storage, interface, &version.to_string())?;
+    }
+    Ok(())
+}
+
+fn coins_to_string(coins: &[Coin]) -> String {
+    coins
+        .iter()
+        .map(Coin::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Creates an event tagged with the current event schema version.
+fn new_event(ty: &str) -> Event {
+    Event::new(ty).add_attribute("event_version", EVENT_VERSION)
+}
+
+impl Default for AffiliateSwap<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cw_serde]
+pub struct ClaimableResponse {
+    pub coins: Vec<Coin>,
+}
+
+#[cw_serde]
+pub struct ClaimOperatorsResponse {
+    pub operators: Vec<Addr>,
+}
+
+#[cw_serde]
+pub struct ConfigResponse {
+    pub owner: Addr,
+    pub max_fee_percentage: Decimal,
+    pub stale_swap_blocks: u64,
+    pub reject_self_referral: bool,
+    pub max_route_length: u32,
+    pub router_pubkey: Option<Binary>,
+    pub fee_collector: Option<Addr>,
+    pub branding: Option<Branding>,
+    pub crank_tip_percentage: Decimal,
+    pub rebate_percentage: Decimal,
+    pub surplus_sharing: Option<SurplusSharing>,
+    pub referral_duration: u64,
+    pub sender_allowlist: bool,
+    pub balance_checks: bool,
+    pub swap_router: Option<Addr>,
+    pub fee_override_pubkey: Option<Binary>,
+    pub settlement_slippage_tolerance: Decimal,
+}
+
+#[cw_serde]
+pub struct ReferralResponse {
+    pub fee_collector: Option<Addr>,
+    pub expires_at: Option<Timestamp>,
+}
+
+#[cw_serde]
+pub struct ChildResponse {
+    pub address: Addr,
+    pub label: String,
+    pub fee_collector: Addr,
+    pub branding: Branding,
+}
+
+#[cw_serde]
+pub struct ChildrenResponse {
+    pub children: Vec<ChildResponse>,
+}
+
+#[cw_serde]
+pub struct PairFeeResponse {
+    pub pair_fee: Option<PairFee>,
+}
+
+#[cw_serde]
+pub struct PairFeeEntry {
+    pub token_in_denom: String,
+    pub token_out_denom: String,
+    pub pair_fee: PairFee,
+}
+
+#[cw_serde]
+pub struct PairFeesResponse {
+    pub pair_fees: Vec<PairFeeEntry>,
+}
+
+#[cw_serde]
+pub struct InputLimitsResponse {
+    pub min_input: Option<Uint128>,
+    pub max_input: Option<Uint128>,
+    pub max_daily_volume: Option<Uint128>,
+}
+
+#[cw_serde]
+pub struct RollingVolumeResponse {
+    pub volume: Uint128,
+    pub max_daily_volume: Option<Uint128>,
+}
+
+#[cw_serde]
+pub struct DeniedPoolsResponse {
+    pub pool_ids: Vec<u64>,
+}
+
+#[cw_serde]
+pub struct SettlementRouteResponse {
+    pub routes: Option<Vec<SwapAmountInRoute>>,
+}
+
+#[cw_serde]
+pub struct CallbackTargetResponse {
+    pub policy: Option<CallbackPolicy>,
+}
+
+#[cw_serde]
+pub struct DenomMetadataResponse {
+    pub metadata: Option<DenomMetadata>,
+}
+
+#[cw_serde]
+pub struct CallbackTargetEntry {
+    pub address: Addr,
+    pub policy: CallbackPolicy,
+}
+
+#[cw_serde]
+pub struct CallbackTargetsResponse {
+    pub targets: Vec<CallbackTargetEntry>,
+}
+
+#[cw_serde]
+pub struct SwapEstimateResponse {
+    pub token_out: Coin,
+    // output per input
+    pub spot_price: Decimal,
+    // in percent, against the spot price
+    pub price_impact: Decimal,
+    pub hops: Vec<HopEstimate>,
+}
+
+#[cw_serde]
+pub struct HopEstimate {
+    pub pool_id: u64,
+    pub token_out: Coin,
+    pub spot_price: Decimal,
+    pub price_impact: Decimal,
+}
+
+#[cw_serde]
+pub struct EffectiveFeeResponse {
+    pub fee_percentage: Decimal,
+    // deducted from the input
+    pub fee: Coin,
+    // share of the fee sent back to the swapper with the output
+    pub rebate: Coin,
+    pub fee_collector: Option<Addr>,
+}
+
+#[cw_serde]
+pub struct SwapSimulationResponse {
+    pub fee_percentage: Decimal,
+    // deducted from the input
+    pub fee: Coin,
+    // share of the fee sent back to the swapper with the output
+    pub rebate: Coin,
+    pub fee_collector: Addr,
+    // input reaching the pools, after the fee
+    pub token_in: Coin,
+    // pool manager estimate of the output
+    pub token_out: Coin,
+    // in percent, against the spot price
+    pub price_impact: Decimal,
+    // min output the swap would be made with
+    pub token_out_min: Coin,
+    // whether the estimated output meets the min output
+    pub min_output_met: bool,
+}
+
+#[cw_serde]
+pub struct FeeHolidaysResponse {
+    pub fee_holidays: Vec<FeeHoliday>,
+}
+
+#[cw_serde]
+pub struct SenderAllowedResponse {
+    pub allowed: bool,
+}
+
+#[cw_serde]
+pub struct AllowedSendersResponse {
+    pub addresses: Vec<Addr>,
+}
+
+#[cw_serde]
+pub struct VolumeResponse {
+    pub volume: Vec<Coin>,
+}
+
+#[cw_serde]
+pub struct DenomSolvency {
+    pub denom: String,
+    pub balance: Uint128,
+    // active swap inputs and escrowed outputs and fees
+    pub liabilities: Uint128,
+    pub surplus: Uint128,
+    pub deficit: Uint128,
+}
+
+#[cw_serde]
+pub struct SolvencyResponse {
+    // false if any denom has a deficit
+    pub solvent: bool,
+    pub denoms: Vec<DenomSolvency>,
+}
+
+#[cw_serde]
+pub struct VolumeDiscountsResponse {
+    pub discounts: Vec<VolumeDiscount>,
+}
+
+#[cw_serde]
+pub struct DailyStats {
+    pub day: u64,
+    pub denom: String,
+    pub volume: Uint128,
+    pub fees: Uint128,
+    pub swap_count: u64,
+}
+
+impl DailyStats {
+    fn new(day: u64, denom: String, stats: Stats) -> Self {
+        DailyStats {
+            day,
+            denom,
+            volume: stats.volume,
+            fees: stats.fees,
+            swap_count: stats.swap_count,
+        }
+    }
+}
+
+#[cw_serde]
+pub struct DailyStatsResponse {
+    pub stats: Vec<DailyStats>,
+}
+
+#[cw_serde]
+pub struct CollectorStats {
+    pub day: u64,
+    pub fee_collector: Addr,
+    pub denom: String,
+    pub volume: Uint128,
+    pub fees: Uint128,
+    pub swap_count: u64,
+}
+
+#[cw_serde]
+pub struct CollectorStatsResponse {
+    pub stats: Vec<CollectorStats>,
 }
 
 #[cw_serde]
@@ -234,9 +4576,28 @@ pub struct MaxFeePercentageResponse {
     pub max_fee_percentage: Decimal,
 }
 
+#[cw_serde]
+pub struct IsPausedResponse {
+    pub paused: bool,
+    pub paused_by: Option<Addr>,
+    pub paused_at: Option<Timestamp>,
+}
+
+#[cw_serde]
+pub struct ContractSupportedInterface {
+    pub supported_interface: String,
+    pub version: String,
+}
+
+#[cw_serde]
+pub struct SupportedInterfacesResponse {
+    pub supported_interfaces: Vec<ContractSupportedInterface>,
+}
+
 // Response for Swap
 #[cw_serde]
 pub struct SwapResponse {
+    pub swap_id: u64,
     pub original_sender: String,
     pub fee: Uint128,
     pub fee_collector: Addr,
@@ -244,4 +4605,8 @@ pub struct SwapResponse {
     pub swap_in_amount: Uint128,
     pub token_out_denom: String,
     pub token_out_amount: Uint128,
+    pub route: Vec<SwapAmountInRoute>,
+    pub client_id: Option<String>,
+    pub swap_in_metadata: Option<DenomMetadata>,
+    pub token_out_metadata: Option<DenomMetadata>,
 }
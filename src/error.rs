@@ -1,34 +1,512 @@
-use cosmwasm_std::{CheckedFromRatioError, StdError};
+use std::fmt;
+
+use cosmwasm_std::{CheckedFromRatioError, Coin, StdError};
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
 pub enum ContractError {
-    #[error("{0}")]
+    #[error("[{code}] {0}", code = self.code())]
     Std(#[from] StdError),
 
-    #[error("{0}")]
+    #[error("[{code}] {0}", code = self.code())]
     Payment(#[from] cw_utils::PaymentError),
 
-    #[error("{0}")]
+    #[error("[{code}] {0}", code = self.code())]
     Math(#[from] CheckedFromRatioError),
 
-    #[error("{0}")]
+    #[error("[{code}] {0}", code = self.code())]
     Overflow(#[from] cosmwasm_std::OverflowError),
 
-    #[error("Invalid max fee percentage. Must be between 0 and {true_max_fee}")]
+    #[error("[{code}] {0}", code = self.code())]
+    ParseReply(#[from] cw_utils::ParseReplyError),
+
+    #[error(
+        "[{code}] Invalid max fee percentage. Must be between 0 and {true_max_fee}",
+        code = self.code()
+    )]
     InvalidMaxFeePercentage { true_max_fee: String },
 
-    #[error("Funds must contain at least one token")]
+    #[error("[{code}] Invalid fee percentage. Must be a multiple of 0.01", code = self.code())]
+    InvalidFeePrecision {},
+
+    #[error(
+        "[{code}] Volume discounts must have increasing min volumes and discounts of at most 1",
+        code = self.code()
+    )]
+    InvalidVolumeDiscounts {},
+
+    #[error("[{code}] The crank tip cannot exceed {max}%", code = self.code())]
+    CrankTipTooHigh { max: String },
+
+    #[error("[{code}] The rebate cannot exceed 100% of the fee", code = self.code())]
+    InvalidRebatePercentage {},
+
+    #[error("[{code}] The settlement slippage tolerance cannot exceed 100%", code = self.code())]
+    InvalidSettlementSlippage {},
+
+    #[error(
+        "[{code}] Surplus shares cannot exceed 100% in total, and a treasury share needs a treasury",
+        code = self.code()
+    )]
+    InvalidSurplusSharing {},
+
+    #[error(
+        "[{code}] A fee collector is required, the sender has no referrer",
+        code = self.code()
+    )]
+    FeeCollectorRequired {},
+
+    #[error("[{code}] The min input cannot exceed the max input", code = self.code())]
+    InvalidInputLimits {},
+
+    #[error(
+        "[{code}] A settlement route must end in another denom than the fee",
+        code = self.code()
+    )]
+    CyclicSettlementRoute {},
+
+    #[error("[{code}] The contract cannot be a callback target", code = self.code())]
+    InvalidCallbackTarget {},
+
+    #[error(
+        "[{code}] The gas limit of a callback must be between 1 and {max}",
+        code = self.code()
+    )]
+    InvalidCallbackGasLimit { max: u64 },
+
+    #[error(
+        "[{code}] A fee holiday must end after it starts, in the future",
+        code = self.code()
+    )]
+    InvalidFeeHoliday {},
+
+    #[error("[{code}] The fee holiday overlaps another one", code = self.code())]
+    FeeHolidayOverlap {},
+
+    #[error("[{code}] State can only be imported before the first swap", code = self.code())]
+    ImportClosed {},
+
+    #[error(
+        "[{code}] The funds sent must match the imported escrowed amounts",
+        code = self.code()
+    )]
+    ImportFundsMismatch {},
+
+    #[error("[{code}] Unauthorized", code = self.code())]
+    Unauthorized {},
+
+    #[error("[{code}] Swaps are paused", code = self.code())]
+    Paused {},
+
+    #[error("[{code}] Swaps are not paused", code = self.code())]
+    NotPaused {},
+
+    #[error(
+        "[{code}] An owner must be provided when migrating from a version without one",
+        code = self.code()
+    )]
+    MissingOwner {},
+
+    #[error("[{code}] Funds must contain at least one token", code = self.code())]
     AtLeastSingleTokenExpected {},
 
-    #[error("There is already an active swap stored for this contract. Re-entry not allowed.")]
+    #[error("[{code}] The fee collector cannot be the contract itself", code = self.code())]
+    FeeCollectorIsContract {},
+
+    #[error("[{code}] The fee collector cannot be the sender", code = self.code())]
+    SelfReferral {},
+
+    #[error("[{code}] Fees of this contract can only be sent to {expected}", code = self.code())]
+    FeeCollectorMismatch { expected: String },
+
+    #[error("[{code}] Child labels must be between 1 and 64 bytes long", code = self.code())]
+    InvalidChildLabel {},
+
+    #[error(
+        "[{code}] The ibc origin does not match the sender of the message",
+        code = self.code()
+    )]
+    InvalidIbcOrigin {},
+
+    #[error(
+        "[{code}] A receiver is required for swaps received through ibc-hooks",
+        code = self.code()
+    )]
+    IbcReceiverRequired {},
+
+    #[error("[{code}] The router public key must be a secp256k1 public key", code = self.code())]
+    InvalidRouterPubkey {},
+
+    #[error("[{code}] A quote signed by the router is required", code = self.code())]
+    QuoteRequired {},
+
+    #[error("[{code}] The quote has expired", code = self.code())]
+    QuoteExpired {},
+
+    #[error(
+        "[{code}] The quote is not signed by the router for this swap",
+        code = self.code()
+    )]
+    InvalidQuoteSignature {},
+
+    #[error(
+        "[{code}] The fee override public key must be a secp256k1 public key",
+        code = self.code()
+    )]
+    InvalidFeeOverridePubkey {},
+
+    #[error("[{code}] Fee overrides are not enabled", code = self.code())]
+    FeeOverrideNotEnabled {},
+
+    #[error("[{code}] The fee override has expired", code = self.code())]
+    FeeOverrideExpired {},
+
+    #[error(
+        "[{code}] A fee override cannot exceed {true_max_fee}%",
+        code = self.code()
+    )]
+    FeeOverrideTooHigh { true_max_fee: String },
+
+    #[error(
+        "[{code}] The fee override is not signed by the override key for this fee collector",
+        code = self.code()
+    )]
+    InvalidFeeOverrideSignature {},
+
+    #[error("[{code}] At least one route must be provided", code = self.code())]
+    EmptyRoutes {},
+
+    #[error(
+        "[{code}] The route swaps {denom} into itself. Set allow_cyclic to do this on purpose",
+        code = self.code()
+    )]
+    CyclicRoute { denom: String },
+
+    #[error(
+        "[{code}] Either token_out_min_amount or slippage_tolerance must be provided",
+        code = self.code()
+    )]
+    MinOutputRequired {},
+
+    #[error("[{code}] The slippage tolerance must be less than 100%", code = self.code())]
+    InvalidSlippageTolerance {},
+
+    #[error(
+        "[{code}] The price impact of the swap is {impact}%, above the max of {max}%",
+        code = self.code()
+    )]
+    PriceImpactTooHigh { impact: String, max: String },
+
+    #[error("[{code}] The swap input is below the min of {min}", code = self.code())]
+    InputTooSmall { min: String },
+
+    #[error(
+        "[{code}] The swap input is above the max of {max}, split it into smaller swaps",
+        code = self.code()
+    )]
+    InputTooLarge { max: String },
+
+    #[error(
+        "[{code}] The swap would exceed the daily volume of the sender, {remaining} remaining",
+        code = self.code()
+    )]
+    DailyVolumeExceeded { remaining: String },
+
+    #[error("[{code}] The sender is not allowed to swap", code = self.code())]
+    SenderNotAllowed {},
+
+    #[error("[{code}] Only swap messages can be simulated", code = self.code())]
+    NotASwap {},
+
+    #[error("[{code}] Pool {pool_id} is denied and cannot be routed through", code = self.code())]
+    PoolDenied { pool_id: u64 },
+
+    #[error("[{code}] Routes cannot have more than {max} hops", code = self.code())]
+    RouteTooLong { max: u32 },
+
+    #[error("[{code}] The max route length must be at least 1", code = self.code())]
+    InvalidMaxRouteLength {},
+
+    #[error("[{code}] There is already an active swap stored with this id.", code = self.code())]
     ActiveSwapExists {},
 
-    #[error("Swap failed: {reason}")]
-    FailedSwap { reason: String },
+    #[error("[{code}] There is nothing to claim", code = self.code())]
+    NothingToClaim {},
+
+    #[error("[{code}] The sender is not an operator of the claimant", code = self.code())]
+    NotClaimOperator {},
+
+    #[error(
+        "[{code}] A claim by an operator is sent to its claimant, without a recipient",
+        code = self.code()
+    )]
+    OperatorClaimRecipient {},
+
+    #[error("[{code}] There is no stale swap to clear", code = self.code())]
+    NoStaleSwap {},
+
+    #[error("[{code}] Unknown reply id {id}", code = self.code())]
+    UnknownReplyId { id: u64 },
+
+    #[error(
+        "[{code}] The swap backend needs a swap router, set it with update_config",
+        code = self.code()
+    )]
+    SwapRouterRequired {},
+
+    #[error("[{code}] The swap reply does not report the output", code = self.code())]
+    SwapOutputMissing {},
+
+    #[error(
+        "[{code}] This deployment is built without the `{feature}` feature",
+        code = self.code()
+    )]
+    FeatureDisabled { feature: String },
+
+    #[error(
+        "[{code}] The contract holds {actual} after the swap instead of {expected}",
+        code = self.code()
+    )]
+    BalanceMismatch { expected: String, actual: String },
+
+    #[error(
+        "[{code}] {address} is not a callback target allowed to receive the swap output",
+        code = self.code()
+    )]
+    CallbackNotAllowed { address: String },
+
+    #[error(
+        "[{code}] The vault takes deposits in {base_token}, not in the swap output",
+        code = self.code()
+    )]
+    VaultDenomMismatch { base_token: String },
+
+    #[error(
+        "[{code}] A client id must be between 1 and {max} bytes long",
+        code = self.code()
+    )]
+    InvalidClientId { max: usize },
+
+    #[error(
+        "[{code}] The client id {client_id} was already used by a recent swap",
+        code = self.code()
+    )]
+    DuplicateClientId { client_id: String },
+
+    #[error(
+        "[{code}] A channel must be between 1 and {max} bytes long",
+        code = self.code()
+    )]
+    InvalidChannel { max: usize },
+
+    #[error(
+        "[{code}] A denom symbol must be between 1 and {max_symbol_length} bytes long, with at most {max_decimals} decimals",
+        code = self.code()
+    )]
+    InvalidDenomMetadata {
+        max_symbol_length: usize,
+        max_decimals: u32,
+    },
+
+    #[error("[{code}] Swap failed: {reason} ({context})", code = self.code())]
+    FailedSwap {
+        reason: String,
+        context: Box<SwapContext>,
+    },
+
+    #[error(
+        "[{code}] Swap failed, the output is below the minimum: {reason} ({context})",
+        code = self.code()
+    )]
+    SlippageExceeded {
+        reason: String,
+        context: Box<SwapContext>,
+    },
 
     #[error(
-        "Unexpected error. This should never happen as validation should have prevented this."
+        "[{code}] Swap failed, a pool in the route does not exist: {reason} ({context})",
+        code = self.code()
+    )]
+    PoolNotFound {
+        reason: String,
+        context: Box<SwapContext>,
+    },
+
+    #[error(
+        "[{code}] Swap failed, insufficient liquidity: {reason} ({context})",
+        code = self.code()
+    )]
+    InsufficientLiquidity {
+        reason: String,
+        context: Box<SwapContext>,
+    },
+
+    #[error(
+        "[{code}] Unexpected error. This should never happen as validation should have prevented this.",
+        code = self.code()
     )]
     Unexpected {},
 }
+
+impl ContractError {
+    /// Stable identifier of the error, also prefixed to its message in brackets, so clients
+    /// can branch on errors without matching messages. Codes are never changed nor reused.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ContractError::Std(_) => "std",
+            ContractError::Payment(_) => "payment",
+            ContractError::Math(_) => "math",
+            ContractError::Overflow(_) => "overflow",
+            ContractError::ParseReply(_) => "parse_reply",
+            ContractError::InvalidMaxFeePercentage { .. } => "invalid_max_fee_percentage",
+            ContractError::InvalidFeePrecision { .. } => "invalid_fee_precision",
+            ContractError::InvalidVolumeDiscounts { .. } => "invalid_volume_discounts",
+            ContractError::CrankTipTooHigh { .. } => "crank_tip_too_high",
+            ContractError::InvalidRebatePercentage { .. } => "invalid_rebate_percentage",
+            ContractError::InvalidSettlementSlippage { .. } => "invalid_settlement_slippage",
+            ContractError::InvalidSurplusSharing { .. } => "invalid_surplus_sharing",
+            ContractError::FeeCollectorRequired { .. } => "fee_collector_required",
+            ContractError::InvalidInputLimits { .. } => "invalid_input_limits",
+            ContractError::CyclicSettlementRoute { .. } => "cyclic_settlement_route",
+            ContractError::InvalidCallbackTarget { .. } => "invalid_callback_target",
+            ContractError::InvalidCallbackGasLimit { .. } => "invalid_callback_gas_limit",
+            ContractError::InvalidFeeHoliday { .. } => "invalid_fee_holiday",
+            ContractError::FeeHolidayOverlap { .. } => "fee_holiday_overlap",
+            ContractError::ImportClosed { .. } => "import_closed",
+            ContractError::ImportFundsMismatch { .. } => "import_funds_mismatch",
+            ContractError::Unauthorized { .. } => "unauthorized",
+            ContractError::Paused { .. } => "paused",
+            ContractError::NotPaused { .. } => "not_paused",
+            ContractError::MissingOwner { .. } => "missing_owner",
+            ContractError::AtLeastSingleTokenExpected { .. } => "at_least_single_token_expected",
+            ContractError::FeeCollectorIsContract { .. } => "fee_collector_is_contract",
+            ContractError::SelfReferral { .. } => "self_referral",
+            ContractError::FeeCollectorMismatch { .. } => "fee_collector_mismatch",
+            ContractError::InvalidChildLabel { .. } => "invalid_child_label",
+            ContractError::InvalidIbcOrigin { .. } => "invalid_ibc_origin",
+            ContractError::IbcReceiverRequired { .. } => "ibc_receiver_required",
+            ContractError::InvalidRouterPubkey { .. } => "invalid_router_pubkey",
+            ContractError::QuoteRequired { .. } => "quote_required",
+            ContractError::QuoteExpired { .. } => "quote_expired",
+            ContractError::InvalidQuoteSignature { .. } => "invalid_quote_signature",
+            ContractError::InvalidFeeOverridePubkey { .. } => "invalid_fee_override_pubkey",
+            ContractError::FeeOverrideNotEnabled { .. } => "fee_override_not_enabled",
+            ContractError::FeeOverrideExpired { .. } => "fee_override_expired",
+            ContractError::FeeOverrideTooHigh { .. } => "fee_override_too_high",
+            ContractError::InvalidFeeOverrideSignature { .. } => "invalid_fee_override_signature",
+            ContractError::EmptyRoutes { .. } => "empty_routes",
+            ContractError::CyclicRoute { .. } => "cyclic_route",
+            ContractError::MinOutputRequired { .. } => "min_output_required",
+            ContractError::InvalidSlippageTolerance { .. } => "invalid_slippage_tolerance",
+            ContractError::PriceImpactTooHigh { .. } => "price_impact_too_high",
+            ContractError::InputTooSmall { .. } => "input_too_small",
+            ContractError::InputTooLarge { .. } => "input_too_large",
+            ContractError::DailyVolumeExceeded { .. } => "daily_volume_exceeded",
+            ContractError::SenderNotAllowed {} => "sender_not_allowed",
+            ContractError::NotASwap {} => "not_a_swap",
+            ContractError::PoolDenied { .. } => "pool_denied",
+            ContractError::RouteTooLong { .. } => "route_too_long",
+            ContractError::InvalidMaxRouteLength { .. } => "invalid_max_route_length",
+            ContractError::ActiveSwapExists { .. } => "active_swap_exists",
+            ContractError::NothingToClaim { .. } => "nothing_to_claim",
+            ContractError::NotClaimOperator {} => "not_claim_operator",
+            ContractError::OperatorClaimRecipient {} => "operator_claim_recipient",
+            ContractError::NoStaleSwap { .. } => "no_stale_swap",
+            ContractError::UnknownReplyId { .. } => "unknown_reply_id",
+            ContractError::SwapRouterRequired {} => "swap_router_required",
+            ContractError::SwapOutputMissing {} => "swap_output_missing",
+            ContractError::FeatureDisabled { .. } => "feature_disabled",
+            ContractError::BalanceMismatch { .. } => "balance_mismatch",
+            ContractError::CallbackNotAllowed { .. } => "callback_not_allowed",
+            ContractError::VaultDenomMismatch { .. } => "vault_denom_mismatch",
+            ContractError::InvalidClientId { .. } => "invalid_client_id",
+            ContractError::DuplicateClientId { .. } => "duplicate_client_id",
+            ContractError::InvalidChannel { .. } => "invalid_channel",
+            ContractError::InvalidDenomMetadata { .. } => "invalid_denom_metadata",
+            ContractError::FailedSwap { .. } => "failed_swap",
+            ContractError::SlippageExceeded { .. } => "slippage_exceeded",
+            ContractError::PoolNotFound { .. } => "pool_not_found",
+            ContractError::InsufficientLiquidity { .. } => "insufficient_liquidity",
+            ContractError::Unexpected { .. } => "unexpected",
+        }
+    }
+}
+
+/// What the user attempted, included in failed swap errors so the swap can be reconstructed
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwapContext {
+    // compact route, see `route_to_string`
+    pub route: String,
+    pub token_in: Coin,
+    pub token_out_min: Coin,
+}
+
+impl fmt::Display for SwapContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "route: {}, token_in: {}, token_out_min: {}",
+            self.route, self.token_in, self.token_out_min
+        )
+    }
+}
+
+/// Category of a failed swap, derived from the error returned by the chain
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwapFailure {
+    Slippage,
+    PoolNotFound,
+    InsufficientLiquidity,
+    Unknown,
+}
+
+impl SwapFailure {
+    /// Matches the messages of the gamm and poolmanager module errors. Depending on the chain
+    /// version the reason may be redacted to its code only, in which case it is `Unknown`.
+    pub fn classify(reason: &str) -> Self {
+        let reason = reason.to_lowercase();
+        let matches = |fragments: &[&str]| fragments.iter().any(|f| reason.contains(f));
+        if matches(&[
+            "lesser than min amount",
+            "less than min amount",
+            // astroport router and pairs
+            "minimum receive amount",
+            "max spread",
+        ]) {
+            SwapFailure::Slippage
+        } else if matches(&["pool not found", "does not exist", "failed to find route"]) {
+            SwapFailure::PoolNotFound
+        } else if matches(&[
+            "insufficient liquidity",
+            "not enough liquidity",
+            // concentrated liquidity pools
+            "no more ticks initialized",
+        ]) {
+            SwapFailure::InsufficientLiquidity
+        } else {
+            SwapFailure::Unknown
+        }
+    }
+
+    /// Value of the `failure` event attribute
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SwapFailure::Slippage => "slippage",
+            SwapFailure::PoolNotFound => "pool_not_found",
+            SwapFailure::InsufficientLiquidity => "insufficient_liquidity",
+            SwapFailure::Unknown => "unknown",
+        }
+    }
+
+    pub fn into_error(self, reason: String, context: SwapContext) -> ContractError {
+        let context = Box::new(context);
+        match self {
+            SwapFailure::Slippage => ContractError::SlippageExceeded { reason, context },
+            SwapFailure::PoolNotFound => ContractError::PoolNotFound { reason, context },
+            SwapFailure::InsufficientLiquidity => {
+                ContractError::InsufficientLiquidity { reason, context }
+            }
+            SwapFailure::Unknown => ContractError::FailedSwap { reason, context },
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Fee overrides signed by the operator.
+//!
+//! Deals negotiated with a fee collector may charge a fee above the max fee, without raising
+//! the max fee of everyone. When the contract is configured with a fee override public key, a
+//! swap may carry an override signed by that key for its fee collector, and is charged the fee
+//! of the override instead, up to the hard cap of the max fee. The key signs the sha256 hash of
+//! the JSON encoded [`FeeOverridePayload`] with secp256k1, as route quotes are signed.
+
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{to_vec, Addr, Api, Binary, Decimal, Env, StdResult, Timestamp};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+
+use crate::contract::TRUE_MAX_FEE;
+use crate::error::ContractError;
+
+/// Fee override attached to a swap
+#[cw_serde]
+pub struct FeeOverride {
+    // fee charged instead of the requested one, whatever the max fee
+    pub fee_percentage: Decimal,
+    // the override is rejected from this block time on
+    pub expires_at: Timestamp,
+    // 64 bytes compact secp256k1 signature of the override hash
+    pub signature: Binary,
+}
+
+/// What the override key signs. The chain id and contract address bind the override to one
+/// deployment, the fee collector to one deal.
+#[derive(Serialize)]
+pub struct FeeOverridePayload<'a> {
+    pub chain_id: &'a str,
+    pub contract: &'a str,
+    pub fee_collector: &'a str,
+    pub fee_percentage: Decimal,
+    pub expires_at: Timestamp,
+}
+
+impl FeeOverridePayload<'_> {
+    /// sha256 of the JSON encoded payload, the message signed by the override key
+    pub fn hash(&self) -> StdResult<[u8; 32]> {
+        Ok(Sha256::digest(to_vec(self)?).into())
+    }
+}
+
+/// Checks that `fee_override` has not expired, stays below the hard cap of the max fee and is
+/// signed by `pubkey` for `fee_collector`. Returns the fee percentage of the override
+pub fn verify_fee_override(
+    api: &dyn Api,
+    env: &Env,
+    pubkey: &Binary,
+    fee_override: &FeeOverride,
+    fee_collector: &Addr,
+) -> Result<Decimal, ContractError> {
+    if env.block.time >= fee_override.expires_at {
+        return Err(ContractError::FeeOverrideExpired {});
+    }
+    if fee_override.fee_percentage > Decimal::from_str(TRUE_MAX_FEE)? {
+        return Err(ContractError::FeeOverrideTooHigh {
+            true_max_fee: TRUE_MAX_FEE.to_string(),
+        });
+    }
+
+    let hash = FeeOverridePayload {
+        chain_id: &env.block.chain_id,
+        contract: env.contract.address.as_str(),
+        fee_collector: fee_collector.as_str(),
+        fee_percentage: fee_override.fee_percentage,
+        expires_at: fee_override.expires_at,
+    }
+    .hash()?;
+    // a malformed signature is as invalid as a wrong one
+    let valid = api
+        .secp256k1_verify(&hash, &fee_override.signature, pubkey)
+        .unwrap_or(false);
+    if !valid {
+        return Err(ContractError::InvalidFeeOverrideSignature {});
+    }
+    Ok(fee_override.fee_percentage)
+}
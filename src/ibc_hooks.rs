@@ -0,0 +1,70 @@
+//! Support for swaps received through ibc-hooks.
+//!
+//! ibc-hooks executes the `wasm` object of an ICS-20 transfer memo on the receiving chain, on
+//! behalf of an intermediary address derived from the channel and the sender on the origin
+//! chain. Nobody controls that address, so swaps received this way must send their output (and
+//! any refund) to an explicit `receiver`. The derivation is one-way, but an origin provided in
+//! the message can be checked against it.
+
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{to_vec, Addr, Api, CanonicalAddr, StdResult};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::ContractError;
+use crate::interface::affiliate_swap_interface::ExecMsg;
+
+// Prefix ibc-hooks uses to derive intermediary sender addresses
+pub const SENDER_PREFIX: &str = "ibc-wasm-hook-intermediary";
+
+/// Sender of the ICS-20 transfer on the origin chain
+#[cw_serde]
+pub struct IbcOrigin {
+    // channel on this chain the transfer was received on
+    pub channel: String,
+    pub sender: String,
+}
+
+/// Address ibc-hooks executes contracts from for transfers received on `channel` from
+/// `original_sender`: `sha256(sha256(SENDER_PREFIX) || "<channel>/<original_sender>")`.
+pub fn intermediate_sender(channel: &str, original_sender: &str) -> CanonicalAddr {
+    let typ_hash = Sha256::digest(SENDER_PREFIX.as_bytes());
+    let hash = Sha256::new()
+        .chain_update(typ_hash)
+        .chain_update(format!("{channel}/{original_sender}"))
+        .finalize();
+    CanonicalAddr::from(hash.as_slice())
+}
+
+/// Checks that `sender` is the intermediary address of `origin`
+pub fn verify_origin(
+    api: &dyn Api,
+    sender: &Addr,
+    origin: &IbcOrigin,
+) -> Result<(), ContractError> {
+    if api.addr_canonicalize(sender.as_str())?
+        != intermediate_sender(&origin.channel, &origin.sender)
+    {
+        return Err(ContractError::InvalidIbcOrigin {});
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Memo<'a> {
+    wasm: WasmHook<'a>,
+}
+
+#[derive(Serialize)]
+struct WasmHook<'a> {
+    contract: &'a str,
+    msg: &'a ExecMsg,
+}
+
+/// ICS-20 memo executing `msg` on `contract` when the transfer is received
+pub fn wasm_memo(contract: &str, msg: &ExecMsg) -> StdResult<String> {
+    let memo = to_vec(&Memo {
+        wasm: WasmHook { contract, msg },
+    })?;
+    Ok(String::from_utf8(memo)?)
+}
@@ -0,0 +1,117 @@
+//! Messages integrators send to the contract, as a sylvia interface.
+//!
+//! Contracts depending on this crate with the `library` feature can build typed messages from
+//! [`affiliate_swap_interface::ExecMsg`] and [`affiliate_swap_interface::QueryMsg`] instead of writing the JSON by hand. Owner-only
+//! messages (configuration, pausing, the factory) are not part of the interface and stay on
+//! the contract.
+
+// swap takes many optional arguments, and so does the ExecMsg constructor sylvia generates
+#![allow(clippy::too_many_arguments)]
+
+use cosmwasm_std::{Coin, Decimal, Deps, DepsMut, Env, MessageInfo, Response, StdError, Uint128};
+use osmosis_std::types::osmosis::poolmanager::v1beta1::SwapAmountInRoute;
+use sylvia::interface;
+
+use crate::contract::{
+    AfterSwap, ClaimOperatorsResponse, ClaimableResponse, ConfigResponse, IsPausedResponse,
+    MaxFeePercentageResponse, OnFailure, ResponseData, SupportedInterfacesResponse,
+};
+use crate::fee_override::FeeOverride;
+use crate::ibc_hooks::IbcOrigin;
+use crate::quote::RouteQuote;
+
+#[interface(module = affiliate_swap_interface)]
+pub trait AffiliateSwapInterface {
+    type Error: From<StdError>;
+
+    /// Executes a swap and charges the affiliate fee.
+    /// The affiliate fee is deducted from the swap amount and sent to the affiliate address once
+    /// the swap has succeeded.
+    #[msg(exec)]
+    fn swap(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        routes: Vec<SwapAmountInRoute>,
+        token_out_min_amount: Option<Coin>,
+        fee_percentage: Option<Decimal>,
+        // Named `affiliate_address` in earlier versions. The old name is still accepted but
+        // deprecated, and will be removed in the next breaking release.
+        #[serde(alias = "affiliate_address")] fee_collector: Option<String>,
+        on_failure: Option<OnFailure>,
+        token_in_denom: Option<String>,
+        allow_cyclic: Option<bool>,
+        receiver: Option<String>,
+        ibc_origin: Option<IbcOrigin>,
+        quote: Option<RouteQuote>,
+        expected_out: Option<Uint128>,
+        slippage_tolerance: Option<Decimal>,
+        max_price_impact: Option<Decimal>,
+        response_data: Option<ResponseData>,
+        with_data: Option<bool>,
+        after: Option<AfterSwap>,
+        client_id: Option<String>,
+        channel: Option<String>,
+        fee_override: Option<FeeOverride>,
+    ) -> Result<Response, Self::Error>;
+
+    /// Removes active swaps whose reply never arrived, once they are older than the configured
+    /// number of blocks, and refunds their full input to the original sender.
+    /// Replies are always processed in the transaction of the swap, so this only happens if a
+    /// reply was missed, e.g. after a migration changing reply handling. Anyone can call it.
+    #[msg(exec)]
+    fn clear_stale_swap(&self, ctx: (DepsMut, Env, MessageInfo)) -> Result<Response, Self::Error>;
+
+    /// Sends the swap outputs and fees escrowed for the sender, after their transfer failed,
+    /// to `recipient` (or the sender if not provided).
+    /// An operator of `claimant` (see `set_claim_operator`) claims for it instead, and the
+    /// balance is always sent to `claimant`.
+    #[msg(exec)]
+    fn claim(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        recipient: Option<String>,
+        claimant: Option<String>,
+    ) -> Result<Response, Self::Error>;
+
+    /// Allows `operator` to claim the balance escrowed for the sender on its behalf, e.g. from
+    /// an automation key, or revokes it.
+    #[msg(exec)]
+    fn set_claim_operator(
+        &self,
+        ctx: (DepsMut, Env, MessageInfo),
+        operator: String,
+        allowed: bool,
+    ) -> Result<Response, Self::Error>;
+
+    #[msg(query)]
+    fn get_max_fee_percentage(
+        &self,
+        ctx: (Deps, Env),
+    ) -> Result<MaxFeePercentageResponse, Self::Error>;
+
+    #[msg(query)]
+    fn get_config(&self, ctx: (Deps, Env)) -> Result<ConfigResponse, Self::Error>;
+
+    #[msg(query)]
+    fn get_claimable(
+        &self,
+        ctx: (Deps, Env),
+        address: String,
+    ) -> Result<ClaimableResponse, Self::Error>;
+
+    #[msg(query)]
+    fn get_claim_operators(
+        &self,
+        ctx: (Deps, Env),
+        claimant: String,
+    ) -> Result<ClaimOperatorsResponse, Self::Error>;
+
+    #[msg(query)]
+    fn is_paused(&self, ctx: (Deps, Env)) -> Result<IsPausedResponse, Self::Error>;
+
+    #[msg(query)]
+    fn get_supported_interfaces(
+        &self,
+        ctx: (Deps, Env),
+    ) -> Result<SupportedInterfacesResponse, Self::Error>;
+}
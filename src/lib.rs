@@ -1,16 +1,50 @@
 pub mod contract;
 
+#[cfg(feature = "astroport")]
+pub mod astroport;
+pub mod backend;
 mod error;
-pub use crate::error::ContractError;
+pub mod fee_override;
+pub mod ibc_hooks;
+pub mod interface;
+mod migrations;
+pub mod osmosis;
+pub mod quote;
+pub mod state_export;
+pub mod vault;
+
+// Messages, responses and errors, for contracts depending on this crate with the `library`
+// feature
+pub use crate::contract::{
+    AfterSwap, AllowedSendersResponse, Branding, CallbackPolicy, CallbackTargetEntry,
+    CallbackTargetResponse, CallbackTargetsResponse, ChildResponse, ChildrenResponse,
+    ClaimableResponse, ConfigResponse, ContractExecMsg, ContractQueryMsg,
+    ContractSupportedInterface, DailyStats, DailyStatsResponse, DeniedPoolsResponse, DenomMetadata,
+    DenomMetadataResponse, DenomSolvency, EffectiveFeeResponse, ExecMsg, FeeHoliday,
+    FeeHolidaysResponse, HopEstimate, InputLimits, InputLimitsResponse, InstantiateMsg,
+    IsPausedResponse, MaxFeePercentageResponse, MigrateMsg, OnFailure, PairFee, PairFeeEntry,
+    PairFeeResponse, PairFeesResponse, QueryMsg, Referral, ReferralResponse, ResponseData,
+    RollingVolumeResponse, SenderAllowedResponse, SettlementRouteResponse, SolvencyResponse,
+    SudoMsg, SupportedInterfacesResponse, SurplusSharing, SwapEstimateResponse, SwapResponse,
+    SwapSimulationResponse, VolumeDiscount, VolumeDiscountsResponse, VolumeResponse,
+};
+pub use crate::error::{ContractError, SwapContext, SwapFailure};
+pub use crate::fee_override::FeeOverride;
+pub use crate::ibc_hooks::IbcOrigin;
+pub use crate::interface::affiliate_swap_interface::{
+    AffiliateSwapInterfaceExecMsg, AffiliateSwapInterfaceQueryMsg,
+};
+pub use crate::quote::RouteQuote;
+pub use crate::state_export::{ExportStateResponse, StateEntry, StateSection};
 
 #[cfg(not(feature = "library"))]
 mod entry_points {
-    use crate::contract::{AffiliateSwap, ContractExecMsg, ContractQueryMsg, InstantiateMsg};
+    use crate::contract::{
+        AffiliateSwap, ContractExecMsg, ContractQueryMsg, InstantiateMsg, MigrateMsg, SudoMsg,
+    };
     use crate::error::ContractError;
     use cosmwasm_std::{entry_point, Binary, Deps, DepsMut, Env, MessageInfo, Reply, Response};
 
-    const CONTRACT: AffiliateSwap = AffiliateSwap::new();
-
     #[entry_point]
     pub fn instantiate(
         deps: DepsMut,
@@ -18,7 +52,7 @@ mod entry_points {
         info: MessageInfo,
         msg: InstantiateMsg,
     ) -> Result<Response, ContractError> {
-        msg.dispatch(&CONTRACT, (deps, env, info))
+        msg.dispatch(&AffiliateSwap::new(), (deps, env, info))
     }
 
     #[entry_point]
@@ -28,17 +62,27 @@ mod entry_points {
         info: MessageInfo,
         msg: ContractExecMsg,
     ) -> Result<Response, ContractError> {
-        msg.dispatch(&CONTRACT, (deps, env, info))
+        msg.dispatch(&AffiliateSwap::new(), (deps, env, info))
     }
 
     #[entry_point]
     pub fn query(deps: Deps, env: Env, msg: ContractQueryMsg) -> Result<Binary, ContractError> {
-        msg.dispatch(&CONTRACT, (deps, env))
+        msg.dispatch(&AffiliateSwap::new(), (deps, env))
+    }
+
+    #[entry_point]
+    pub fn migrate(deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+        msg.dispatch(&AffiliateSwap::new(), (deps, env))
     }
 
     #[entry_point]
     pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
-        CONTRACT.reply((deps, env), msg)
+        AffiliateSwap::new().reply((deps, env), msg)
+    }
+
+    #[entry_point]
+    pub fn sudo(deps: DepsMut, env: Env, msg: SudoMsg) -> Result<Response, ContractError> {
+        AffiliateSwap::new().sudo((deps, env), msg)
     }
 }
 
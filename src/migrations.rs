@@ -0,0 +1,60 @@
+use cosmwasm_std::{Addr, Decimal, Storage};
+use cw_storage_plus::Item;
+
+use crate::contract::{
+    percentage_to_bps, Config, DEFAULT_MAX_ROUTE_LENGTH, DEFAULT_SETTLEMENT_SLIPPAGE_BPS,
+    DEFAULT_STALE_SWAP_BLOCKS,
+};
+use crate::error::ContractError;
+
+// Versions before the Config struct stored the max fee on its own, without an owner
+pub(crate) const LEGACY_MAX_FEE: Item<Decimal> = Item::new("max_fee");
+
+// The single in-flight swap of 0.1.0. Its reply removes it, so it is only ever left behind by a
+// swap whose reply never ran, and this version cannot complete it.
+pub(crate) const LEGACY_ACTIVE_SWAP_KEY: &str = "active_swap";
+
+/// Moves a legacy max fee into the Config struct, in basis points, and applies the new owner,
+/// if any.
+pub fn migrate_config(
+    storage: &mut dyn Storage,
+    config: &Item<Config>,
+    owner: Option<Addr>,
+) -> Result<(), ContractError> {
+    let new_config = if let Some(max_fee_percentage) = LEGACY_MAX_FEE.may_load(storage)? {
+        let config = Config {
+            owner: owner.ok_or(ContractError::MissingOwner {})?,
+            max_fee_bps: percentage_to_bps(max_fee_percentage)?,
+            stale_swap_blocks: DEFAULT_STALE_SWAP_BLOCKS,
+            reject_self_referral: false,
+            max_route_length: DEFAULT_MAX_ROUTE_LENGTH,
+            router_pubkey: None,
+            fee_collector: None,
+            branding: None,
+            crank_tip_bps: 0,
+            rebate_bps: 0,
+            surplus_sharing: None,
+            referral_duration: 0,
+            sender_allowlist: false,
+            balance_checks: false,
+            swap_router: None,
+            fee_override_pubkey: None,
+            settlement_slippage_bps: DEFAULT_SETTLEMENT_SLIPPAGE_BPS,
+        };
+        LEGACY_MAX_FEE.remove(storage);
+        config
+    } else {
+        let config = config.load(storage)?;
+        Config {
+            owner: owner.unwrap_or(config.owner),
+            ..config
+        }
+    };
+    config.save(storage, &new_config)?;
+    Ok(())
+}
+
+/// Removes the in-flight swap of 0.1.0, superseded by `AffiliateSwap::active_swaps`
+pub fn remove_legacy_active_swap(storage: &mut dyn Storage) {
+    storage.remove(LEGACY_ACTIVE_SWAP_KEY.as_bytes());
+}
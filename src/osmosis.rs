@@ -0,0 +1,93 @@
+//! Swap backend of the Osmosis pool manager.
+//!
+//! Everything that depends on the versions of the Osmosis modules is kept here: the type URL of
+//! the swap message, the encoding of its response and the paths of the estimate and spot price
+//! queries. Forks and testnets running other module versions only have to adapt this module.
+
+use std::str::FromStr;
+
+use cosmwasm_std::{
+    Addr, Binary, Coin, CosmosMsg, Decimal, QuerierWrapper, Storage, SubMsgResult, Uint128,
+};
+use osmosis_std::types::osmosis::gamm::v2::GammQuerier;
+use osmosis_std::types::osmosis::poolmanager::v1beta1::{
+    MsgSwapExactAmountIn, MsgSwapExactAmountInResponse, PoolmanagerQuerier, SwapAmountInRoute,
+};
+
+use crate::backend::SwapBackend;
+use crate::error::ContractError;
+
+/// The response of `MsgSwapExactAmountIn` for `token_out_amount`, as the pool manager encodes it
+pub fn swap_response_data(token_out_amount: Uint128) -> Binary {
+    MsgSwapExactAmountInResponse {
+        token_out_amount: token_out_amount.to_string(),
+    }
+    .into()
+}
+
+/// Swaps through `MsgSwapExactAmountIn`, the routes hopping through pool ids
+pub struct OsmosisBackend;
+
+impl SwapBackend for OsmosisBackend {
+    fn load(_storage: &dyn Storage) -> Result<Self, ContractError> {
+        Ok(OsmosisBackend)
+    }
+
+    fn swap_msg(
+        &self,
+        sender: &Addr,
+        routes: Vec<SwapAmountInRoute>,
+        token_in: &Coin,
+        token_out_min_amount: Uint128,
+    ) -> Result<CosmosMsg, ContractError> {
+        Ok(MsgSwapExactAmountIn {
+            sender: sender.to_string(),
+            routes,
+            token_in: Some(token_in.clone().into()),
+            token_out_min_amount: token_out_min_amount.to_string(),
+        }
+        .into())
+    }
+
+    fn swap_output(&self, result: SubMsgResult) -> Result<Uint128, ContractError> {
+        let res: MsgSwapExactAmountInResponse = result.try_into()?;
+        Ok(Uint128::from_str(&res.token_out_amount)?)
+    }
+
+    fn estimate_swap(
+        &self,
+        querier: &QuerierWrapper,
+        sender: &Addr,
+        routes: &[SwapAmountInRoute],
+        token_in: &Coin,
+    ) -> Result<Uint128, ContractError> {
+        let estimate = PoolmanagerQuerier::new(querier)
+            .estimate_swap_exact_amount_in(
+                sender.to_string(),
+                routes.first().ok_or(ContractError::EmptyRoutes {})?.pool_id,
+                token_in.to_string(),
+                routes.to_vec(),
+            )?
+            .token_out_amount;
+        Ok(Uint128::from_str(&estimate)?)
+    }
+
+    /// The product of the spot prices of the pools of the route
+    fn route_spot_price(
+        &self,
+        querier: &QuerierWrapper,
+        routes: &[SwapAmountInRoute],
+        denom_in: &str,
+    ) -> Result<Decimal, ContractError> {
+        let mut price = Decimal::one();
+        let mut denom_in = denom_in.to_string();
+        for route in routes {
+            let spot_price = GammQuerier::new(querier)
+                .spot_price(route.pool_id, denom_in, route.token_out_denom.clone())?
+                .spot_price;
+            price = price.checked_mul(Decimal::from_str(&spot_price)?)?;
+            denom_in = route.token_out_denom.clone();
+        }
+        Ok(price)
+    }
+}
@@ -0,0 +1,91 @@
+//! Route quotes signed by an off-chain router.
+//!
+//! When the contract is configured with a router public key, every swap must carry a quote
+//! signed by that key, so only routes and minimum outputs produced by the operator's quoting
+//! service are executed. The router signs the sha256 hash of the JSON encoded [`QuotePayload`]
+//! with secp256k1, as in cosmos transaction signatures.
+
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{to_vec, Api, Binary, Coin, Env, StdResult, Timestamp};
+use osmosis_std::types::osmosis::poolmanager::v1beta1::SwapAmountInRoute;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::ContractError;
+
+/// Quote attached to a swap
+#[cw_serde]
+pub struct RouteQuote {
+    // the quote is rejected from this block time on
+    pub expires_at: Timestamp,
+    // 64 bytes compact secp256k1 signature of the quote hash
+    pub signature: Binary,
+}
+
+/// What the router signs. The chain id and contract address bind the quote to one deployment.
+#[derive(Serialize)]
+pub struct QuotePayload<'a> {
+    pub chain_id: &'a str,
+    pub contract: &'a str,
+    pub routes: &'a [SwapAmountInRoute],
+    pub token_in: &'a Coin,
+    pub token_out_min_amount: &'a Coin,
+    pub expires_at: Timestamp,
+}
+
+impl QuotePayload<'_> {
+    /// sha256 of the JSON encoded payload, the message signed by the router
+    pub fn hash(&self) -> StdResult<[u8; 32]> {
+        Ok(Sha256::digest(to_vec(self)?).into())
+    }
+}
+
+/// Whether `pubkey` is a secp256k1 public key, compressed or not
+pub fn is_secp256k1_pubkey(pubkey: &Binary) -> bool {
+    match pubkey.as_slice() {
+        [0x02 | 0x03, rest @ ..] => rest.len() == 32,
+        [0x04, rest @ ..] => rest.len() == 64,
+        _ => false,
+    }
+}
+
+/// Checks that `router_pubkey` is a secp256k1 public key, compressed or not
+pub fn validate_router_pubkey(router_pubkey: &Binary) -> Result<(), ContractError> {
+    if !is_secp256k1_pubkey(router_pubkey) {
+        return Err(ContractError::InvalidRouterPubkey {});
+    }
+    Ok(())
+}
+
+/// Checks that `quote` has not expired and is signed by `router_pubkey` for this swap
+pub fn verify_quote(
+    api: &dyn Api,
+    env: &Env,
+    router_pubkey: &Binary,
+    quote: &RouteQuote,
+    routes: &[SwapAmountInRoute],
+    token_in: &Coin,
+    token_out_min_amount: &Coin,
+) -> Result<(), ContractError> {
+    if env.block.time >= quote.expires_at {
+        return Err(ContractError::QuoteExpired {});
+    }
+
+    let hash = QuotePayload {
+        chain_id: &env.block.chain_id,
+        contract: env.contract.address.as_str(),
+        routes,
+        token_in,
+        token_out_min_amount,
+        expires_at: quote.expires_at,
+    }
+    .hash()?;
+    // a malformed signature is as invalid as a wrong one
+    let valid = api
+        .secp256k1_verify(&hash, &quote.signature, router_pubkey)
+        .unwrap_or(false);
+    if !valid {
+        return Err(ContractError::InvalidQuoteSignature {});
+    }
+    Ok(())
+}
@@ -0,0 +1,538 @@
+//! Export and import of the contract state, for redeployments.
+//!
+//! The state is dumped one section at a time, in pages ordered by storage key, so the same
+//! state always exports to the same pages. Each page returns the raw storage key to continue
+//! after, which keeps a single pagination cursor for sections with different key types.
+//! The exported entries can be imported as is into a new deployment, before its first swap.
+
+use cosmwasm_schema::cw_serde;
+use std::collections::BTreeMap;
+
+use cosmwasm_std::{Addr, Api, Binary, Coin, Env, Order, Record, StdResult, Storage, Uint128};
+use cw_storage_plus::{Bound, IndexList, IndexedMap, KeyDeserialize, Map, PrimaryKey};
+use osmosis_std::types::osmosis::poolmanager::v1beta1::SwapAmountInRoute;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::contract::{
+    validate_callback_target, validate_volume_discounts, AffiliateSwap, CallbackPolicy, Config,
+    DenomMetadata, FeeHoliday, InputLimits, PairFee, Referral, Stats, VolumeDiscount,
+};
+use crate::error::ContractError;
+
+pub const DEFAULT_EXPORT_LIMIT: u32 = 30;
+pub const MAX_EXPORT_LIMIT: u32 = 100;
+
+#[cw_serde]
+pub enum StateSection {
+    Config,
+    PairFees,
+    VolumeDiscounts,
+    Volumes,
+    DailyStats,
+    // swap outputs and fees escrowed for their recipients
+    Claimable,
+    Referrals,
+    DeniedPools,
+    InputLimits,
+    RollingVolumes,
+    AllowedSenders,
+    FeeHolidays,
+    CallbackTargets,
+    SettlementRoutes,
+    ChannelStats,
+    DenomMetadata,
+    ClaimOperators,
+    CollectorStats,
+}
+
+#[cw_serde]
+pub enum StateEntry {
+    Config(Config),
+    PairFee {
+        token_in_denom: String,
+        token_out_denom: String,
+        pair_fee: PairFee,
+    },
+    VolumeDiscounts {
+        denom: String,
+        discounts: Vec<VolumeDiscount>,
+    },
+    Volume {
+        address: Addr,
+        denom: String,
+        amount: Uint128,
+    },
+    DailyStats {
+        day: u64,
+        denom: String,
+        stats: Stats,
+    },
+    Claimable {
+        address: Addr,
+        denom: String,
+        amount: Uint128,
+    },
+    Referral {
+        address: Addr,
+        referral: Referral,
+    },
+    DeniedPool {
+        pool_id: u64,
+    },
+    InputLimits {
+        denom: String,
+        limits: InputLimits,
+    },
+    RollingVolume {
+        address: Addr,
+        denom: String,
+        buckets: Vec<(u64, Uint128)>,
+    },
+    AllowedSender {
+        address: Addr,
+    },
+    FeeHoliday(FeeHoliday),
+    CallbackTarget {
+        address: Addr,
+        policy: CallbackPolicy,
+    },
+    SettlementRoute {
+        fee_collector: Addr,
+        denom: String,
+        routes: Vec<SwapAmountInRoute>,
+    },
+    ChannelStats {
+        channel: String,
+        day: u64,
+        denom: String,
+        stats: Stats,
+    },
+    DenomMetadata {
+        denom: String,
+        metadata: DenomMetadata,
+    },
+    ClaimOperator {
+        claimant: Addr,
+        operator: Addr,
+    },
+    CollectorStats {
+        day: u64,
+        fee_collector: Addr,
+        denom: String,
+        stats: Stats,
+    },
+}
+
+#[cw_serde]
+pub struct ExportStateResponse {
+    pub entries: Vec<StateEntry>,
+    // `start_after` of the next page, none once the section is exhausted
+    pub next_start_after: Option<Binary>,
+}
+
+pub fn export_state(
+    contract: &AffiliateSwap,
+    storage: &dyn Storage,
+    section: StateSection,
+    start_after: Option<Binary>,
+    limit: Option<u32>,
+) -> StdResult<ExportStateResponse> {
+    let limit = limit.unwrap_or(DEFAULT_EXPORT_LIMIT).min(MAX_EXPORT_LIMIT) as usize;
+    match section {
+        StateSection::Config => Ok(ExportStateResponse {
+            entries: vec![StateEntry::Config(contract.config.load(storage)?)],
+            next_start_after: None,
+        }),
+        StateSection::PairFees => page(
+            &contract.pair_fees,
+            storage,
+            start_after,
+            limit,
+            |(token_in_denom, token_out_denom), pair_fee| StateEntry::PairFee {
+                token_in_denom,
+                token_out_denom,
+                pair_fee,
+            },
+        ),
+        StateSection::VolumeDiscounts => page(
+            &contract.volume_discounts,
+            storage,
+            start_after,
+            limit,
+            |denom, discounts| StateEntry::VolumeDiscounts { denom, discounts },
+        ),
+        StateSection::Volumes => page(
+            &contract.volumes,
+            storage,
+            start_after,
+            limit,
+            |(address, denom), amount| StateEntry::Volume {
+                address,
+                denom,
+                amount,
+            },
+        ),
+        StateSection::DailyStats => page(
+            &contract.daily_stats,
+            storage,
+            start_after,
+            limit,
+            |(day, denom), stats| StateEntry::DailyStats { day, denom, stats },
+        ),
+        StateSection::Claimable => page(
+            &contract.claimable,
+            storage,
+            start_after,
+            limit,
+            |(address, denom), amount| StateEntry::Claimable {
+                address,
+                denom,
+                amount,
+            },
+        ),
+        StateSection::Referrals => page(
+            &contract.referrals,
+            storage,
+            start_after,
+            limit,
+            |address, referral| StateEntry::Referral { address, referral },
+        ),
+        StateSection::DeniedPools => page(
+            &contract.denied_pools,
+            storage,
+            start_after,
+            limit,
+            |pool_id, ()| StateEntry::DeniedPool { pool_id },
+        ),
+        StateSection::InputLimits => page(
+            &contract.input_limits,
+            storage,
+            start_after,
+            limit,
+            |denom, limits| StateEntry::InputLimits { denom, limits },
+        ),
+        StateSection::RollingVolumes => page(
+            &contract.rolling_volumes,
+            storage,
+            start_after,
+            limit,
+            |(address, denom), buckets| StateEntry::RollingVolume {
+                address,
+                denom,
+                buckets,
+            },
+        ),
+        StateSection::AllowedSenders => page(
+            &contract.allowed_senders,
+            storage,
+            start_after,
+            limit,
+            |address, ()| StateEntry::AllowedSender { address },
+        ),
+        StateSection::FeeHolidays => page(
+            &contract.fee_holidays,
+            storage,
+            start_after,
+            limit,
+            |_, holiday| StateEntry::FeeHoliday(holiday),
+        ),
+        StateSection::CallbackTargets => page(
+            &contract.callback_targets,
+            storage,
+            start_after,
+            limit,
+            |address, policy| StateEntry::CallbackTarget { address, policy },
+        ),
+        StateSection::SettlementRoutes => page(
+            &contract.settlement_routes,
+            storage,
+            start_after,
+            limit,
+            |(fee_collector, denom), routes| StateEntry::SettlementRoute {
+                fee_collector,
+                denom,
+                routes,
+            },
+        ),
+        StateSection::ChannelStats => page(
+            &contract.channel_stats,
+            storage,
+            start_after,
+            limit,
+            |(channel, day, denom), stats| StateEntry::ChannelStats {
+                channel,
+                day,
+                denom,
+                stats,
+            },
+        ),
+        StateSection::DenomMetadata => page(
+            &contract.denom_metadata,
+            storage,
+            start_after,
+            limit,
+            |denom, metadata| StateEntry::DenomMetadata { denom, metadata },
+        ),
+        StateSection::ClaimOperators => page(
+            &contract.claim_operators,
+            storage,
+            start_after,
+            limit,
+            |(claimant, operator), ()| StateEntry::ClaimOperator { claimant, operator },
+        ),
+        StateSection::CollectorStats => page(
+            &contract.collector_stats,
+            storage,
+            start_after,
+            limit,
+            |(day, fee_collector, denom), stats| StateEntry::CollectorStats {
+                day,
+                fee_collector,
+                denom,
+                stats,
+            },
+        ),
+    }
+}
+
+/// Saves exported `entries`, checked like the setters check them. The owner of the deployment
+/// is kept over the imported one. Escrowed outputs and fees are added to the existing ones and
+/// must be paid for exactly by `funds`.
+pub fn import_state(
+    contract: &AffiliateSwap,
+    storage: &mut dyn Storage,
+    api: &dyn Api,
+    env: &Env,
+    entries: Vec<StateEntry>,
+    funds: &[Coin],
+) -> Result<(), ContractError> {
+    // the escrowed amounts must be paid for before anything is saved
+    let mut escrowed = BTreeMap::<String, Uint128>::new();
+    for entry in &entries {
+        if let StateEntry::Claimable { denom, amount, .. } = entry {
+            let total = escrowed.entry(denom.clone()).or_default();
+            *total = total.checked_add(*amount)?;
+        }
+    }
+    let mut paid = BTreeMap::<String, Uint128>::new();
+    for coin in funds {
+        let total = paid.entry(coin.denom.clone()).or_default();
+        *total = total.checked_add(coin.amount)?;
+    }
+    escrowed.retain(|_, amount| !amount.is_zero());
+    paid.retain(|_, amount| !amount.is_zero());
+    if escrowed != paid {
+        return Err(ContractError::ImportFundsMismatch {});
+    }
+
+    for entry in entries {
+        match entry {
+            StateEntry::Config(config) => {
+                let owner = contract.config.load(storage)?.owner;
+                let config = Config { owner, ..config };
+                config.validate(api)?;
+                contract.config.save(storage, &config)?;
+            }
+            StateEntry::PairFee {
+                token_in_denom,
+                token_out_denom,
+                pair_fee,
+            } => {
+                pair_fee.validate()?;
+                contract
+                    .pair_fees
+                    .save(storage, (&token_in_denom, &token_out_denom), &pair_fee)?
+            }
+            StateEntry::VolumeDiscounts { denom, discounts } => {
+                validate_volume_discounts(&discounts)?;
+                contract
+                    .volume_discounts
+                    .save(storage, &denom, &discounts)?
+            }
+            StateEntry::Volume {
+                address,
+                denom,
+                amount,
+            } => contract
+                .volumes
+                .save(storage, (&address, &denom), &amount)?,
+            // nothing would keep them up to date
+            StateEntry::DailyStats { .. }
+            | StateEntry::ChannelStats { .. }
+            | StateEntry::CollectorStats { .. }
+                if !cfg!(feature = "stats") =>
+            {
+                return Err(ContractError::FeatureDisabled {
+                    feature: "stats".to_string(),
+                })
+            }
+            StateEntry::DailyStats { day, denom, stats } => {
+                contract.daily_stats.save(storage, (day, &denom), &stats)?
+            }
+            StateEntry::Claimable {
+                address,
+                denom,
+                amount,
+            } => {
+                contract.claimable.update(
+                    storage,
+                    (&address, &denom),
+                    |claimable| -> StdResult<_> {
+                        Ok(claimable.unwrap_or_default().checked_add(amount)?)
+                    },
+                )?;
+            }
+            StateEntry::Referral { address, referral } => {
+                contract.save_referral(storage, &address, &referral)?
+            }
+            StateEntry::DeniedPool { pool_id } => {
+                contract.denied_pools.save(storage, pool_id, &())?
+            }
+            StateEntry::InputLimits { denom, limits } => {
+                limits.validate()?;
+                contract.input_limits.save(storage, &denom, &limits)?
+            }
+            StateEntry::RollingVolume {
+                address,
+                denom,
+                buckets,
+            } => contract
+                .rolling_volumes
+                .save(storage, (&address, &denom), &buckets)?,
+            StateEntry::AllowedSender { address } => {
+                api.addr_validate(address.as_str())?;
+                contract.allowed_senders.save(storage, &address, &())?
+            }
+            // past holidays are imported as well, they are only checked for overlaps
+            StateEntry::FeeHoliday(holiday) => {
+                contract.validate_fee_holiday(storage, &holiday)?;
+                contract
+                    .fee_holidays
+                    .save(storage, holiday.start.nanos(), &holiday)?
+            }
+            StateEntry::CallbackTarget { address, policy } => {
+                api.addr_validate(address.as_str())?;
+                validate_callback_target(env, &address, &policy)?;
+                contract.callback_targets.save(storage, &address, &policy)?
+            }
+            // checked against the config and denied pools imported before them
+            StateEntry::SettlementRoute {
+                fee_collector,
+                denom,
+                routes,
+            } => {
+                contract.validate_settlement_route(storage, &denom, &routes)?;
+                contract
+                    .settlement_routes
+                    .save(storage, (&fee_collector, &denom), &routes)?
+            }
+            StateEntry::ChannelStats {
+                channel,
+                day,
+                denom,
+                stats,
+            } => contract
+                .channel_stats
+                .save(storage, (&channel, day, &denom), &stats)?,
+            StateEntry::DenomMetadata { denom, metadata } => {
+                metadata.validate()?;
+                contract.denom_metadata.save(storage, &denom, &metadata)?
+            }
+            StateEntry::CollectorStats {
+                day,
+                fee_collector,
+                denom,
+                stats,
+            } => contract
+                .collector_stats
+                .save(storage, (day, &fee_collector, &denom), &stats)?,
+            StateEntry::ClaimOperator { claimant, operator } => {
+                api.addr_validate(claimant.as_str())?;
+                api.addr_validate(operator.as_str())?;
+                contract
+                    .claim_operators
+                    .save(storage, (&claimant, &operator), &())?
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Map whose primary entries can be exported, plain or indexed
+trait ExportableMap<'a, K: PrimaryKey<'a>, T> {
+    fn range_from<'c>(
+        &self,
+        storage: &'c dyn Storage,
+        min: Option<Bound<'a, K>>,
+    ) -> Box<dyn Iterator<Item = StdResult<Record<T>>> + 'c>
+    where
+        T: 'c;
+}
+
+impl<'a, K, T> ExportableMap<'a, K, T> for Map<'a, K, T>
+where
+    K: PrimaryKey<'a>,
+    T: Serialize + DeserializeOwned,
+{
+    fn range_from<'c>(
+        &self,
+        storage: &'c dyn Storage,
+        min: Option<Bound<'a, K>>,
+    ) -> Box<dyn Iterator<Item = StdResult<Record<T>>> + 'c>
+    where
+        T: 'c,
+    {
+        self.range_raw(storage, min, None, Order::Ascending)
+    }
+}
+
+impl<'a, K, T, I> ExportableMap<'a, K, T> for IndexedMap<'a, K, T, I>
+where
+    K: PrimaryKey<'a> + KeyDeserialize,
+    T: Serialize + DeserializeOwned + Clone,
+    I: IndexList<T>,
+{
+    fn range_from<'c>(
+        &self,
+        storage: &'c dyn Storage,
+        min: Option<Bound<'a, K>>,
+    ) -> Box<dyn Iterator<Item = StdResult<Record<T>>> + 'c>
+    where
+        T: 'c,
+    {
+        self.range_raw(storage, min, None, Order::Ascending)
+    }
+}
+
+/// One page of `map` after the raw key `start_after`
+fn page<'a, K, T>(
+    map: &impl ExportableMap<'a, K, T>,
+    storage: &dyn Storage,
+    start_after: Option<Binary>,
+    limit: usize,
+    to_entry: impl Fn(K::Output, T) -> StateEntry,
+) -> StdResult<ExportStateResponse>
+where
+    K: PrimaryKey<'a> + KeyDeserialize,
+    T: Serialize + DeserializeOwned,
+{
+    let min = start_after.map(|key| Bound::ExclusiveRaw(key.into()));
+    let records = map
+        .range_from(storage, min)
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+    // a short page is the last one
+    let next_start_after = match records.last() {
+        Some((key, _)) if records.len() == limit => Some(Binary::from(key.as_slice())),
+        _ => None,
+    };
+    let entries = records
+        .into_iter()
+        .map(|(key, value)| Ok(to_entry(K::from_vec(key)?, value)))
+        .collect::<StdResult<_>>()?;
+    Ok(ExportStateResponse {
+        entries,
+        next_start_after,
+    })
+}
@@ -0,0 +1,170 @@
+//! Gas benchmarks for swaps, run with `cargo test --features bench bench`.
+//!
+//! Each case is compared against the baseline recorded in `test_artifacts/gas_baselines.json`
+//! and fails if it uses more than `TOLERANCE_PERCENT` more gas. After an intended change in gas
+//! usage (or after rebuilding the wasm artifact), record new baselines with
+//! `BENCH_RECORD=1 cargo test --features bench bench` and commit the updated file. Running
+//! them without a baseline file fails.
+//!
+//! Every case runs against the default build and against the build without stats tracking
+//! (`--no-default-features`), to measure what the stats cost.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use cosmwasm_std::{from_slice, to_vec, Coin, Decimal};
+use osmosis_std::types::osmosis::poolmanager::v1beta1::SwapAmountInRoute;
+use osmosis_test_tube::{Account, Gamm, Module};
+
+use crate::interface::affiliate_swap_interface::ExecMsg;
+
+use super::{TestEnv, TestEnvBuilder};
+
+const BASELINES_PATH: &str = "./test_artifacts/gas_baselines.json";
+const TOLERANCE_PERCENT: u64 = 2;
+
+// Builds benchmarked, by the suffix of their cases
+const ARTIFACTS: [(&str, &str); 2] = [
+    ("stats", "./test_artifacts/affiliate_swap.wasm"),
+    ("no_stats", "./test_artifacts/affiliate_swap_no_stats.wasm"),
+];
+
+// Denoms produced by each hop, in order. Pool `n` swaps `DENOMS[n - 1]` for `DENOMS[n]`
+const DENOMS: [&str; 4] = ["uosmo", "uion", "uatom", "ujuno"];
+
+struct BenchEnv {
+    t: TestEnv,
+    pool_ids: Vec<u64>,
+}
+
+fn setup_bench(wasm_path: &str) -> BenchEnv {
+    let funds = DENOMS
+        .iter()
+        .map(|denom| Coin::new(1_000_000_000_000, *denom))
+        .collect::<Vec<_>>();
+    let t = TestEnvBuilder::new()
+        .with_wasm(wasm_path)
+        .with_account("trader", funds)
+        .with_account("affiliate", vec![])
+        .build();
+    let trader = t.account("trader");
+
+    // A chain of pools so routes of any length up to DENOMS.len() - 1 can be built
+    let gamm = Gamm::new(&t.app);
+    let pool_ids = DENOMS
+        .windows(2)
+        .map(|pair| {
+            gamm.create_basic_pool(
+                &[
+                    Coin::new(1_000_000_000, pair[0]),
+                    Coin::new(1_000_000_000, pair[1]),
+                ],
+                trader,
+            )
+            .unwrap()
+            .data
+            .pool_id
+        })
+        .collect();
+
+    BenchEnv { t, pool_ids }
+}
+
+impl BenchEnv {
+    fn route(&self, hops: usize) -> Vec<SwapAmountInRoute> {
+        self.pool_ids[..hops]
+            .iter()
+            .zip(&DENOMS[1..])
+            .map(|(pool_id, denom)| SwapAmountInRoute {
+                pool_id: *pool_id,
+                token_out_denom: denom.to_string(),
+            })
+            .collect()
+    }
+
+    fn swap_gas(&self, hops: usize, fee_percentage: Option<Decimal>) -> u64 {
+        let routes = self.route(hops);
+        let token_out_denom = routes.last().unwrap().token_out_denom.clone();
+        self.t
+            .wasm()
+            .execute(
+                &self.t.contract_addr,
+                &ExecMsg::Swap {
+                    routes,
+                    token_out_min_amount: Some(Coin::new(1, token_out_denom)),
+                    fee_percentage,
+                    fee_collector: Some(self.t.account("affiliate").address()),
+                    on_failure: None,
+                    token_in_denom: None,
+                    allow_cyclic: None,
+                    receiver: None,
+                    ibc_origin: None,
+                    quote: None,
+                    expected_out: None,
+                    slippage_tolerance: None,
+                    max_price_impact: None,
+                    response_data: None,
+                    with_data: None,
+                    after: None,
+                    client_id: None,
+                    channel: None,
+                    fee_override: None,
+                },
+                &[Coin::new(1_000_000, DENOMS[0])],
+                self.t.account("trader"),
+            )
+            .unwrap()
+            .gas_info
+            .gas_used
+    }
+}
+
+#[test]
+fn bench_swap_gas() {
+    let record = std::env::var("BENCH_RECORD").is_ok();
+    let baselines: Option<BTreeMap<String, u64>> = std::fs::read(BASELINES_PATH)
+        .ok()
+        .map(|raw| from_slice(&raw).unwrap());
+    assert!(
+        baselines.is_some() || record,
+        "no baselines in {BASELINES_PATH}, record them with BENCH_RECORD=1"
+    );
+
+    let mut measured = BTreeMap::new();
+    for (build, wasm_path) in ARTIFACTS {
+        let env = setup_bench(wasm_path);
+        for hops in 1..DENOMS.len() {
+            for (fee_mode, fee_percentage) in [
+                ("no_fee", None),
+                ("fee", Some(Decimal::from_str("1").unwrap())),
+            ] {
+                let name = format!("swap_{hops}_hops_{fee_mode}_{build}");
+                measured.insert(name, env.swap_gas(hops, fee_percentage));
+            }
+        }
+    }
+
+    if record {
+        std::fs::write(BASELINES_PATH, to_vec(&measured).unwrap()).unwrap();
+        println!("recorded gas baselines to {BASELINES_PATH}: {measured:?}");
+        return;
+    }
+
+    let baselines = baselines.unwrap_or_default();
+    let mut regressions = vec![];
+    for (name, gas_used) in &measured {
+        let Some(baseline) = baselines.get(name) else {
+            regressions.push(format!("{name}: no baseline, used {gas_used}"));
+            continue;
+        };
+        println!("{name}: {gas_used} (baseline {baseline})");
+        if *gas_used > baseline + baseline * TOLERANCE_PERCENT / 100 {
+            regressions.push(format!("{name}: used {gas_used}, baseline {baseline}"));
+        }
+    }
+    assert!(
+        regressions.is_empty(),
+        "gas regressions (record new baselines with BENCH_RECORD=1 if intended):\n{}",
+        regressions.join("\n")
+    );
+}
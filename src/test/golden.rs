@@ -0,0 +1,162 @@
+//! Snapshots of the messages and events produced by a canonical set of swaps.
+//!
+//! Each case is compared against the response recorded in `test_artifacts/golden_messages.json`,
+//! so that unintended changes (e.g. to a Stargate type URL, the proto encoding of a message or
+//! an event attribute) fail loudly. After an intended change, record the new responses with
+//! `GOLDEN_RECORD=1 cargo test golden` and commit the updated file.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+use cosmwasm_std::{Coin, Decimal, Reply, Response, SubMsgResponse, SubMsgResult};
+use osmosis_std::types::osmosis::poolmanager::v1beta1::{
+    MsgSwapExactAmountInResponse, SwapAmountInRoute,
+};
+use serde_json::Value;
+
+use crate::contract::{AffiliateSwap, OnFailure, ReplyKind};
+use crate::interface::affiliate_swap_interface::ExecMsg;
+use crate::{execute, reply, ContractExecMsg};
+
+const GOLDEN_PATH: &str = "./test_artifacts/golden_messages.json";
+
+fn swap_msg(
+    routes: &[(u64, &str)],
+    fee_percentage: &str,
+    on_failure: Option<OnFailure>,
+) -> ContractExecMsg {
+    ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+        routes: routes
+            .iter()
+            .map(|(pool_id, denom)| SwapAmountInRoute {
+                pool_id: *pool_id,
+                token_out_denom: denom.to_string(),
+            })
+            .collect(),
+        token_out_min_amount: Some(Coin::new(1, routes.last().unwrap().1)),
+        fee_percentage: Some(Decimal::from_str(fee_percentage).unwrap()),
+        fee_collector: Some("collector".to_string()),
+        on_failure,
+        token_in_denom: None,
+        allow_cyclic: None,
+        receiver: None,
+        ibc_origin: None,
+        quote: None,
+        expected_out: None,
+        slippage_tolerance: None,
+        max_price_impact: None,
+        response_data: None,
+        with_data: None,
+        after: None,
+        client_id: None,
+        channel: None,
+        fee_override: None,
+    })
+}
+
+fn swap_reply(swap_id: u64, token_out_amount: u128) -> Reply {
+    Reply {
+        id: ReplyKind::Swap.reply_id(swap_id),
+        result: SubMsgResult::Ok(SubMsgResponse {
+            events: vec![],
+            data: Some(
+                MsgSwapExactAmountInResponse {
+                    token_out_amount: token_out_amount.to_string(),
+                }
+                .into(),
+            ),
+        }),
+    }
+}
+
+// The responses of every case, by name
+fn golden_responses() -> BTreeMap<String, Response> {
+    let mut deps = mock_dependencies();
+    AffiliateSwap::new()
+        .instantiate(
+            (deps.as_mut(), mock_env(), mock_info("owner", &[])),
+            Some(Decimal::from_str("5").unwrap()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+    let info = mock_info("sender", &[Coin::new(1_000_000, "uosmo")]);
+    let mut responses = BTreeMap::new();
+
+    let msg = swap_msg(&[(1, "uion")], "1", None);
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    responses.insert("swap_single_hop".to_string(), res);
+
+    let res = reply(deps.as_mut(), mock_env(), swap_reply(1, 980_000)).unwrap();
+    responses.insert("swap_reply".to_string(), res);
+
+    let output_transfer_failed = Reply {
+        id: ReplyKind::OutputTransfer.reply_id(1),
+        result: SubMsgResult::Err("blocked address".to_string()),
+    };
+    let res = reply(deps.as_mut(), mock_env(), output_transfer_failed).unwrap();
+    responses.insert("output_transfer_failed".to_string(), res);
+
+    let msg = swap_msg(&[(1, "uion"), (2, "uatom")], "0", None);
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    responses.insert("swap_multi_hop_no_fee".to_string(), res);
+
+    let on_failure = Some(OnFailure::RefundAndAck {
+        refund_address: None,
+    });
+    let msg = swap_msg(&[(1, "uion")], "1", on_failure);
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    let swap_failed = Reply {
+        id: ReplyKind::Swap.reply_id(3),
+        result: SubMsgResult::Err(
+            "token amount calculated (1) is lesser than min amount (2)".to_string(),
+        ),
+    };
+    let res = reply(deps.as_mut(), mock_env(), swap_failed).unwrap();
+    responses.insert("swap_refunded".to_string(), res);
+
+    responses
+}
+
+#[test]
+fn golden_messages() {
+    let responses: BTreeMap<String, Value> = golden_responses()
+        .into_iter()
+        .map(|(name, res)| (name, serde_json::to_value(res).unwrap()))
+        .collect();
+
+    if std::env::var("GOLDEN_RECORD").is_ok() {
+        let golden = serde_json::to_string_pretty(&responses).unwrap() + "\n";
+        std::fs::write(GOLDEN_PATH, golden).unwrap();
+        println!("recorded golden messages to {GOLDEN_PATH}");
+        return;
+    }
+
+    let golden: BTreeMap<String, Value> = std::fs::read(GOLDEN_PATH)
+        .map(|raw| serde_json::from_slice(&raw).unwrap())
+        .unwrap_or_default();
+
+    let mut changed = vec![];
+    for (name, res) in &responses {
+        match golden.get(name) {
+            Some(expected) if expected == res => {}
+            Some(expected) => changed.push(format!(
+                "{name}:\nexpected {}\ngot {}",
+                serde_json::to_string_pretty(expected).unwrap(),
+                serde_json::to_string_pretty(res).unwrap()
+            )),
+            None => changed.push(format!("{name}: not recorded")),
+        }
+    }
+    assert!(
+        changed.is_empty(),
+        "responses changed (record them with GOLDEN_RECORD=1 if intended):\n{}",
+        changed.join("\n")
+    );
+}
@@ -1,10 +1,12 @@
 use std::str::FromStr;
 
 use cosmwasm_std::{coins, Coin, Decimal};
+use osmosis_std::types::osmosis::poolmanager::v1beta1::SwapAmountInRoute;
 use osmosis_test_tube::{Account, FeeSetting};
 
 use crate::{
-    contract::{ExecMsg, InstantiateMsg, DEFAULT_MAX_FEE, TRUE_MAX_FEE},
+    contract::{InstantiateMsg, DEFAULT_MAX_FEE, TRUE_MAX_FEE},
+    interface::affiliate_swap_interface::ExecMsg,
     ContractError,
 };
 
@@ -14,6 +16,13 @@ fn setup_integration(fee: Option<Decimal>) -> TestEnv {
     TestEnvBuilder::new()
         .with_instantiate_msg(InstantiateMsg {
             max_fee_percentage: fee,
+            owner: None,
+            stale_swap_blocks: None,
+            reject_self_referral: None,
+            max_route_length: None,
+            router_pubkey: None,
+            fee_collector: None,
+            branding: None,
         })
         .build()
 }
@@ -55,6 +64,13 @@ fn test_instantiate_with_fee_greater_than_true_max_fee_percent() {
     TestEnvBuilder::new()
         .with_instantiate_msg(InstantiateMsg {
             max_fee_percentage: Some(Decimal::from_str(format!("{max_fee}").as_str()).unwrap()),
+            owner: None,
+            stale_swap_blocks: None,
+            reject_self_referral: None,
+            max_route_length: None,
+            router_pubkey: None,
+            fee_collector: None,
+            branding: None,
         })
         .build();
 }
@@ -68,9 +84,24 @@ fn test_no_funds_sent() {
             &t.contract_addr,
             &ExecMsg::Swap {
                 routes: vec![],
-                token_out_min_amount: Coin::new(1, "uion"),
+                token_out_min_amount: Some(Coin::new(1, "uion")),
                 fee_percentage: None,
-                fee_collector: String::new(),
+                fee_collector: Some(String::new()),
+                on_failure: None,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote: None,
+                expected_out: None,
+                slippage_tolerance: None,
+                max_price_impact: None,
+                response_data: None,
+                with_data: None,
+                after: None,
+                client_id: None,
+                channel: None,
+                fee_override: None,
             },
             &[],
             &t.accounts[0],
@@ -99,10 +130,28 @@ fn test_failed_swap() {
         .execute(
             &t.contract_addr,
             &ExecMsg::Swap {
-                routes: vec![],
-                token_out_min_amount: Coin::new(1, "non-existent"),
+                routes: vec![SwapAmountInRoute {
+                    pool_id: 1000,
+                    token_out_denom: "non-existent".to_string(),
+                }],
+                token_out_min_amount: Some(Coin::new(1, "non-existent")),
                 fee_percentage: None,
-                fee_collector: t.accounts[1].address(),
+                fee_collector: Some(t.accounts[1].address()),
+                on_failure: None,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote: None,
+                expected_out: None,
+                slippage_tolerance: None,
+                max_price_impact: None,
+                response_data: None,
+                with_data: None,
+                after: None,
+                client_id: None,
+                channel: None,
+                fee_override: None,
             },
             &[Coin::new(1, "uosmo")],
             &sender,
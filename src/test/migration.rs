@@ -0,0 +1,223 @@
+//! Upgrade tests: a released build is instantiated and used, then migrated to the current build
+//! (`test_artifacts/affiliate_swap.wasm`), and its state is checked to survive.
+//!
+//! Released builds are kept as `test_artifacts/affiliate_swap_v<version>.wasm`. Their messages
+//! are written as raw JSON, as the types of the current build no longer match them.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use cosmwasm_std::{Coin, Decimal, Uint128};
+use osmosis_std::types::cosmos::bank::v1beta1::QueryBalanceRequest;
+use osmosis_std::types::osmosis::poolmanager::v1beta1::SwapAmountInRoute;
+use osmosis_test_tube::cosmrs::proto::cosmwasm::wasm::v1::{
+    MsgMigrateContract, MsgMigrateContractResponse,
+};
+use osmosis_test_tube::{
+    Account, Bank, Gamm, Module, OsmosisTestApp, Runner, RunnerExecuteResult, SigningAccount, Wasm,
+};
+use serde_json::json;
+
+use crate::contract::{ConfigResponse, MigrateMsg};
+use crate::interface::affiliate_swap_interface::{ExecMsg, QueryMsg};
+use crate::ContractError;
+
+use super::TestEnv;
+
+const V0_1_0_WASM: &str = "./test_artifacts/affiliate_swap_v0.1.0.wasm";
+const CURRENT_WASM: &str = "./test_artifacts/affiliate_swap.wasm";
+
+fn store_code(app: &OsmosisTestApp, path: &str, signer: &SigningAccount) -> u64 {
+    let wasm_byte_code = std::fs::read(path).unwrap();
+    Wasm::new(app)
+        .store_code(&wasm_byte_code, None, signer)
+        .unwrap()
+        .data
+        .code_id
+}
+
+// The test-tube Wasm module has no migrate helper
+fn migrate(
+    app: &OsmosisTestApp,
+    contract: &str,
+    code_id: u64,
+    msg: &MigrateMsg,
+    signer: &SigningAccount,
+) -> RunnerExecuteResult<MsgMigrateContractResponse> {
+    app.execute(
+        MsgMigrateContract {
+            sender: signer.address(),
+            contract: contract.to_string(),
+            code_id,
+            msg: serde_json::to_vec(msg).unwrap(),
+        },
+        "/cosmwasm.wasm.v1.MsgMigrateContract",
+        signer,
+    )
+}
+
+fn balance(app: &OsmosisTestApp, address: &str, denom: &str) -> Uint128 {
+    Bank::new(app)
+        .query_balance(&QueryBalanceRequest {
+            address: address.to_string(),
+            denom: denom.to_string(),
+        })
+        .unwrap()
+        .balance
+        .map(|coin| Uint128::from_str(&coin.amount).unwrap())
+        .unwrap_or_default()
+}
+
+/// Instantiates the 0.1.0 release with a 5% max fee, creates a uosmo/uion pool and swaps
+/// through it once, with the fee going to the `affiliate` account.
+fn setup_v0_1_0() -> (TestEnv, u64) {
+    let app = OsmosisTestApp::new();
+    let accounts = app
+        .init_accounts(
+            &[
+                Coin::new(1_000_000_000_000, "uion"),
+                Coin::new(1_000_000_000_000, "uosmo"),
+            ],
+            2,
+        )
+        .unwrap();
+    let admin = &accounts[0];
+    let affiliate = app.init_account(&[]).unwrap();
+
+    let pool_id = Gamm::new(&app)
+        .create_basic_pool(
+            &[
+                Coin::new(1_000_000_000, "uosmo"),
+                Coin::new(1_000_000_000, "uion"),
+            ],
+            admin,
+        )
+        .unwrap()
+        .data
+        .pool_id;
+
+    let wasm = Wasm::new(&app);
+    let code_id = store_code(&app, V0_1_0_WASM, admin);
+    let contract_addr = wasm
+        .instantiate(
+            code_id,
+            &json!({ "max_fee_percentage": "5" }),
+            Some(&admin.address()),
+            None,
+            &[],
+            admin,
+        )
+        .unwrap()
+        .data
+        .address;
+
+    wasm.execute(
+        &contract_addr,
+        &json!({
+            "swap": {
+                "routes": [{ "pool_id": pool_id.to_string(), "token_out_denom": "uion" }],
+                "token_out_min_amount": { "denom": "uion", "amount": "1" },
+                "fee_percentage": "1",
+                "fee_collector": affiliate.address(),
+            }
+        }),
+        &[Coin::new(1_000_000, "uosmo")],
+        admin,
+    )
+    .unwrap();
+
+    let t = TestEnv {
+        app,
+        contract_addr,
+        accounts,
+        named_accounts: HashMap::from([("affiliate".to_string(), affiliate)]),
+    };
+    (t, pool_id)
+}
+
+#[test]
+fn test_migrate_from_v0_1_0() {
+    // migrating the release onto itself would pass without testing the current code
+    assert_ne!(
+        std::fs::read(CURRENT_WASM).unwrap(),
+        std::fs::read(V0_1_0_WASM).unwrap(),
+        "{CURRENT_WASM} is the 0.1.0 build, rebuild it from the current tree"
+    );
+    let (t, pool_id) = setup_v0_1_0();
+    let admin = &t.accounts[0];
+    let fee_collector = t.account("affiliate").address();
+    let fee_collected = balance(&t.app, &fee_collector, "uosmo");
+    assert_eq!(fee_collected, Uint128::new(10_000));
+
+    let code_id = store_code(&t.app, CURRENT_WASM, admin);
+
+    // 0.1.0 stored no owner, so the migration requires one
+    let err = migrate(
+        &t.app,
+        &t.contract_addr,
+        code_id,
+        &MigrateMsg { owner: None },
+        admin,
+    )
+    .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains(&ContractError::MissingOwner {}.to_string()));
+
+    migrate(
+        &t.app,
+        &t.contract_addr,
+        code_id,
+        &MigrateMsg {
+            owner: Some(admin.address()),
+        },
+        admin,
+    )
+    .unwrap();
+
+    // The max fee survives the migration
+    assert_eq!(t.query_max_fee(), Decimal::from_str("5").unwrap());
+    let config = t
+        .wasm()
+        .query::<QueryMsg, ConfigResponse>(&t.contract_addr, &QueryMsg::GetConfig {})
+        .unwrap();
+    assert_eq!(config.owner.as_str(), admin.address());
+    assert_eq!(config.max_fee_percentage, Decimal::from_str("5").unwrap());
+
+    // The migrated contract keeps swapping and paying the fee
+    t.wasm()
+        .execute(
+            &t.contract_addr,
+            &ExecMsg::Swap {
+                routes: vec![SwapAmountInRoute {
+                    pool_id,
+                    token_out_denom: "uion".to_string(),
+                }],
+                token_out_min_amount: Some(Coin::new(1, "uion")),
+                fee_percentage: Some(Decimal::from_str("1").unwrap()),
+                fee_collector: Some(fee_collector.clone()),
+                on_failure: None,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote: None,
+                expected_out: None,
+                slippage_tolerance: None,
+                max_price_impact: None,
+                response_data: None,
+                with_data: None,
+                after: None,
+                client_id: None,
+                channel: None,
+                fee_override: None,
+            },
+            &[Coin::new(1_000_000, "uosmo")],
+            admin,
+        )
+        .unwrap();
+    assert_eq!(
+        balance(&t.app, &fee_collector, "uosmo"),
+        fee_collected + Uint128::new(10_000)
+    );
+}
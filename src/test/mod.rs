@@ -1,7 +1,16 @@
+#[cfg(feature = "bench")]
+mod bench;
+// the snapshots are of the Osmosis swap messages
+#[cfg(not(feature = "astroport"))]
+mod golden;
 mod integration;
+mod migration;
 mod unit;
 
-use crate::contract::{InstantiateMsg, QueryMsg};
+use crate::contract::InstantiateMsg;
+use crate::interface::affiliate_swap_interface::QueryMsg;
+
+use std::collections::HashMap;
 
 use cosmwasm_std::{Coin, Decimal};
 
@@ -12,6 +21,8 @@ pub struct TestEnv {
     pub app: OsmosisTestApp,
     pub contract_addr: String,
     accounts: Vec<SigningAccount>,
+    // accounts added with `TestEnvBuilder::with_account`, by name
+    named_accounts: HashMap<String, SigningAccount>,
 }
 
 impl TestEnv {
@@ -29,24 +40,50 @@ impl TestEnv {
     fn wasm(&self) -> Wasm<'_, OsmosisTestApp> {
         Wasm::new(&self.app)
     }
+
+    /// Account added with `TestEnvBuilder::with_account`
+    pub fn account(&self, name: &str) -> &SigningAccount {
+        self.named_accounts
+            .get(name)
+            .unwrap_or_else(|| panic!("no account named {name}"))
+    }
 }
 
 pub struct TestEnvBuilder {
     instantiate_msg: Option<InstantiateMsg>,
+    // the current build by default
+    wasm_path: Option<String>,
+    // initial balances of the named accounts
+    accounts: HashMap<String, Vec<Coin>>,
 }
 
 impl TestEnvBuilder {
     pub fn new() -> Self {
         Self {
             instantiate_msg: None,
+            wasm_path: None,
+            accounts: HashMap::new(),
         }
     }
 
+    /// Adds an account named `name` funded with `balances`, to model a role (swapper,
+    /// affiliate, treasury...). It is available from `TestEnv::account`.
+    pub fn with_account(mut self, name: &str, balances: Vec<Coin>) -> Self {
+        self.accounts.insert(name.to_string(), balances);
+        self
+    }
+
     pub fn with_instantiate_msg(mut self, msg: InstantiateMsg) -> Self {
         self.instantiate_msg = Some(msg);
         self
     }
 
+    /// Deploys the build at `path` instead of `test_artifacts/affiliate_swap.wasm`
+    pub fn with_wasm(mut self, path: &str) -> Self {
+        self.wasm_path = Some(path.to_string());
+        self
+    }
+
     pub fn build(self) -> TestEnv {
         let app = OsmosisTestApp::new();
 
@@ -63,7 +100,12 @@ impl TestEnvBuilder {
         let wasm = Wasm::new(&app);
         let admin = &accounts[0];
 
-        let wasm_byte_code = std::fs::read("./test_artifacts/affiliate_swap.wasm").unwrap();
+        let wasm_path = self
+            .wasm_path
+            .as_deref()
+            .unwrap_or("./test_artifacts/affiliate_swap.wasm");
+        let wasm_byte_code =
+            std::fs::read(wasm_path).unwrap_or_else(|err| panic!("cannot read {wasm_path}: {err}"));
         let code_id = wasm
             .store_code(&wasm_byte_code, None, admin)
             .unwrap()
@@ -75,6 +117,13 @@ impl TestEnvBuilder {
                 code_id,
                 &self.instantiate_msg.unwrap_or(InstantiateMsg {
                     max_fee_percentage: None,
+                    owner: None,
+                    stale_swap_blocks: None,
+                    reject_self_referral: None,
+                    max_route_length: None,
+                    router_pubkey: None,
+                    fee_collector: None,
+                    branding: None,
                 }),
                 None,  // contract admin used for migration, not the same as cw1_whitelist admin
                 None,  // contract label
@@ -85,10 +134,17 @@ impl TestEnvBuilder {
             .data
             .address;
 
+        let named_accounts = self
+            .accounts
+            .into_iter()
+            .map(|(name, balances)| (name, app.init_account(&balances).unwrap()))
+            .collect();
+
         TestEnv {
             app,
             contract_addr,
             accounts,
+            named_accounts,
         }
     }
 }
@@ -1,21 +1,48 @@
+// Most tests assert the Osmosis swap messages and replies, and are not built with the
+// astroport feature, leaving some imports and helpers unused
+#![cfg_attr(feature = "astroport", allow(dead_code, unused_imports))]
+
 use std::collections::HashMap;
 use std::fmt::Display;
 use std::str::FromStr;
 
 use cosmwasm_std::testing::{
-    mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+    mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR,
 };
 use cosmwasm_std::{
-    from_binary, Addr, BankMsg, Coin, CosmosMsg, Decimal, DepsMut, Empty, OwnedDeps, Reply,
-    Response, SubMsgResponse, SubMsgResult, Uint128,
+    from_binary, from_slice, to_binary, to_vec, Addr, BankMsg, Binary, Coin, ContractInfoResponse,
+    ContractResult, CosmosMsg, Decimal, DepsMut, Empty, Env, OwnedDeps, Querier, QuerierResult,
+    QueryRequest, Reply, ReplyOn, Response, Storage, SubMsgResponse, SubMsgResult, SystemResult,
+    Timestamp, Uint128, WasmMsg, WasmQuery,
 };
+use k256::ecdsa::signature::Signer;
+use k256::ecdsa::{Signature, SigningKey};
+use osmosis_std::types::osmosis::gamm::v2::QuerySpotPriceResponse;
 use osmosis_std::types::osmosis::poolmanager::v1beta1::{
-    MsgSwapExactAmountIn, MsgSwapExactAmountInResponse, SwapAmountInRoute,
+    EstimateSwapExactAmountInRequest, EstimateSwapExactAmountInResponse, MsgSwapExactAmountIn,
+    MsgSwapExactAmountInResponse, SwapAmountInRoute,
 };
 
-use crate::contract::ExecMsg;
-use crate::contract::{AffiliateSwap, ContractExecMsg, SwapResponse};
-use crate::{execute, reply};
+use crate::backend::SwapBackend;
+use crate::contract::{
+    fee_amount, price_impact, AffiliateSwap, AfterSwap, Branding, CallbackPolicy,
+    CallbackTargetEntry, Config, ContractExecMsg, DenomSolvency, EffectiveFeeResponse, FeeHoliday,
+    HopEstimate, InstantiateMsg, IsPausedResponse, OnFailure, PairFee, PairFeeEntry, ReplyKind,
+    ResponseData, SudoMsg, SwapEstimateResponse, SwapResponse, SwapSimulationResponse,
+    VolumeDiscount, CLIENT_ID_RETENTION_SECONDS, CONFIG_KEY, DEFAULT_MAX_ROUTE_LENGTH,
+    DEFAULT_SETTLEMENT_SLIPPAGE_BPS, DEFAULT_STALE_SWAP_BLOCKS, EVENT_VERSION,
+    MAX_CALLBACK_GAS_LIMIT, MAX_CLIENT_ID_LENGTH, TRUE_MAX_FEE,
+};
+use crate::fee_override::{FeeOverride, FeeOverridePayload};
+use crate::ibc_hooks::{intermediate_sender, wasm_memo, IbcOrigin};
+use crate::interface::affiliate_swap_interface::ExecMsg;
+use crate::interface::AffiliateSwapInterface;
+use crate::migrations::{LEGACY_ACTIVE_SWAP_KEY, LEGACY_MAX_FEE};
+use crate::osmosis::OsmosisBackend;
+use crate::quote::{QuotePayload, RouteQuote};
+use crate::state_export::{StateEntry, StateSection};
+use crate::vault::{VaultExecuteMsg, VaultQueryMsg};
+use crate::{execute, reply, ContractError, SwapContext, SwapFailure};
 
 fn setup_unit(fee: Option<Decimal>) -> OwnedDeps<MockStorage, MockApi, MockQuerier, Empty> {
     let affiliate_swap = AffiliateSwap::new();
@@ -23,14 +50,22 @@ fn setup_unit(fee: Option<Decimal>) -> OwnedDeps<MockStorage, MockApi, MockQueri
     // instantiate contract
     affiliate_swap
         .instantiate(
-            (deps.as_mut(), mock_env(), mock_info("instantiator", &[])),
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
             fee,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .unwrap();
 
     deps
 }
 
+const OWNER: &str = "owner";
 const SENDER: &str = "sender";
 const COLLECTOR: &str = "collector";
 
@@ -39,14 +74,29 @@ fn simple_execute(deps: DepsMut, amount: u128, fee: Option<Decimal>) -> Response
         deps,
         mock_env(),
         mock_info(SENDER, &[Coin::new(amount, "uosmo")]),
-        ContractExecMsg::AffiliateSwap(ExecMsg::Swap {
+        ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
             routes: vec![SwapAmountInRoute {
                 pool_id: 1,
                 token_out_denom: "uion".to_string(),
             }],
-            token_out_min_amount: Coin::new(1, "uion"),
+            token_out_min_amount: Some(Coin::new(1, "uion")),
             fee_percentage: fee,
-            fee_collector: COLLECTOR.to_string(),
+            fee_collector: Some(COLLECTOR.to_string()),
+            on_failure: None,
+            token_in_denom: None,
+            allow_cyclic: None,
+            receiver: None,
+            ibc_origin: None,
+            quote: None,
+            expected_out: None,
+            slippage_tolerance: None,
+            max_price_impact: None,
+            response_data: None,
+            with_data: None,
+            after: None,
+            client_id: None,
+            channel: None,
+            fee_override: None,
         }),
     )
     .unwrap()
@@ -83,135 +133,107 @@ fn is_valid_bank_send_msg(msg: &CosmosMsg, receiver: &str, amount: Uint128, deno
     }
 }
 
+fn stored_fee(deps: &OwnedDeps<MockStorage, MockApi, MockQuerier, Empty>, swap_id: u64) -> Uint128 {
+    let affiliate_swap = AffiliateSwap::new();
+    let active_swap = affiliate_swap
+        .active_swaps
+        .load(&deps.storage, swap_id)
+        .unwrap();
+    active_swap.fee.amount
+}
+
 #[test]
+#[cfg(not(feature = "astroport"))]
 fn test_fee_calculation() {
-    let affiliate_swap = AffiliateSwap::new();
     let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
 
+    // The fee is only sent in the reply, the swap is the only message
+
     // No fee set, no fee taken
     let res = simple_execute(deps.as_mut(), 100, None);
     assert_eq!(res.messages.len(), 1);
+    assert_eq!(stored_fee(&deps, 1), Uint128::zero());
     assert!(is_valid_swap_msg(
         &res.messages[0].msg,
         Coin::new(100, "uosmo")
     ));
 
-    // delete the active swap. This would normally be handled by the reply
-    affiliate_swap.active_swap.remove(&mut deps.storage);
-
     // Fee 1%, swap 99%
     let res = simple_execute(deps.as_mut(), 100, Some(Decimal::from_str("1").unwrap()));
-    assert_eq!(res.messages.len(), 2);
-    assert!(is_valid_bank_send_msg(
-        &res.messages[0].msg,
-        COLLECTOR,
-        1u128.into(),
-        "uosmo"
-    ));
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(stored_fee(&deps, 2), Uint128::new(1));
     assert!(is_valid_swap_msg(
-        &res.messages[1].msg,
+        &res.messages[0].msg,
         Coin::new(99, "uosmo")
     ),);
 
-    // delete the active swap. This would normally be handled by the reply
-    affiliate_swap.active_swap.remove(&mut deps.storage);
-
     // Fee 10%, defaults to max: 5%
     let res = simple_execute(deps.as_mut(), 100, Some(Decimal::from_str("10").unwrap()));
-    assert_eq!(res.messages.len(), 2);
-    assert!(is_valid_bank_send_msg(
-        &res.messages[0].msg,
-        COLLECTOR,
-        5u128.into(),
-        "uosmo"
-    ));
+    assert_eq!(stored_fee(&deps, 3), Uint128::new(5));
     assert!(is_valid_swap_msg(
-        &res.messages[1].msg,
+        &res.messages[0].msg,
         Coin::new(95, "uosmo")
     ));
 
-    // delete the active swap. This would normally be handled by the reply
-    affiliate_swap.active_swap.remove(&mut deps.storage);
-
     // Non-int fee
     let res = simple_execute(deps.as_mut(), 1000, Some(Decimal::from_str("1.7").unwrap()));
-    assert_eq!(res.messages.len(), 2);
-    assert!(is_valid_bank_send_msg(
-        &res.messages[0].msg,
-        COLLECTOR,
-        17u128.into(),
-        "uosmo"
-    ));
+    assert_eq!(stored_fee(&deps, 4), Uint128::new(17));
     assert!(is_valid_swap_msg(
-        &res.messages[1].msg,
+        &res.messages[0].msg,
         Coin::new(983, "uosmo")
     ));
 
-    // delete the active swap. This would normally be handled by the reply
-    affiliate_swap.active_swap.remove(&mut deps.storage);
-
     // Edge cases
 
     // low amounts send amount: no fee taken
     let res = simple_execute(deps.as_mut(), 1, Some(Decimal::from_str("1").unwrap()));
-    assert_eq!(res.messages.len(), 1);
+    assert_eq!(stored_fee(&deps, 5), Uint128::zero());
     assert!(is_valid_swap_msg(
         &res.messages[0].msg,
         Coin::new(1, "uosmo")
     ));
-    // delete the active swap. This would normally be handled by the reply
-    affiliate_swap.active_swap.remove(&mut deps.storage);
-
     // Fee rounds to less than one: no fee taken
     let res = simple_execute(deps.as_mut(), 9, Some(Decimal::from_str("10").unwrap()));
-    assert_eq!(res.messages.len(), 1);
+    assert_eq!(stored_fee(&deps, 6), Uint128::zero());
     assert!(is_valid_swap_msg(
         &res.messages[0].msg,
         Coin::new(9, "uosmo")
     ));
-    // delete the active swap. This would normally be handled by the reply
-    affiliate_swap.active_swap.remove(&mut deps.storage);
-
     // Fee rounds to at least one: fee taken
     let res = simple_execute(deps.as_mut(), 20, Some(Decimal::from_str("5").unwrap()));
-    assert_eq!(res.messages.len(), 2);
-    assert!(is_valid_bank_send_msg(
-        &res.messages[0].msg,
-        COLLECTOR,
-        1u128.into(),
-        "uosmo"
-    ));
+    assert_eq!(stored_fee(&deps, 7), Uint128::new(1));
     assert!(is_valid_swap_msg(
-        &res.messages[1].msg,
+        &res.messages[0].msg,
         Coin::new(19, "uosmo")
     ));
-    // delete the active swap. This would normally be handled by the reply
-    affiliate_swap.active_swap.remove(&mut deps.storage);
-
     // Max uint amount
-    let res = simple_execute(deps.as_mut(), Uint128::MAX.into(), Some(Decimal::from_str("5").unwrap()));
-    assert_eq!(res.messages.len(), 2);
-    let fee = Uint128::MAX*(Decimal::from_str("5").unwrap().checked_div(Decimal::from_str("100").unwrap()).unwrap());
-    assert!(is_valid_bank_send_msg(
-        &res.messages[0].msg,
-        COLLECTOR,
-        fee,
-        "uosmo"
-    ));
+    let res = simple_execute(
+        deps.as_mut(),
+        Uint128::MAX.into(),
+        Some(Decimal::from_str("5").unwrap()),
+    );
+    let fee = Uint128::MAX
+        * (Decimal::from_str("5")
+            .unwrap()
+            .checked_div(Decimal::from_str("100").unwrap())
+            .unwrap());
+    assert_eq!(stored_fee(&deps, 8), fee);
     assert!(is_valid_swap_msg(
-        &res.messages[1].msg,
-        Coin::new((Uint128::MAX-fee).into(), "uosmo")
+        &res.messages[0].msg,
+        Coin::new((Uint128::MAX - fee).into(), "uosmo")
     ));
-    // delete the active swap. This would normally be handled by the reply
-    affiliate_swap.active_swap.remove(&mut deps.storage);
 }
 
 fn simple_reply(deps: DepsMut, amount: impl Display) -> Response {
+    reply_with_id(deps, 1, amount)
+}
+
+fn reply_with_id(deps: DepsMut, swap_id: u64, amount: impl Display) -> Response {
     reply(
         deps,
         mock_env(),
         Reply {
-            id: 1,
+            id: ReplyKind::Swap.reply_id(swap_id),
             result: SubMsgResult::Ok(SubMsgResponse {
                 events: vec![],
                 data: Some(
@@ -227,15 +249,23 @@ fn simple_reply(deps: DepsMut, amount: impl Display) -> Response {
 }
 
 #[test]
+#[cfg(not(feature = "astroport"))]
 fn test_reply() {
     let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
 
     simple_execute(deps.as_mut(), 100, Some(Decimal::from_str("1").unwrap()));
     let res = simple_reply(deps.as_mut(), 98);
 
-    assert_eq!(res.messages.len(), 1);
+    // The fee is paid once the swap succeeded
+    assert_eq!(res.messages.len(), 2);
+    assert!(is_valid_bank_send_msg(
+        &res.messages[0].msg,
+        COLLECTOR,
+        1u128.into(),
+        "uosmo"
+    ));
     assert_eq!(
-        res.messages[0].msg,
+        res.messages[1].msg,
         CosmosMsg::Bank(BankMsg::Send {
             to_address: SENDER.to_string(),
             amount: vec![Coin::new(98, "uion")],
@@ -244,7 +274,40 @@ fn test_reply() {
 
     // The active swap has been deleted
     let affiliate_swap = AffiliateSwap::new();
-    affiliate_swap.active_swap.load(&deps.storage).unwrap_err();
+    affiliate_swap
+        .active_swaps
+        .load(&deps.storage, 1)
+        .unwrap_err();
+
+    // the fee is reported paid once its transfer succeeded
+    assert!(!res.events.iter().any(|e| e.ty == "affiliate_fee_paid"));
+    let fee_res = reply(
+        deps.as_mut(),
+        mock_env(),
+        Reply {
+            id: ReplyKind::FeeTransfer.reply_id(1),
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: None,
+            }),
+        },
+    )
+    .unwrap();
+    let event = fee_res
+        .events
+        .iter()
+        .find(|e| e.ty == "affiliate_fee_paid")
+        .unwrap();
+    let event_attributes = event
+        .attributes
+        .iter()
+        .map(|a| (a.key.clone(), a.value.clone()))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(event_attributes["fee_collector"], COLLECTOR);
+    assert_eq!(event_attributes["denom"], "uosmo");
+    assert_eq!(event_attributes["amount"], "1");
+    assert_eq!(event_attributes["swap_id"], "1");
+    assert_eq!(event_attributes["event_version"], EVENT_VERSION);
 
     // get the event
     let event = res
@@ -258,16 +321,24 @@ fn test_reply() {
         .iter()
         .map(|a| (a.key.clone(), a.value.clone()))
         .collect::<HashMap<_, _>>();
+    assert_eq!(event_attributes["event_version"], EVENT_VERSION);
     assert_eq!(event_attributes["sender"], SENDER);
     assert_eq!(event_attributes["swap_token_in"], "99uosmo");
     assert_eq!(event_attributes["token_out"], "98uion");
     assert_eq!(event_attributes["fee"], "1uosmo");
+    assert_eq!(event_attributes["fee_percentage"], "1");
+    assert_eq!(event_attributes["fee_collector"], COLLECTOR);
+    assert_eq!(event_attributes["route"], "1:uion");
+    assert_eq!(event_attributes["swap_id"], "1");
+    // 98 / 99
+    assert_eq!(event_attributes["execution_price"], "0.989898989898989898");
 
     // check data
     let response: SwapResponse = from_binary(&res.data.unwrap()).unwrap();
     assert_eq!(
         response,
         SwapResponse {
+            swap_id: 1,
             original_sender: SENDER.to_string(),
             fee: 1_u128.into(),
             fee_collector: Addr::unchecked(COLLECTOR),
@@ -275,21 +346,5151 @@ fn test_reply() {
             swap_in_amount: 99_u128.into(),
             token_out_denom: "uion".to_string(),
             token_out_amount: 98_u128.into(),
+            route: vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uion".to_string(),
+            }],
+            client_id: None,
+            swap_in_metadata: None,
+            token_out_metadata: None,
+        }
+    );
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_response_data() {
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+    let swap = |deps: DepsMut, response_data: Option<ResponseData>, with_data: Option<bool>| {
+        execute(
+            deps,
+            mock_env(),
+            mock_info(SENDER, &[Coin::new(100, "uosmo")]),
+            ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+                routes: vec![SwapAmountInRoute {
+                    pool_id: 1,
+                    token_out_denom: "uion".to_string(),
+                }],
+                token_out_min_amount: Some(Coin::new(1, "uion")),
+                fee_percentage: Some(Decimal::from_str("1").unwrap()),
+                fee_collector: Some(COLLECTOR.to_string()),
+                on_failure: None,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote: None,
+                expected_out: None,
+                slippage_tolerance: None,
+                max_price_impact: None,
+                response_data,
+                with_data,
+                after: None,
+                client_id: None,
+                channel: None,
+                fee_override: None,
+            }),
+        )
+        .unwrap()
+    };
+
+    // the JSON response by default
+    swap(deps.as_mut(), None, None);
+    let res = reply_with_id(deps.as_mut(), 1, 98);
+    let response: SwapResponse = from_binary(&res.data.unwrap()).unwrap();
+    assert_eq!(response.token_out_amount, Uint128::new(98));
+
+    // the response of a direct swap
+    swap(deps.as_mut(), Some(ResponseData::Proto), Some(true));
+    let res = reply_with_id(deps.as_mut(), 2, 97);
+    let response: MsgSwapExactAmountInResponse = res.data.unwrap().try_into().unwrap();
+    assert_eq!(response.token_out_amount, "97");
+
+    // only the events
+    swap(deps.as_mut(), Some(ResponseData::None), None);
+    let res = reply_with_id(deps.as_mut(), 3, 96);
+    assert_eq!(res.data, None);
+    assert!(res.events.iter().any(|e| e.ty == "affiliate_swap"));
+    assert_eq!(res.messages.len(), 2);
+
+    // whatever the requested format
+    swap(deps.as_mut(), Some(ResponseData::Json), Some(false));
+    let res = reply_with_id(deps.as_mut(), 4, 95);
+    assert_eq!(res.data, None);
+    assert!(res.events.iter().any(|e| e.ty == "affiliate_swap"));
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_client_id() {
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+    let swap = |deps: DepsMut, env: Env, sender: &str, client_id: &str, on_failure| {
+        execute(
+            deps,
+            env,
+            mock_info(sender, &[Coin::new(100, "uosmo")]),
+            ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+                routes: vec![SwapAmountInRoute {
+                    pool_id: 1,
+                    token_out_denom: "uion".to_string(),
+                }],
+                token_out_min_amount: Some(Coin::new(1, "uion")),
+                fee_percentage: None,
+                fee_collector: Some(COLLECTOR.to_string()),
+                on_failure,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote: None,
+                expected_out: None,
+                slippage_tolerance: None,
+                max_price_impact: None,
+                response_data: None,
+                with_data: None,
+                after: None,
+                client_id: Some(client_id.to_string()),
+                channel: None,
+                fee_override: None,
+            }),
+        )
+    };
+
+    for client_id in ["", &"x".repeat(MAX_CLIENT_ID_LENGTH + 1)] {
+        let err = swap(deps.as_mut(), mock_env(), SENDER, client_id, None).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InvalidClientId {
+                max: MAX_CLIENT_ID_LENGTH
+            }
+        );
+    }
+
+    // the client id is reported with the swap
+    swap(deps.as_mut(), mock_env(), SENDER, "order-1", None).unwrap();
+    let res = reply_with_id(deps.as_mut(), 1, 98);
+    let response: SwapResponse = from_binary(&res.data.unwrap()).unwrap();
+    assert_eq!(response.client_id, Some("order-1".to_string()));
+    let swap_event = res
+        .events
+        .iter()
+        .find(|e| e.ty == "affiliate_swap")
+        .unwrap();
+    assert!(swap_event
+        .attributes
+        .iter()
+        .any(|a| a.key == "client_id" && a.value == "order-1"));
+
+    // a rebroadcast is rejected, ids are scoped by sender
+    let err = swap(deps.as_mut(), mock_env(), SENDER, "order-1", None).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::DuplicateClientId {
+            client_id: "order-1".to_string()
         }
     );
+    swap(deps.as_mut(), mock_env(), "other", "order-1", None).unwrap();
+
+    // the id can be reused once the retention window is over
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(CLIENT_ID_RETENTION_SECONDS);
+    swap(deps.as_mut(), env, SENDER, "order-1", None).unwrap();
+
+    // a refunded swap releases its id
+    let on_failure = Some(OnFailure::RefundAndAck {
+        refund_address: None,
+    });
+    swap(
+        deps.as_mut(),
+        mock_env(),
+        SENDER,
+        "order-2",
+        on_failure.clone(),
+    )
+    .unwrap();
+    let res = reply(
+        deps.as_mut(),
+        mock_env(),
+        Reply {
+            id: ReplyKind::Swap.reply_id(4),
+            result: SubMsgResult::Err("pool not found".to_string()),
+        },
+    )
+    .unwrap();
+    let failed_event = res
+        .events
+        .iter()
+        .find(|e| e.ty == "affiliate_swap_failed")
+        .unwrap();
+    assert!(failed_event
+        .attributes
+        .iter()
+        .any(|a| a.key == "client_id" && a.value == "order-2"));
+    swap(deps.as_mut(), mock_env(), SENDER, "order-2", on_failure).unwrap();
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_concurrent_swaps() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+
+    // Two swaps in flight at the same time
+    let res = simple_execute(deps.as_mut(), 100, None);
+    assert_eq!(res.messages[0].id, ReplyKind::Swap.reply_id(1));
+    let res = simple_execute(deps.as_mut(), 200, None);
+    assert_eq!(res.messages[0].id, ReplyKind::Swap.reply_id(2));
+
+    // Replies can be processed in any order
+    let res = reply_with_id(deps.as_mut(), 2, 150);
+    let response: SwapResponse = from_binary(&res.data.unwrap()).unwrap();
+    assert_eq!(response.swap_id, 2);
+    assert_eq!(response.swap_in_amount, Uint128::new(200));
+    affiliate_swap.active_swaps.load(&deps.storage, 1).unwrap();
+
+    let res = reply_with_id(deps.as_mut(), 1, 75);
+    let response: SwapResponse = from_binary(&res.data.unwrap()).unwrap();
+    assert_eq!(response.swap_id, 1);
+    assert_eq!(response.swap_in_amount, Uint128::new(100));
+    affiliate_swap
+        .active_swaps
+        .load(&deps.storage, 1)
+        .unwrap_err();
 }
 
 #[test]
+#[cfg(not(feature = "astroport"))]
 fn test_bad_reply() {
     let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
     simple_execute(deps.as_mut(), 100, Some(Decimal::from_str("1").unwrap()));
-    reply(
+    let err = reply(
+        deps.as_mut(),
+        mock_env(),
+        Reply {
+            id: ReplyKind::Swap.reply_id(1),
+            result: SubMsgResult::Err("Any error should do here".to_string()),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::FailedSwap {
+            reason: "Any error should do here".to_string(),
+            context: Box::new(SwapContext {
+                route: "1:uion".to_string(),
+                token_in: Coin::new(99, "uosmo"),
+                token_out_min: Coin::new(1, "uion"),
+            }),
+        }
+    );
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_swap_failure_classification() {
+    let cases = [
+        (
+            "failed to execute message; message index: 0: token amount calculated (98) is lesser than min amount (99): calculated amount is lesser than min amount",
+            SwapFailure::Slippage,
+        ),
+        ("pool not found", SwapFailure::PoolNotFound),
+        (
+            "failed to find route for pool id (1000)",
+            SwapFailure::PoolNotFound,
+        ),
+        (
+            "there are no more ticks initialized to fill the swap",
+            SwapFailure::InsufficientLiquidity,
+        ),
+        (
+            "100uosmo is smaller than 200uosmo: insufficient funds",
+            SwapFailure::Unknown,
+        ),
+        ("codespace: wasm, code: 5", SwapFailure::Unknown),
+    ];
+    for (reason, failure) in cases {
+        assert_eq!(SwapFailure::classify(reason), failure, "{reason}");
+    }
+
+    // The reverting path returns the matching error
+    let mut deps = setup_unit(None);
+    simple_execute(deps.as_mut(), 100, None);
+    let err = reply(
+        deps.as_mut(),
+        mock_env(),
+        Reply {
+            id: ReplyKind::Swap.reply_id(1),
+            result: SubMsgResult::Err("pool not found".to_string()),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::PoolNotFound {
+            reason: "pool not found".to_string(),
+            context: Box::new(SwapContext {
+                route: "1:uion".to_string(),
+                token_in: Coin::new(100, "uosmo"),
+                token_out_min: Coin::new(1, "uion"),
+            }),
+        }
+    );
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_bad_reply_refund() {
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(SENDER, &[Coin::new(100, "uosmo")]),
+        ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+            routes: vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uion".to_string(),
+            }],
+            token_out_min_amount: Some(Coin::new(1, "uion")),
+            fee_percentage: Some(Decimal::from_str("1").unwrap()),
+            fee_collector: Some(COLLECTOR.to_string()),
+            on_failure: Some(OnFailure::RefundAndAck {
+                refund_address: Some("refund".to_string()),
+            }),
+            token_in_denom: None,
+            allow_cyclic: None,
+            receiver: None,
+            ibc_origin: None,
+            quote: None,
+            expected_out: None,
+            slippage_tolerance: None,
+            max_price_impact: None,
+            response_data: None,
+            with_data: None,
+            after: None,
+            client_id: None,
+            channel: None,
+            fee_override: None,
+        }),
+    )
+    .unwrap();
+
+    let res = reply(
         deps.as_mut(),
         mock_env(),
         Reply {
-            id: 1,
+            id: ReplyKind::Swap.reply_id(1),
             result: SubMsgResult::Err("Any error should do here".to_string()),
         },
     )
+    .unwrap();
+
+    // The full input, fee included, goes to the refund address
+    assert_eq!(res.messages.len(), 1);
+    assert!(is_valid_bank_send_msg(
+        &res.messages[0].msg,
+        "refund",
+        100u128.into(),
+        "uosmo"
+    ));
+
+    // The active swap has been deleted
+    let affiliate_swap = AffiliateSwap::new();
+    affiliate_swap
+        .active_swaps
+        .load(&deps.storage, 1)
+        .unwrap_err();
+
+    let event = res
+        .events
+        .iter()
+        .find(|e| e.ty == "affiliate_swap_failed")
+        .unwrap();
+    let event_attributes = event
+        .attributes
+        .iter()
+        .map(|a| (a.key.clone(), a.value.clone()))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(event_attributes["sender"], SENDER);
+    assert_eq!(event_attributes["swap_id"], "1");
+    assert_eq!(event_attributes["refund_address"], "refund");
+    assert_eq!(event_attributes["refund"], "100uosmo");
+    assert_eq!(event_attributes["route"], "1:uion");
+    assert_eq!(event_attributes["token_in"], "99uosmo");
+    assert_eq!(event_attributes["token_out_min"], "1uion");
+    assert_eq!(event_attributes["failure"], "unknown");
+    assert_eq!(event_attributes["reason"], "Any error should do here");
+}
+
+#[test]
+fn test_config_raw_layout() {
+    let deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+
+    // The config must be readable with a raw query on the documented key
+    let raw = deps.storage.get(CONFIG_KEY.as_bytes()).unwrap();
+    let config: Config = from_slice(&raw).unwrap();
+    assert_eq!(
+        config,
+        Config {
+            owner: Addr::unchecked(OWNER),
+            max_fee_bps: 500,
+            stale_swap_blocks: DEFAULT_STALE_SWAP_BLOCKS,
+            reject_self_referral: false,
+            max_route_length: DEFAULT_MAX_ROUTE_LENGTH,
+            router_pubkey: None,
+            fee_collector: None,
+            branding: None,
+            crank_tip_bps: 0,
+            rebate_bps: 0,
+            surplus_sharing: None,
+            referral_duration: 0,
+            sender_allowlist: false,
+            balance_checks: false,
+            swap_router: None,
+            fee_override_pubkey: None,
+            settlement_slippage_bps: DEFAULT_SETTLEMENT_SLIPPAGE_BPS,
+        }
+    );
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_pause() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+
+    // Only the owner can pause
+    let err = affiliate_swap
+        .pause((deps.as_mut(), mock_env(), mock_info(SENDER, &[])))
+        .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    affiliate_swap
+        .pause((deps.as_mut(), mock_env(), mock_info(OWNER, &[])))
+        .unwrap();
+
+    let res = affiliate_swap
+        .is_paused((deps.as_ref(), mock_env()))
+        .unwrap();
+    assert_eq!(
+        res,
+        IsPausedResponse {
+            paused: true,
+            paused_by: Some(Addr::unchecked(OWNER)),
+            paused_at: Some(mock_env().block.time),
+        }
+    );
+
+    // Swaps are rejected while paused
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(SENDER, &[Coin::new(100, "uosmo")]),
+        ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+            routes: vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uion".to_string(),
+            }],
+            token_out_min_amount: Some(Coin::new(1, "uion")),
+            fee_percentage: None,
+            fee_collector: Some(COLLECTOR.to_string()),
+            on_failure: None,
+            token_in_denom: None,
+            allow_cyclic: None,
+            receiver: None,
+            ibc_origin: None,
+            quote: None,
+            expected_out: None,
+            slippage_tolerance: None,
+            max_price_impact: None,
+            response_data: None,
+            with_data: None,
+            after: None,
+            client_id: None,
+            channel: None,
+            fee_override: None,
+        }),
+    )
     .unwrap_err();
+    assert_eq!(err, ContractError::Paused {});
+
+    affiliate_swap
+        .unpause((deps.as_mut(), mock_env(), mock_info(OWNER, &[])))
+        .unwrap();
+    let res = affiliate_swap
+        .is_paused((deps.as_ref(), mock_env()))
+        .unwrap();
+    assert!(!res.paused);
+
+    simple_execute(deps.as_mut(), 100, None);
+}
+
+#[test]
+fn test_supported_interfaces() {
+    let affiliate_swap = AffiliateSwap::new();
+    let deps = setup_unit(None);
+
+    let res = affiliate_swap
+        .get_supported_interfaces((deps.as_ref(), mock_env()))
+        .unwrap();
+    let interfaces = res
+        .supported_interfaces
+        .into_iter()
+        .map(|i| (i.supported_interface, i.version))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(interfaces.len(), 3);
+    assert_eq!(
+        interfaces["crates.io:affiliate_swap"],
+        env!("CARGO_PKG_VERSION")
+    );
+    assert!(interfaces.contains_key("crates.io:cw2"));
+    assert!(interfaces.contains_key("crates.io:cw22"));
+
+    // cw22 layout: readable with a raw query on the namespaced key
+    let mut key = vec![0, "supported_interfaces".len() as u8];
+    key.extend_from_slice(b"supported_interfaces");
+    key.extend_from_slice(b"crates.io:cw22");
+    let raw = deps.storage.get(&key).unwrap();
+    assert_eq!(from_slice::<String>(&raw).unwrap(), "0.2.0");
+}
+
+#[test]
+fn test_instantiate_config_event() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = mock_dependencies();
+    let res = affiliate_swap
+        .instantiate(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            Some(Decimal::from_str("5").unwrap()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+
+    let event = res
+        .events
+        .iter()
+        .find(|e| e.ty == "affiliate_swap_config")
+        .unwrap();
+    let event_attributes = event
+        .attributes
+        .iter()
+        .map(|a| (a.key.clone(), a.value.clone()))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(event_attributes["owner"], OWNER);
+    assert_eq!(event_attributes["max_fee_percentage"], "5");
+}
+
+#[test]
+fn test_config_changed_event() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    let changed_event = |res: &Response| {
+        res.events
+            .iter()
+            .find(|e| e.ty == "affiliate_swap_config_changed")
+            .unwrap()
+            .attributes
+            .iter()
+            .map(|a| (a.key.clone(), a.value.clone()))
+            .collect::<HashMap<_, _>>()
+    };
+
+    let res = affiliate_swap
+        .set_pair_fee(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            "uosmo".to_string(),
+            "uion".to_string(),
+            Some(PairFee::Fixed {
+                fee_percentage: Decimal::one(),
+            }),
+        )
+        .unwrap();
+    let event = changed_event(&res);
+    assert_eq!(event["key"], "pair_fee");
+    assert_eq!(event["token_in_denom"], "uosmo");
+    assert_eq!(event["token_out_denom"], "uion");
+    assert_eq!(event["fee_percentage"], "1");
+    assert!(!event.contains_key("method"));
+
+    let res = affiliate_swap
+        .pause((deps.as_mut(), mock_env(), mock_info(OWNER, &[])))
+        .unwrap();
+    let event = changed_event(&res);
+    assert_eq!(event["key"], "paused");
+    assert_eq!(event["paused"], "true");
+}
+
+#[test]
+fn test_migrate() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = mock_dependencies();
+
+    // State as written by the first version of the contract
+    cw2::set_contract_version(&mut deps.storage, "crates.io:affiliate_swap", "0.0.1").unwrap();
+    LEGACY_MAX_FEE
+        .save(&mut deps.storage, &Decimal::from_str("5").unwrap())
+        .unwrap();
+    // a 0.1.0 swap whose reply never ran
+    deps.storage.set(
+        LEGACY_ACTIVE_SWAP_KEY.as_bytes(),
+        br#"{"original_sender":"sender","fee":{"denom":"uosmo","amount":"1"}}"#,
+    );
+
+    // The owner is required as the legacy state has none
+    let err = affiliate_swap
+        .migrate((deps.as_mut(), mock_env()), None)
+        .unwrap_err();
+    assert_eq!(err, ContractError::MissingOwner {});
+
+    affiliate_swap
+        .migrate((deps.as_mut(), mock_env()), Some(OWNER.to_string()))
+        .unwrap();
+
+    assert_eq!(
+        affiliate_swap.config.load(&deps.storage).unwrap(),
+        Config {
+            owner: Addr::unchecked(OWNER),
+            max_fee_bps: 500,
+            stale_swap_blocks: DEFAULT_STALE_SWAP_BLOCKS,
+            reject_self_referral: false,
+            max_route_length: DEFAULT_MAX_ROUTE_LENGTH,
+            router_pubkey: None,
+            fee_collector: None,
+            branding: None,
+            crank_tip_bps: 0,
+            rebate_bps: 0,
+            surplus_sharing: None,
+            referral_duration: 0,
+            sender_allowlist: false,
+            balance_checks: false,
+            swap_router: None,
+            fee_override_pubkey: None,
+            settlement_slippage_bps: DEFAULT_SETTLEMENT_SLIPPAGE_BPS,
+        }
+    );
+    assert!(LEGACY_MAX_FEE.may_load(&deps.storage).unwrap().is_none());
+    assert!(deps
+        .storage
+        .get(LEGACY_ACTIVE_SWAP_KEY.as_bytes())
+        .is_none());
+
+    let version = cw2::get_contract_version(&deps.storage).unwrap();
+    assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+}
+
+#[test]
+fn test_reply_ids() {
+    let id = ReplyKind::Swap.reply_id(42);
+    assert_eq!(ReplyKind::parse(id).unwrap(), (ReplyKind::Swap, 42));
+
+    // ids without a known kind are rejected
+    assert_eq!(
+        ReplyKind::parse(42).unwrap_err(),
+        ContractError::UnknownReplyId { id: 42 }
+    );
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_reply_without_fee() {
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+
+    simple_execute(deps.as_mut(), 100, None);
+    let res = simple_reply(deps.as_mut(), 98);
+
+    // No fee transfer nor fee event
+    assert_eq!(res.messages.len(), 1);
+    assert!(is_valid_bank_send_msg(
+        &res.messages[0].msg,
+        SENDER,
+        98u128.into(),
+        "uion"
+    ));
+    assert!(!res.events.iter().any(|e| e.ty == "affiliate_fee_paid"));
+}
+
+#[test]
+fn test_max_fee_precision() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = mock_dependencies();
+
+    // The max fee is stored in basis points, finer percentages are rejected
+    let err = affiliate_swap
+        .instantiate(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            Some(Decimal::from_str("1.234").unwrap()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(err, ContractError::InvalidFeePrecision {});
+
+    affiliate_swap
+        .instantiate(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            Some(Decimal::from_str("1.23").unwrap()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+    let res = affiliate_swap
+        .get_config((deps.as_ref(), mock_env()))
+        .unwrap();
+    assert_eq!(res.max_fee_percentage, Decimal::from_str("1.23").unwrap());
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_clear_stale_swap() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+
+    // Two swaps whose replies never arrived, the second one a block later
+    simple_execute(deps.as_mut(), 100, Some(Decimal::from_str("1").unwrap()));
+    let mut env = mock_env();
+    env.block.height += 1;
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(SENDER, &[Coin::new(50, "uosmo")]),
+        ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+            routes: vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uion".to_string(),
+            }],
+            token_out_min_amount: Some(Coin::new(1, "uion")),
+            fee_percentage: None,
+            fee_collector: Some(COLLECTOR.to_string()),
+            on_failure: None,
+            token_in_denom: None,
+            allow_cyclic: None,
+            receiver: None,
+            ibc_origin: None,
+            quote: None,
+            expected_out: None,
+            slippage_tolerance: None,
+            max_price_impact: None,
+            response_data: None,
+            with_data: None,
+            after: None,
+            client_id: None,
+            channel: None,
+            fee_override: None,
+        }),
+    )
+    .unwrap();
+
+    // Nothing is stale yet
+    env.block.height += DEFAULT_STALE_SWAP_BLOCKS - 1;
+    let err = affiliate_swap
+        .clear_stale_swap((deps.as_mut(), env.clone(), mock_info("anyone", &[])))
+        .unwrap_err();
+    assert_eq!(err, ContractError::NoStaleSwap {});
+
+    // Only the first swap is old enough, its full input is refunded
+    env.block.height += 1;
+    let res = affiliate_swap
+        .clear_stale_swap((deps.as_mut(), env.clone(), mock_info("anyone", &[])))
+        .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert!(is_valid_bank_send_msg(
+        &res.messages[0].msg,
+        SENDER,
+        100u128.into(),
+        "uosmo"
+    ));
+    assert!(affiliate_swap
+        .active_swaps
+        .may_load(&deps.storage, 1)
+        .unwrap()
+        .is_none());
+    assert!(affiliate_swap
+        .active_swaps
+        .may_load(&deps.storage, 2)
+        .unwrap()
+        .is_some());
+
+    env.block.height += 1;
+    let res = affiliate_swap
+        .clear_stale_swap((deps.as_mut(), env, mock_info("anyone", &[])))
+        .unwrap();
+    assert!(is_valid_bank_send_msg(
+        &res.messages[0].msg,
+        SENDER,
+        50u128.into(),
+        "uosmo"
+    ));
+    assert!(affiliate_swap
+        .active_swaps
+        .may_load(&deps.storage, 2)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_empty_routes() {
+    let mut deps = setup_unit(None);
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(SENDER, &[Coin::new(100, "uosmo")]),
+        ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+            routes: vec![],
+            token_out_min_amount: Some(Coin::new(1, "uion")),
+            fee_percentage: None,
+            fee_collector: Some(COLLECTOR.to_string()),
+            on_failure: None,
+            token_in_denom: None,
+            allow_cyclic: None,
+            receiver: None,
+            ibc_origin: None,
+            quote: None,
+            expected_out: None,
+            slippage_tolerance: None,
+            max_price_impact: None,
+            response_data: None,
+            with_data: None,
+            after: None,
+            client_id: None,
+            channel: None,
+            fee_override: None,
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::EmptyRoutes {});
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_fee_collector_validation() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = mock_dependencies();
+    affiliate_swap
+        .instantiate(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            None,
+            None,
+            None,
+            Some(true),
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+
+    let swap_with_collector = |deps: DepsMut, fee_collector: &str| {
+        affiliate_swap.swap(
+            (
+                deps,
+                mock_env(),
+                mock_info(SENDER, &[Coin::new(100, "uosmo")]),
+            ),
+            vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uion".to_string(),
+            }],
+            Some(Coin::new(1, "uion")),
+            None,
+            Some(fee_collector.to_string()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    };
+
+    // Fees sent to the contract would be stranded
+    let contract = mock_env().contract.address;
+    let err = swap_with_collector(deps.as_mut(), contract.as_str()).unwrap_err();
+    assert_eq!(err, ContractError::FeeCollectorIsContract {});
+
+    // Self-referral is rejected when enabled
+    let err = swap_with_collector(deps.as_mut(), SENDER).unwrap_err();
+    assert_eq!(err, ContractError::SelfReferral {});
+
+    swap_with_collector(deps.as_mut(), COLLECTOR).unwrap();
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_extra_funds_refunded() {
+    let mut deps = setup_unit(None);
+    let swap_msg = |token_in_denom: Option<&str>| {
+        ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+            routes: vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uion".to_string(),
+            }],
+            token_out_min_amount: Some(Coin::new(1, "uion")),
+            fee_percentage: None,
+            fee_collector: Some(COLLECTOR.to_string()),
+            on_failure: None,
+            token_in_denom: token_in_denom.map(String::from),
+            allow_cyclic: None,
+            receiver: None,
+            ibc_origin: None,
+            quote: None,
+            expected_out: None,
+            slippage_tolerance: None,
+            max_price_impact: None,
+            response_data: None,
+            with_data: None,
+            after: None,
+            client_id: None,
+            channel: None,
+            fee_override: None,
+        })
+    };
+    let funds = [
+        Coin::new(100, "uosmo"),
+        Coin::new(5, "uatom"),
+        Coin::new(7, "ujuno"),
+    ];
+
+    // Without an input denom, only one coin can be sent
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(SENDER, &funds),
+        swap_msg(None),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::Payment(cw_utils::PaymentError::MultipleDenoms {})
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(SENDER, &funds),
+        swap_msg(Some("uion")),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::Payment(cw_utils::PaymentError::MissingDenom("uion".to_string()))
+    );
+
+    // The matching coin is swapped, the rest is sent back
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(SENDER, &funds),
+        swap_msg(Some("uosmo")),
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert!(is_valid_swap_msg(
+        &res.messages[0].msg,
+        Coin::new(100, "uosmo")
+    ));
+    assert_eq!(
+        res.messages[1].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: SENDER.to_string(),
+            amount: vec![Coin::new(5, "uatom"), Coin::new(7, "ujuno")],
+        })
+    );
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_failed_output_transfer() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+
+    simple_execute(deps.as_mut(), 100, None);
+    let res = simple_reply(deps.as_mut(), 98);
+    assert_eq!(res.messages[0].id, ReplyKind::OutputTransfer.reply_id(1));
+
+    // The recipient cannot receive funds: the output is escrowed instead of reverting
+    let res = reply(
+        deps.as_mut(),
+        mock_env(),
+        Reply {
+            id: ReplyKind::OutputTransfer.reply_id(1),
+            result: SubMsgResult::Err("blocked address".to_string()),
+        },
+    )
+    .unwrap();
+    assert!(res
+        .events
+        .iter()
+        .any(|e| e.ty == "affiliate_swap_output_escrowed"));
+
+    let claimable = affiliate_swap
+        .get_claimable((deps.as_ref(), mock_env()), SENDER.to_string())
+        .unwrap();
+    assert_eq!(claimable.coins, vec![Coin::new(98, "uion")]);
+
+    // The recipient claims to another address
+    let err = affiliate_swap
+        .claim(
+            (deps.as_mut(), mock_env(), mock_info(COLLECTOR, &[])),
+            None,
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(err, ContractError::NothingToClaim {});
+
+    let res = affiliate_swap
+        .claim(
+            (deps.as_mut(), mock_env(), mock_info(SENDER, &[])),
+            Some("other".to_string()),
+            None,
+        )
+        .unwrap();
+    assert!(is_valid_bank_send_msg(
+        &res.messages[0].msg,
+        "other",
+        98u128.into(),
+        "uion"
+    ));
+    assert_eq!(res.events[0].ty, "affiliate_swap_escrow_claimed");
+    let claimable = affiliate_swap
+        .get_claimable((deps.as_ref(), mock_env()), SENDER.to_string())
+        .unwrap();
+    assert!(claimable.coins.is_empty());
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_failed_fee_transfer() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+
+    simple_execute(deps.as_mut(), 100, Some(Decimal::one()));
+    let res = simple_reply(deps.as_mut(), 98);
+    let fee_msg = res
+        .messages
+        .iter()
+        .find(|m| m.id == ReplyKind::FeeTransfer.reply_id(1))
+        .unwrap();
+    assert_eq!(fee_msg.reply_on, ReplyOn::Always);
+    assert!(is_valid_bank_send_msg(
+        &fee_msg.msg,
+        COLLECTOR,
+        1u128.into(),
+        "uosmo"
+    ));
+
+    // The collector cannot receive funds (e.g. a module account): the fee is escrowed for it
+    // instead of reverting the swap
+    let res = reply(
+        deps.as_mut(),
+        mock_env(),
+        Reply {
+            id: ReplyKind::FeeTransfer.reply_id(1),
+            result: SubMsgResult::Err("blocked address".to_string()),
+        },
+    )
+    .unwrap();
+    let event = res
+        .events
+        .iter()
+        .find(|e| e.ty == "affiliate_fee_escrowed")
+        .unwrap();
+    assert!(!res.events.iter().any(|e| e.ty == "affiliate_fee_paid"));
+    assert!(event
+        .attributes
+        .iter()
+        .any(|a| a.key == "amount" && a.value == "1uosmo"));
+    let claimable = affiliate_swap
+        .get_claimable((deps.as_ref(), mock_env()), COLLECTOR.to_string())
+        .unwrap();
+    assert_eq!(claimable.coins, vec![Coin::new(1, "uosmo")]);
+    assert!(!affiliate_swap.pending_fees.has(&deps.storage, 1));
+
+    // a successful transfer escrows nothing
+    simple_execute(deps.as_mut(), 100, Some(Decimal::one()));
+    reply_with_id(deps.as_mut(), 2, 98);
+    let res = reply(
+        deps.as_mut(),
+        mock_env(),
+        Reply {
+            id: ReplyKind::FeeTransfer.reply_id(2),
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: None,
+            }),
+        },
+    )
+    .unwrap();
+    assert_eq!(res.events.len(), 1);
+    assert_eq!(res.events[0].ty, "affiliate_fee_paid");
+    assert!(!affiliate_swap.pending_fees.has(&deps.storage, 2));
+    let claimable = affiliate_swap
+        .get_claimable((deps.as_ref(), mock_env()), COLLECTOR.to_string())
+        .unwrap();
+    assert_eq!(claimable.coins, vec![Coin::new(1, "uosmo")]);
+
+    // the collector claims its escrowed fee like an escrowed output
+    let res = affiliate_swap
+        .claim(
+            (deps.as_mut(), mock_env(), mock_info(COLLECTOR, &[])),
+            None,
+            None,
+        )
+        .unwrap();
+    assert!(is_valid_bank_send_msg(
+        &res.messages[0].msg,
+        COLLECTOR,
+        1u128.into(),
+        "uosmo"
+    ));
+    assert_eq!(res.events[0].ty, "affiliate_swap_escrow_claimed");
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_claim_operators() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+    let claim = |deps: DepsMut, recipient: Option<&str>| {
+        affiliate_swap.claim(
+            (deps, mock_env(), mock_info("bot", &[])),
+            recipient.map(str::to_string),
+            Some(COLLECTOR.to_string()),
+        )
+    };
+
+    // 1uosmo of fee escrowed for the collector
+    simple_execute(deps.as_mut(), 100, Some(Decimal::one()));
+    simple_reply(deps.as_mut(), 98);
+    reply(
+        deps.as_mut(),
+        mock_env(),
+        Reply {
+            id: ReplyKind::FeeTransfer.reply_id(1),
+            result: SubMsgResult::Err("blocked address".to_string()),
+        },
+    )
+    .unwrap();
+
+    let err = claim(deps.as_mut(), None).unwrap_err();
+    assert_eq!(err, ContractError::NotClaimOperator {});
+    affiliate_swap
+        .set_claim_operator(
+            (deps.as_mut(), mock_env(), mock_info(COLLECTOR, &[])),
+            "bot".to_string(),
+            true,
+        )
+        .unwrap();
+    let operators = affiliate_swap
+        .get_claim_operators((deps.as_ref(), mock_env()), COLLECTOR.to_string())
+        .unwrap();
+    assert_eq!(operators.operators, vec![Addr::unchecked("bot")]);
+
+    // the operator cannot redirect the funds, they go to the collector
+    let err = claim(deps.as_mut(), Some("bot")).unwrap_err();
+    assert_eq!(err, ContractError::OperatorClaimRecipient {});
+    let res = claim(deps.as_mut(), None).unwrap();
+    assert!(is_valid_bank_send_msg(
+        &res.messages[0].msg,
+        COLLECTOR,
+        1u128.into(),
+        "uosmo"
+    ));
+    assert!(res.events[0]
+        .attributes
+        .iter()
+        .any(|a| a.key == "operator" && a.value == "bot"));
+    let claimable = affiliate_swap
+        .get_claimable((deps.as_ref(), mock_env()), COLLECTOR.to_string())
+        .unwrap();
+    assert!(claimable.coins.is_empty());
+
+    // a revoked operator cannot claim anymore
+    affiliate_swap
+        .set_claim_operator(
+            (deps.as_mut(), mock_env(), mock_info(COLLECTOR, &[])),
+            "bot".to_string(),
+            false,
+        )
+        .unwrap();
+    let err = claim(deps.as_mut(), None).unwrap_err();
+    assert_eq!(err, ContractError::NotClaimOperator {});
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_cyclic_route() {
+    let mut deps = setup_unit(None);
+    let swap_msg = |allow_cyclic: Option<bool>| {
+        ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+            routes: vec![
+                SwapAmountInRoute {
+                    pool_id: 1,
+                    token_out_denom: "uion".to_string(),
+                },
+                SwapAmountInRoute {
+                    pool_id: 2,
+                    token_out_denom: "uosmo".to_string(),
+                },
+            ],
+            token_out_min_amount: Some(Coin::new(1, "uosmo")),
+            fee_percentage: None,
+            fee_collector: Some(COLLECTOR.to_string()),
+            on_failure: None,
+            token_in_denom: None,
+            allow_cyclic,
+            receiver: None,
+            ibc_origin: None,
+            quote: None,
+            expected_out: None,
+            slippage_tolerance: None,
+            max_price_impact: None,
+            response_data: None,
+            with_data: None,
+            after: None,
+            client_id: None,
+            channel: None,
+            fee_override: None,
+        })
+    };
+    let info = mock_info(SENDER, &[Coin::new(100, "uosmo")]);
+
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), swap_msg(None)).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::CyclicRoute {
+            denom: "uosmo".to_string()
+        }
+    );
+
+    execute(deps.as_mut(), mock_env(), info, swap_msg(Some(true))).unwrap();
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_max_route_length() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    let swap_msg = |hops: u64| {
+        ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+            routes: (1..=hops)
+                .map(|pool_id| SwapAmountInRoute {
+                    pool_id,
+                    token_out_denom: "uion".to_string(),
+                })
+                .collect(),
+            token_out_min_amount: Some(Coin::new(1, "uion")),
+            fee_percentage: None,
+            fee_collector: Some(COLLECTOR.to_string()),
+            on_failure: None,
+            token_in_denom: None,
+            allow_cyclic: None,
+            receiver: None,
+            ibc_origin: None,
+            quote: None,
+            expected_out: None,
+            slippage_tolerance: None,
+            max_price_impact: None,
+            response_data: None,
+            with_data: None,
+            after: None,
+            client_id: None,
+            channel: None,
+            fee_override: None,
+        })
+    };
+    let info = mock_info(SENDER, &[Coin::new(100, "uosmo")]);
+
+    execute(deps.as_mut(), mock_env(), info.clone(), swap_msg(5)).unwrap();
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), swap_msg(6)).unwrap_err();
+    assert_eq!(err, ContractError::RouteTooLong { max: 5 });
+
+    // Only the owner can change the cap, which cannot be zero
+    let err = affiliate_swap
+        .update_config(
+            (deps.as_mut(), mock_env(), mock_info(SENDER, &[])),
+            None,
+            None,
+            Some(6),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    let err = affiliate_swap
+        .update_config(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            None,
+            None,
+            Some(0),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(err, ContractError::InvalidMaxRouteLength {});
+
+    let res = affiliate_swap
+        .update_config(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            None,
+            None,
+            Some(6),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+    assert!(res.events.iter().any(|e| e.ty == "affiliate_swap_config"));
+    execute(deps.as_mut(), mock_env(), info, swap_msg(6)).unwrap();
+
+    let config = affiliate_swap
+        .get_config((deps.as_ref(), mock_env()))
+        .unwrap();
+    assert_eq!(config.max_route_length, 6);
+    assert_eq!(config.stale_swap_blocks, DEFAULT_STALE_SWAP_BLOCKS);
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_fee_math_boundaries() {
+    let max = Uint128::MAX;
+
+    assert_eq!(fee_amount(max, Decimal::zero()).unwrap(), Uint128::zero());
+    assert_eq!(
+        fee_amount(max, Decimal::from_str("10").unwrap()).unwrap(),
+        Uint128::new(34028236692093846346337460743176821145)
+    );
+    assert_eq!(fee_amount(max, Decimal::percent(10000)).unwrap(), max);
+    // rounded down
+    assert_eq!(
+        fee_amount(Uint128::one(), Decimal::from_str("1.5").unwrap()).unwrap(),
+        Uint128::zero()
+    );
+    // a fee above the amount overflows instead of panicking
+    assert!(matches!(
+        fee_amount(max, Decimal::from_str("101").unwrap()).unwrap_err(),
+        ContractError::Overflow(_)
+    ));
+
+    // The largest possible input can be swapped
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+    let res = simple_execute(
+        deps.as_mut(),
+        u128::MAX,
+        Some(Decimal::from_str("5").unwrap()),
+    );
+    let fee = Uint128::new(17014118346046923173168730371588410572);
+    assert_eq!(stored_fee(&deps, 1), fee);
+    assert!(is_valid_swap_msg(
+        &res.messages[0].msg,
+        Coin {
+            denom: "uosmo".to_string(),
+            amount: max - fee,
+        }
+    ));
+}
+
+// Deterministic xorshift generator for the property tests, so that failures are reproducible
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    // amounts of every magnitude, up to Uint128::MAX
+    fn amount(&mut self) -> Uint128 {
+        let bits = self.below(129) as u32;
+        let value = (self.next_u64() as u128) << 64 | self.next_u64() as u128;
+        Uint128::new(value.checked_shr(128 - bits).unwrap_or_default())
+    }
+
+    // percentages with basis point precision, from 0 to 10%
+    fn fee_percentage(&mut self) -> Decimal {
+        Decimal::from_ratio(self.below(1001), 100u64)
+    }
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_fee_math_properties() {
+    let mut rng = Rng(0x5eed);
+
+    for _ in 0..10_000 {
+        let amount = rng.amount();
+        let fee_percentage = rng.fee_percentage();
+        let fee = fee_amount(amount, fee_percentage).unwrap();
+        assert!(fee <= amount, "{fee} > {amount} at {fee_percentage}%");
+        // rounded down, so a higher percentage never yields a lower fee
+        let higher = fee_percentage + Decimal::from_ratio(1u64, 100u64);
+        assert!(fee <= fee_amount(amount, higher).unwrap());
+    }
+
+    // Through the swap: the fee never exceeds the max fee and the input is fully accounted for
+    for _ in 0..200 {
+        let max_fee = rng.fee_percentage();
+        let fee_percentage = rng.fee_percentage();
+        let amount = rng.amount().max(Uint128::one());
+
+        let mut deps = setup_unit(Some(max_fee));
+        let res = simple_execute(deps.as_mut(), amount.u128(), Some(fee_percentage));
+        let fee = stored_fee(&deps, 1);
+        assert!(fee <= fee_amount(amount, max_fee).unwrap());
+        assert!(is_valid_swap_msg(
+            &res.messages[0].msg,
+            Coin {
+                denom: "uosmo".to_string(),
+                amount: amount - fee,
+            }
+        ));
+    }
+}
+
+#[test]
+fn test_error_codes() {
+    assert_eq!(
+        ContractError::Unauthorized {}.to_string(),
+        "[unauthorized] Unauthorized"
+    );
+    assert_eq!(
+        ContractError::RouteTooLong { max: 5 }.to_string(),
+        "[route_too_long] Routes cannot have more than 5 hops"
+    );
+    let err = ContractError::Payment(cw_utils::PaymentError::NoFunds {});
+    assert_eq!(err.code(), "payment");
+    assert_eq!(err.to_string(), "[payment] No funds sent");
+}
+
+#[test]
+fn test_deprecated_field_names() {
+    // Clients built against the old message still use `affiliate_address`
+    let msg: ExecMsg = from_slice(
+        br#"{"swap":{"routes":[{"pool_id":"1","token_out_denom":"uion"}],"token_out_min_amount":{"denom":"uion","amount":"1"},"affiliate_address":"collector"}}"#,
+    )
+    .unwrap();
+    match msg {
+        ExecMsg::Swap { fee_collector, .. } => {
+            assert_eq!(fee_collector.as_deref(), Some(COLLECTOR))
+        }
+        _ => panic!("expected a swap message"),
+    }
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_receiver() {
+    let mut deps = setup_unit(None);
+    let swap_msg = |receiver: &str| {
+        ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+            routes: vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uion".to_string(),
+            }],
+            token_out_min_amount: Some(Coin::new(1, "uion")),
+            fee_percentage: None,
+            fee_collector: Some(COLLECTOR.to_string()),
+            on_failure: None,
+            token_in_denom: None,
+            allow_cyclic: None,
+            receiver: Some(receiver.to_string()),
+            ibc_origin: None,
+            quote: None,
+            expected_out: None,
+            slippage_tolerance: None,
+            max_price_impact: None,
+            response_data: None,
+            with_data: None,
+            after: None,
+            client_id: None,
+            channel: None,
+            fee_override: None,
+        })
+    };
+    let info = mock_info(SENDER, &[Coin::new(100, "uosmo")]);
+
+    execute(deps.as_mut(), mock_env(), info, swap_msg("receiver")).unwrap();
+    let res = simple_reply(deps.as_mut(), 98);
+
+    // The output goes to the receiver rather than the sender
+    assert!(is_valid_bank_send_msg(
+        &res.messages[0].msg,
+        "receiver",
+        98u128.into(),
+        "uion"
+    ));
+}
+
+#[test]
+fn test_ibc_origin() {
+    let mut deps = setup_unit(None);
+
+    let intermediary = intermediate_sender("channel-0", "cosmos1sender");
+    assert_eq!(intermediary.len(), 32);
+    assert_eq!(
+        intermediary,
+        intermediate_sender("channel-0", "cosmos1sender")
+    );
+    assert_ne!(
+        intermediary,
+        intermediate_sender("channel-1", "cosmos1sender")
+    );
+
+    // The sender is not the intermediary address of the claimed origin
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(SENDER, &[Coin::new(100, "uosmo")]),
+        ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+            routes: vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uion".to_string(),
+            }],
+            token_out_min_amount: Some(Coin::new(1, "uion")),
+            fee_percentage: None,
+            fee_collector: Some(COLLECTOR.to_string()),
+            on_failure: None,
+            token_in_denom: None,
+            allow_cyclic: None,
+            receiver: Some("receiver".to_string()),
+            ibc_origin: Some(IbcOrigin {
+                channel: "channel-0".to_string(),
+                sender: "cosmos1sender".to_string(),
+            }),
+            quote: None,
+            expected_out: None,
+            slippage_tolerance: None,
+            max_price_impact: None,
+            response_data: None,
+            with_data: None,
+            after: None,
+            client_id: None,
+            channel: None,
+            fee_override: None,
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::InvalidIbcOrigin {});
+}
+
+#[test]
+fn test_wasm_memo() {
+    let msg = ExecMsg::Claim {
+        recipient: None,
+        claimant: None,
+    };
+    let memo = wasm_memo("contract", &msg).unwrap();
+    assert_eq!(
+        memo,
+        r#"{"wasm":{"contract":"contract","msg":{"claim":{"recipient":null,"claimant":null}}}}"#
+    );
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_route_quote() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+
+    let router_key = SigningKey::from_bytes(&[1u8; 32]).unwrap();
+    let router_pubkey = Binary::from(router_key.verifying_key().to_bytes().as_slice());
+
+    let err = affiliate_swap
+        .set_router_pubkey(
+            (deps.as_mut(), mock_env(), mock_info(SENDER, &[])),
+            Some(router_pubkey.clone()),
+        )
+        .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    let err = affiliate_swap
+        .set_router_pubkey(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            Some(Binary::from(&[2u8; 32])),
+        )
+        .unwrap_err();
+    assert_eq!(err, ContractError::InvalidRouterPubkey {});
+    affiliate_swap
+        .set_router_pubkey(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            Some(router_pubkey),
+        )
+        .unwrap();
+
+    let routes = vec![SwapAmountInRoute {
+        pool_id: 1,
+        token_out_denom: "uion".to_string(),
+    }];
+    let token_in = Coin::new(100, "uosmo");
+    let env = mock_env();
+    let sign = |token_out_min_amount: &Coin, expires_at: Timestamp| {
+        let payload = QuotePayload {
+            chain_id: &env.block.chain_id,
+            contract: env.contract.address.as_str(),
+            routes: &routes,
+            token_in: &token_in,
+            token_out_min_amount,
+            expires_at,
+        };
+        let signature: Signature = router_key.sign(&to_vec(&payload).unwrap());
+        RouteQuote {
+            expires_at,
+            signature: Binary::from(signature.as_ref()),
+        }
+    };
+    let swap = |deps: DepsMut, quote: Option<RouteQuote>| {
+        execute(
+            deps,
+            mock_env(),
+            mock_info(SENDER, std::slice::from_ref(&token_in)),
+            ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+                routes: routes.clone(),
+                token_out_min_amount: Some(Coin::new(90, "uion")),
+                fee_percentage: None,
+                fee_collector: Some(COLLECTOR.to_string()),
+                on_failure: None,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote,
+                expected_out: None,
+                slippage_tolerance: None,
+                max_price_impact: None,
+                response_data: None,
+                with_data: None,
+                after: None,
+                client_id: None,
+                channel: None,
+                fee_override: None,
+            }),
+        )
+    };
+    let expires_at = mock_env().block.time.plus_seconds(30);
+
+    let err = swap(deps.as_mut(), None).unwrap_err();
+    assert_eq!(err, ContractError::QuoteRequired {});
+
+    let quote = sign(&Coin::new(90, "uion"), mock_env().block.time);
+    let err = swap(deps.as_mut(), Some(quote)).unwrap_err();
+    assert_eq!(err, ContractError::QuoteExpired {});
+
+    // The quote was for a higher minimum output
+    let quote = sign(&Coin::new(95, "uion"), expires_at);
+    let err = swap(deps.as_mut(), Some(quote)).unwrap_err();
+    assert_eq!(err, ContractError::InvalidQuoteSignature {});
+
+    let quote = sign(&Coin::new(90, "uion"), expires_at);
+    swap(deps.as_mut(), Some(quote)).unwrap();
+
+    // Quotes are no longer required once the router is removed
+    affiliate_swap
+        .set_router_pubkey((deps.as_mut(), mock_env(), mock_info(OWNER, &[])), None)
+        .unwrap();
+    swap(deps.as_mut(), None).unwrap();
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_fee_override() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+
+    let override_key = SigningKey::from_bytes(&[3u8; 32]).unwrap();
+    let override_pubkey = Binary::from(override_key.verifying_key().to_bytes().as_slice());
+    let err = affiliate_swap
+        .set_fee_override_pubkey(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            Some(Binary::from(&[2u8; 32])),
+        )
+        .unwrap_err();
+    assert_eq!(err, ContractError::InvalidFeeOverridePubkey {});
+
+    let env = mock_env();
+    let sign = |fee_collector: &str, fee_percentage: &str, expires_at: Timestamp| {
+        let fee_percentage = Decimal::from_str(fee_percentage).unwrap();
+        let payload = FeeOverridePayload {
+            chain_id: &env.block.chain_id,
+            contract: env.contract.address.as_str(),
+            fee_collector,
+            fee_percentage,
+            expires_at,
+        };
+        let signature: Signature = override_key.sign(&to_vec(&payload).unwrap());
+        FeeOverride {
+            fee_percentage,
+            expires_at,
+            signature: Binary::from(signature.as_ref()),
+        }
+    };
+    let swap = |deps: DepsMut, fee_override: FeeOverride| {
+        execute(
+            deps,
+            mock_env(),
+            mock_info(SENDER, &[Coin::new(1000, "uosmo")]),
+            ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+                routes: vec![SwapAmountInRoute {
+                    pool_id: 1,
+                    token_out_denom: "uion".to_string(),
+                }],
+                token_out_min_amount: Some(Coin::new(1, "uion")),
+                fee_percentage: Some(Decimal::one()),
+                fee_collector: Some(COLLECTOR.to_string()),
+                on_failure: None,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote: None,
+                expected_out: None,
+                slippage_tolerance: None,
+                max_price_impact: None,
+                response_data: None,
+                with_data: None,
+                after: None,
+                client_id: None,
+                channel: None,
+                fee_override: Some(fee_override),
+            }),
+        )
+    };
+    let expires_at = mock_env().block.time.plus_seconds(30);
+
+    let err = swap(deps.as_mut(), sign(COLLECTOR, "8", expires_at)).unwrap_err();
+    assert_eq!(err, ContractError::FeeOverrideNotEnabled {});
+    affiliate_swap
+        .set_fee_override_pubkey(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            Some(override_pubkey),
+        )
+        .unwrap();
+
+    // signed for another deal
+    let err = swap(deps.as_mut(), sign("other", "8", expires_at)).unwrap_err();
+    assert_eq!(err, ContractError::InvalidFeeOverrideSignature {});
+    let mut tampered = sign(COLLECTOR, "8", expires_at);
+    tampered.fee_percentage = Decimal::from_str("9").unwrap();
+    let err = swap(deps.as_mut(), tampered).unwrap_err();
+    assert_eq!(err, ContractError::InvalidFeeOverrideSignature {});
+    let err = swap(deps.as_mut(), sign(COLLECTOR, "8", mock_env().block.time)).unwrap_err();
+    assert_eq!(err, ContractError::FeeOverrideExpired {});
+    // the hard cap still applies
+    let err = swap(deps.as_mut(), sign(COLLECTOR, "10.5", expires_at)).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::FeeOverrideTooHigh {
+            true_max_fee: TRUE_MAX_FEE.to_string()
+        }
+    );
+
+    // 8% above the max fee of 5%, instead of the 1% requested
+    let res = swap(deps.as_mut(), sign(COLLECTOR, "8", expires_at)).unwrap();
+    assert!(is_valid_swap_msg(
+        &res.messages[0].msg,
+        Coin::new(920, "uosmo")
+    ));
+    let res = simple_reply(deps.as_mut(), 900);
+    let swap_event = res
+        .events
+        .iter()
+        .find(|e| e.ty == "affiliate_swap")
+        .unwrap();
+    let attributes = swap_event
+        .attributes
+        .iter()
+        .map(|a| (a.key.clone(), a.value.clone()))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(attributes["fee"], "80uosmo");
+    assert_eq!(attributes["fee_override"], "true");
+}
+
+#[test]
+fn test_create_child() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    deps.querier.update_wasm(|query| match query {
+        WasmQuery::ContractInfo { .. } => {
+            let mut info = ContractInfoResponse::default();
+            info.code_id = 7;
+            SystemResult::Ok(ContractResult::Ok(to_binary(&info).unwrap()))
+        }
+        _ => panic!("unexpected query"),
+    });
+    let branding = Branding {
+        name: "Brand".to_string(),
+        website: Some("https://brand.example".to_string()),
+        logo_uri: None,
+    };
+    let create_child = |deps: DepsMut, sender: &str, label: &str| {
+        affiliate_swap.create_child(
+            (deps, mock_env(), mock_info(sender, &[])),
+            label.to_string(),
+            COLLECTOR.to_string(),
+            branding.clone(),
+            Some(Decimal::from_str("0.5").unwrap()),
+            None,
+        )
+    };
+
+    let err = create_child(deps.as_mut(), SENDER, "brand").unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    let err = create_child(deps.as_mut(), OWNER, "").unwrap_err();
+    assert_eq!(err, ContractError::InvalidChildLabel {});
+
+    let res = create_child(deps.as_mut(), OWNER, "brand").unwrap();
+    assert_eq!(res.messages[0].id, ReplyKind::ChildInstantiate.reply_id(1));
+    match &res.messages[0].msg {
+        CosmosMsg::Wasm(WasmMsg::Instantiate2 {
+            admin,
+            code_id,
+            msg,
+            salt,
+            ..
+        }) => {
+            assert_eq!(admin.as_deref(), Some(OWNER));
+            assert_eq!(*code_id, 7);
+            assert_eq!(salt.as_slice(), b"brand");
+            let msg: InstantiateMsg = from_binary(msg).unwrap();
+            assert_eq!(msg.owner.as_deref(), Some(OWNER));
+            assert_eq!(msg.fee_collector.as_deref(), Some(COLLECTOR));
+            assert_eq!(msg.branding, Some(branding.clone()));
+        }
+        msg => panic!("unexpected message {msg:?}"),
+    }
+
+    // MsgInstantiateContract2Response { address: "child" }
+    let res = reply(
+        deps.as_mut(),
+        mock_env(),
+        Reply {
+            id: ReplyKind::ChildInstantiate.reply_id(1),
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: Some(Binary::from(b"\x0a\x05child")),
+            }),
+        },
+    )
+    .unwrap();
+    assert!(res
+        .events
+        .iter()
+        .any(|e| e.ty == "affiliate_swap_child_created"));
+
+    let children = affiliate_swap
+        .list_children((deps.as_ref(), mock_env()), None, None)
+        .unwrap()
+        .children;
+    assert_eq!(children.len(), 1);
+    assert_eq!(children[0].address, Addr::unchecked("child"));
+    assert_eq!(children[0].label, "brand");
+    assert_eq!(children[0].branding, branding);
+    let children = affiliate_swap
+        .list_children((deps.as_ref(), mock_env()), Some("child".to_string()), None)
+        .unwrap()
+        .children;
+    assert!(children.is_empty());
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_fixed_fee_collector() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = mock_dependencies();
+    affiliate_swap
+        .instantiate(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(COLLECTOR.to_string()),
+            None,
+        )
+        .unwrap();
+    let swap = |deps: DepsMut, fee_collector: &str| {
+        affiliate_swap.swap(
+            (
+                deps,
+                mock_env(),
+                mock_info(SENDER, &[Coin::new(100, "uosmo")]),
+            ),
+            vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uion".to_string(),
+            }],
+            Some(Coin::new(1, "uion")),
+            None,
+            Some(fee_collector.to_string()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    };
+
+    let err = swap(deps.as_mut(), "other").unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::FeeCollectorMismatch {
+            expected: COLLECTOR.to_string()
+        }
+    );
+    swap(deps.as_mut(), COLLECTOR).unwrap();
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_pair_fee() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    let set_pair_fee = |deps: DepsMut, sender: &str, token_in: &str, pair_fee: Option<PairFee>| {
+        affiliate_swap.set_pair_fee(
+            (deps, mock_env(), mock_info(sender, &[])),
+            token_in.to_string(),
+            "uion".to_string(),
+            pair_fee,
+        )
+    };
+    // fee charged on a swap of 1000uosmo to uion requesting a 5% fee
+    let swap_fee = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier, Empty>| {
+        simple_execute(deps.as_mut(), 1000, Some(Decimal::from_str("5").unwrap()));
+        let swap_id = affiliate_swap.swap_count.load(&deps.storage).unwrap();
+        affiliate_swap
+            .active_swaps
+            .load(&deps.storage, swap_id)
+            .unwrap()
+            .fee
+            .amount
+    };
+    let max = PairFee::Max {
+        max_fee_percentage: Decimal::from_str("3").unwrap(),
+    };
+
+    let err = set_pair_fee(deps.as_mut(), SENDER, "uosmo", Some(max.clone())).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    let err = set_pair_fee(
+        deps.as_mut(),
+        OWNER,
+        "uosmo",
+        Some(PairFee::Fixed {
+            fee_percentage: Decimal::from_str("10.5").unwrap(),
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidMaxFeePercentage {
+            true_max_fee: "10".to_string()
+        }
+    );
+    let err = set_pair_fee(
+        deps.as_mut(),
+        OWNER,
+        "uosmo",
+        Some(PairFee::Max {
+            max_fee_percentage: Decimal::from_str("0.005").unwrap(),
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::InvalidFeePrecision {});
+
+    // without an override, the max fee of the config applies
+    assert_eq!(swap_fee(&mut deps), Uint128::new(15));
+
+    // the pair max fee replaces the max fee of the config, even when higher
+    set_pair_fee(deps.as_mut(), OWNER, "uosmo", Some(max.clone())).unwrap();
+    assert_eq!(swap_fee(&mut deps), Uint128::new(30));
+
+    // a fixed fee is charged whatever the requested fee
+    let fixed = PairFee::Fixed {
+        fee_percentage: Decimal::from_str("0.5").unwrap(),
+    };
+    set_pair_fee(deps.as_mut(), OWNER, "uosmo", Some(fixed.clone())).unwrap();
+    assert_eq!(swap_fee(&mut deps), Uint128::new(5));
+
+    set_pair_fee(deps.as_mut(), OWNER, "uatom", Some(max.clone())).unwrap();
+    let pair_fee = affiliate_swap
+        .get_pair_fee(
+            (deps.as_ref(), mock_env()),
+            "uosmo".to_string(),
+            "uion".to_string(),
+        )
+        .unwrap();
+    assert_eq!(pair_fee.pair_fee, Some(fixed.clone()));
+    let list = affiliate_swap
+        .list_pair_fees((deps.as_ref(), mock_env()), None, None)
+        .unwrap();
+    assert_eq!(
+        list.pair_fees,
+        vec![
+            PairFeeEntry {
+                token_in_denom: "uatom".to_string(),
+                token_out_denom: "uion".to_string(),
+                pair_fee: max,
+            },
+            PairFeeEntry {
+                token_in_denom: "uosmo".to_string(),
+                token_out_denom: "uion".to_string(),
+                pair_fee: fixed,
+            },
+        ]
+    );
+    let list = affiliate_swap
+        .list_pair_fees(
+            (deps.as_ref(), mock_env()),
+            Some(("uatom".to_string(), "uion".to_string())),
+            None,
+        )
+        .unwrap();
+    assert_eq!(list.pair_fees.len(), 1);
+
+    // removing the override restores the max fee of the config
+    set_pair_fee(deps.as_mut(), OWNER, "uosmo", None).unwrap();
+    assert_eq!(swap_fee(&mut deps), Uint128::new(15));
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_volume_discounts() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+    let set_discounts = |deps: DepsMut, sender: &str, discounts: &[(u128, &str)]| {
+        affiliate_swap.set_volume_discounts(
+            (deps, mock_env(), mock_info(sender, &[])),
+            "uosmo".to_string(),
+            discounts
+                .iter()
+                .map(|(min_volume, discount)| VolumeDiscount {
+                    min_volume: Uint128::new(*min_volume),
+                    discount: Decimal::from_str(discount).unwrap(),
+                })
+                .collect(),
+        )
+    };
+    let brackets = [(1000, "0.5"), (3000, "1")];
+
+    let err = set_discounts(deps.as_mut(), SENDER, &brackets).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    for invalid in [[(1000, "0.5"), (1000, "1")], [(1000, "0.5"), (3000, "1.5")]] {
+        let err = set_discounts(deps.as_mut(), OWNER, &invalid).unwrap_err();
+        assert_eq!(err, ContractError::InvalidVolumeDiscounts {});
+    }
+    set_discounts(deps.as_mut(), OWNER, &brackets).unwrap();
+
+    // below the first bracket the full 1% fee is charged, and the full input counts
+    simple_execute(deps.as_mut(), 1000, Some(Decimal::one()));
+    assert_eq!(stored_fee(&deps, 1), Uint128::new(10));
+    reply_with_id(deps.as_mut(), 1, 900);
+
+    // half the fee is waived from 1000uosmo of volume
+    simple_execute(deps.as_mut(), 1000, Some(Decimal::one()));
+    assert_eq!(stored_fee(&deps, 2), Uint128::new(5));
+    reply_with_id(deps.as_mut(), 2, 900);
+    let volume = affiliate_swap
+        .get_volume((deps.as_ref(), mock_env()), SENDER.to_string())
+        .unwrap();
+    assert_eq!(volume.volume, vec![Coin::new(2000, "uosmo")]);
+
+    // the fee is waived entirely from 3000uosmo, for this sender only
+    simple_execute(deps.as_mut(), 1000, Some(Decimal::one()));
+    reply_with_id(deps.as_mut(), 3, 900);
+    simple_execute(deps.as_mut(), 1000, Some(Decimal::one()));
+    assert_eq!(stored_fee(&deps, 4), Uint128::zero());
+    affiliate_swap
+        .swap(
+            (
+                deps.as_mut(),
+                mock_env(),
+                mock_info("other", &[Coin::new(1000, "uosmo")]),
+            ),
+            vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uion".to_string(),
+            }],
+            Some(Coin::new(1, "uion")),
+            Some(Decimal::one()),
+            Some(COLLECTOR.to_string()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+    assert_eq!(stored_fee(&deps, 5), Uint128::new(10));
+
+    // without brackets the full fee is charged again
+    set_discounts(deps.as_mut(), OWNER, &[]).unwrap();
+    let discounts = affiliate_swap
+        .get_volume_discounts((deps.as_ref(), mock_env()), "uosmo".to_string())
+        .unwrap();
+    assert!(discounts.discounts.is_empty());
+    simple_execute(deps.as_mut(), 1000, Some(Decimal::one()));
+    assert_eq!(stored_fee(&deps, 6), Uint128::new(10));
+}
+
+#[test]
+#[cfg(all(feature = "stats", not(feature = "astroport")))]
+fn test_daily_stats() {
+    use crate::contract::{DailyStats, Stats};
+
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+    let day = mock_env().block.time.seconds() / 86_400;
+    let reply_on_day = |deps: DepsMut, swap_id: u64, day: u64| {
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_seconds(day * 86_400 + 1);
+        reply(
+            deps,
+            env,
+            Reply {
+                id: ReplyKind::Swap.reply_id(swap_id),
+                result: SubMsgResult::Ok(SubMsgResponse {
+                    events: vec![],
+                    data: Some(
+                        MsgSwapExactAmountInResponse {
+                            token_out_amount: "90".to_string(),
+                        }
+                        .into(),
+                    ),
+                }),
+            },
+        )
+        .unwrap();
+    };
+
+    // two swaps on the first day, one on the next
+    simple_execute(deps.as_mut(), 100, Some(Decimal::one()));
+    reply_on_day(deps.as_mut(), 1, day);
+    simple_execute(deps.as_mut(), 1000, Some(Decimal::one()));
+    reply_on_day(deps.as_mut(), 2, day);
+    simple_execute(deps.as_mut(), 1000, Some(Decimal::from_str("2").unwrap()));
+    reply_on_day(deps.as_mut(), 3, day + 1);
+
+    let list = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier, Empty>,
+                from_day,
+                to_day,
+                denom: Option<&str>,
+                start_after| {
+        affiliate_swap
+            .list_daily_stats(
+                (deps.as_ref(), mock_env()),
+                from_day,
+                to_day,
+                denom.map(str::to_string),
+                start_after,
+                None,
+            )
+            .unwrap()
+            .stats
+    };
+    let first_day = DailyStats {
+        day,
+        denom: "uosmo".to_string(),
+        volume: Uint128::new(1100),
+        fees: Uint128::new(11),
+        swap_count: 2,
+    };
+    let next_day = DailyStats {
+        day: day + 1,
+        denom: "uosmo".to_string(),
+        volume: Uint128::new(1000),
+        fees: Uint128::new(20),
+        swap_count: 1,
+    };
+    assert_eq!(
+        list(&deps, None, None, None, None),
+        vec![first_day.clone(), next_day.clone()]
+    );
+    assert_eq!(
+        list(&deps, Some(day + 1), None, None, None),
+        vec![next_day.clone()]
+    );
+    assert_eq!(
+        list(&deps, None, Some(day), None, None),
+        vec![first_day.clone()]
+    );
+    assert_eq!(
+        list(&deps, None, None, None, Some((day, "uosmo".to_string()))),
+        vec![next_day.clone()]
+    );
+    assert!(list(&deps, Some(day + 2), None, None, None).is_empty());
+
+    // the time series of one denom skips the others
+    affiliate_swap
+        .daily_stats
+        .save(
+            &mut deps.storage,
+            (day, "uatom"),
+            &Stats {
+                volume: Uint128::new(50),
+                fees: Uint128::new(1),
+                swap_count: 1,
+            },
+        )
+        .unwrap();
+    assert_eq!(list(&deps, None, None, None, None).len(), 3);
+    assert_eq!(
+        list(&deps, None, None, Some("uosmo"), None),
+        vec![first_day.clone(), next_day.clone()]
+    );
+    assert_eq!(
+        list(
+            &deps,
+            None,
+            None,
+            Some("uosmo"),
+            Some((day, "uosmo".to_string()))
+        ),
+        vec![next_day]
+    );
+    assert_eq!(
+        list(&deps, Some(day), Some(day), Some("uatom"), None)[0].volume,
+        Uint128::new(50)
+    );
+    assert!(list(&deps, Some(day + 1), None, Some("uatom"), None).is_empty());
+
+    // the same totals per fee collector, for leaderboards over a day or the series of one
+    // collector
+    affiliate_swap
+        .collector_stats
+        .save(
+            &mut deps.storage,
+            (day, &Addr::unchecked("other"), "uosmo"),
+            &Stats {
+                volume: Uint128::new(70),
+                fees: Uint128::new(2),
+                swap_count: 1,
+            },
+        )
+        .unwrap();
+    let list_collector = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier, Empty>,
+                          fee_collector: Option<&str>,
+                          start_after: Option<(u64, &str)>| {
+        affiliate_swap
+            .list_collector_stats(
+                (deps.as_ref(), mock_env()),
+                None,
+                Some(day + 1),
+                fee_collector.map(str::to_string),
+                start_after.map(|(day, fee_collector)| {
+                    (day, fee_collector.to_string(), "uosmo".to_string())
+                }),
+                None,
+            )
+            .unwrap()
+            .stats
+            .into_iter()
+            .map(|stats| {
+                (
+                    stats.day,
+                    stats.fee_collector.into_string(),
+                    stats.volume.u128(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        list_collector(&deps, None, None),
+        vec![
+            // the shorter address first within a day, as they are length-prefixed in the keys
+            (day, "other".to_string(), 70),
+            (day, COLLECTOR.to_string(), 1100),
+            (day + 1, COLLECTOR.to_string(), 1000),
+        ]
+    );
+    assert_eq!(
+        list_collector(&deps, Some(COLLECTOR), None),
+        vec![
+            (day, COLLECTOR.to_string(), 1100),
+            (day + 1, COLLECTOR.to_string(), 1000),
+        ]
+    );
+    assert_eq!(
+        list_collector(&deps, Some(COLLECTOR), Some((day, COLLECTOR))),
+        vec![(day + 1, COLLECTOR.to_string(), 1000)]
+    );
+    assert_eq!(
+        list_collector(&deps, Some("other"), None),
+        vec![(day, "other".to_string(), 70)]
+    );
+}
+
+#[test]
+#[cfg(all(feature = "stats", not(feature = "astroport")))]
+fn test_channel_stats() {
+    use crate::contract::{DailyStats, MAX_CHANNEL_LENGTH};
+
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+    let swap = |deps: DepsMut, amount: u128, channel: Option<&str>| {
+        execute(
+            deps,
+            mock_env(),
+            mock_info(SENDER, &[Coin::new(amount, "uosmo")]),
+            ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+                routes: vec![SwapAmountInRoute {
+                    pool_id: 1,
+                    token_out_denom: "uion".to_string(),
+                }],
+                token_out_min_amount: Some(Coin::new(1, "uion")),
+                fee_percentage: Some(Decimal::one()),
+                fee_collector: Some(COLLECTOR.to_string()),
+                on_failure: None,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote: None,
+                expected_out: None,
+                slippage_tolerance: None,
+                max_price_impact: None,
+                response_data: None,
+                with_data: None,
+                after: None,
+                client_id: None,
+                channel: channel.map(str::to_string),
+                fee_override: None,
+            }),
+        )
+    };
+
+    for channel in ["", &"x".repeat(MAX_CHANNEL_LENGTH + 1)] {
+        let err = swap(deps.as_mut(), 100, Some(channel)).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InvalidChannel {
+                max: MAX_CHANNEL_LENGTH
+            }
+        );
+    }
+
+    // untagged swaps only count in the daily stats
+    swap(deps.as_mut(), 100, Some("mobile")).unwrap();
+    let res = reply_with_id(deps.as_mut(), 1, 90);
+    let swap_event = res
+        .events
+        .iter()
+        .find(|e| e.ty == "affiliate_swap")
+        .unwrap();
+    assert!(swap_event
+        .attributes
+        .iter()
+        .any(|a| a.key == "channel" && a.value == "mobile"));
+    swap(deps.as_mut(), 1000, Some("widget-v2")).unwrap();
+    reply_with_id(deps.as_mut(), 2, 900);
+    swap(deps.as_mut(), 1000, None).unwrap();
+    reply_with_id(deps.as_mut(), 3, 900);
+
+    let day = mock_env().block.time.seconds() / 86_400;
+    let list = |channel: &str| {
+        affiliate_swap
+            .list_channel_stats(
+                (deps.as_ref(), mock_env()),
+                channel.to_string(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap()
+            .stats
+    };
+    assert_eq!(
+        list("mobile"),
+        vec![DailyStats {
+            day,
+            denom: "uosmo".to_string(),
+            volume: Uint128::new(100),
+            fees: Uint128::new(1),
+            swap_count: 1,
+        }]
+    );
+    assert_eq!(list("widget-v2")[0].volume, Uint128::new(1000));
+    assert!(list("desktop").is_empty());
+    let daily_stats = affiliate_swap
+        .list_daily_stats((deps.as_ref(), mock_env()), None, None, None, None, None)
+        .unwrap()
+        .stats;
+    assert_eq!(daily_stats[0].swap_count, 3);
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_denom_metadata() {
+    use crate::contract::{DenomMetadata, MAX_DENOM_DECIMALS, MAX_SYMBOL_LENGTH};
+
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+    let set = |deps: DepsMut, sender: &str, symbol: &str, decimals: u32| {
+        affiliate_swap.set_denom_metadata(
+            (deps, mock_env(), mock_info(sender, &[])),
+            "uion".to_string(),
+            Some(DenomMetadata {
+                symbol: symbol.to_string(),
+                decimals,
+            }),
+        )
+    };
+    let err = set(deps.as_mut(), SENDER, "ION", 6).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    for (symbol, decimals) in [
+        ("", 6),
+        (&*"X".repeat(MAX_SYMBOL_LENGTH + 1), 6),
+        ("ION", MAX_DENOM_DECIMALS + 1),
+    ] {
+        let err = set(deps.as_mut(), OWNER, symbol, decimals).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InvalidDenomMetadata {
+                max_symbol_length: MAX_SYMBOL_LENGTH,
+                max_decimals: MAX_DENOM_DECIMALS,
+            }
+        );
+    }
+    set(deps.as_mut(), OWNER, "ION", 6).unwrap();
+    let ion = DenomMetadata {
+        symbol: "ION".to_string(),
+        decimals: 6,
+    };
+    let metadata = affiliate_swap
+        .get_denom_metadata((deps.as_ref(), mock_env()), "uion".to_string())
+        .unwrap()
+        .metadata;
+    assert_eq!(metadata, Some(ion.clone()));
+
+    // only the output denom has metadata
+    simple_execute(deps.as_mut(), 100, Some(Decimal::one()));
+    let res = simple_reply(deps.as_mut(), 98);
+    let swap_event = res
+        .events
+        .iter()
+        .find(|e| e.ty == "affiliate_swap")
+        .unwrap();
+    let attributes = swap_event
+        .attributes
+        .iter()
+        .map(|a| (a.key.as_str(), a.value.as_str()))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(attributes["token_out_symbol"], "ION");
+    assert_eq!(attributes["token_out_decimals"], "6");
+    assert!(!attributes.contains_key("swap_token_in_symbol"));
+    let response: SwapResponse = from_binary(&res.data.unwrap()).unwrap();
+    assert_eq!(response.swap_in_metadata, None);
+    assert_eq!(response.token_out_metadata, Some(ion));
+
+    affiliate_swap
+        .set_denom_metadata(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            "uion".to_string(),
+            None,
+        )
+        .unwrap();
+    let metadata = affiliate_swap
+        .get_denom_metadata((deps.as_ref(), mock_env()), "uion".to_string())
+        .unwrap()
+        .metadata;
+    assert_eq!(metadata, None);
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_crank_tip() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+    let set_tip = |deps: DepsMut, tip: &str| {
+        affiliate_swap.update_config(
+            (deps, mock_env(), mock_info(OWNER, &[])),
+            None,
+            None,
+            None,
+            Some(Decimal::from_str(tip).unwrap()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    };
+
+    let err = set_tip(deps.as_mut(), "1.5").unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::CrankTipTooHigh {
+            max: "1".to_string()
+        }
+    );
+    let err = set_tip(deps.as_mut(), "0.005").unwrap_err();
+    assert_eq!(err, ContractError::InvalidFeePrecision {});
+    set_tip(deps.as_mut(), "0.5").unwrap();
+    let config = affiliate_swap
+        .get_config((deps.as_ref(), mock_env()))
+        .unwrap();
+    assert_eq!(
+        config.crank_tip_percentage,
+        Decimal::from_str("0.5").unwrap()
+    );
+
+    // Two stale swaps: each refund pays 0.5% of the full input to the caller
+    simple_execute(deps.as_mut(), 1000, Some(Decimal::one()));
+    simple_execute(deps.as_mut(), 2000, None);
+    let mut env = mock_env();
+    env.block.height += DEFAULT_STALE_SWAP_BLOCKS + 1;
+    let res = affiliate_swap
+        .clear_stale_swap((deps.as_mut(), env, mock_info("cranker", &[])))
+        .unwrap();
+    assert_eq!(res.messages.len(), 3);
+    assert!(is_valid_bank_send_msg(
+        &res.messages[0].msg,
+        SENDER,
+        995u128.into(),
+        "uosmo"
+    ));
+    assert!(is_valid_bank_send_msg(
+        &res.messages[1].msg,
+        SENDER,
+        1990u128.into(),
+        "uosmo"
+    ));
+    assert!(is_valid_bank_send_msg(
+        &res.messages[2].msg,
+        "cranker",
+        15u128.into(),
+        "uosmo"
+    ));
+    let cleared = res
+        .events
+        .iter()
+        .find(|e| e.ty == "affiliate_swap_cleared")
+        .unwrap();
+    assert!(cleared
+        .attributes
+        .iter()
+        .any(|a| a.key == "tip" && a.value == "5"));
+}
+
+/// `(share, recipient, amount)` of the `affiliate_fee_share` events of `res`
+fn fee_shares(res: &Response) -> Vec<(String, String, String)> {
+    res.events
+        .iter()
+        .filter(|e| e.ty == "affiliate_fee_share")
+        .map(|e| {
+            let attribute = |key: &str| {
+                e.attributes
+                    .iter()
+                    .find(|a| a.key == key)
+                    .unwrap()
+                    .value
+                    .clone()
+            };
+            (
+                attribute("share"),
+                attribute("recipient"),
+                attribute("amount"),
+            )
+        })
+        .collect()
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_rebate() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+    let set_rebate = |deps: DepsMut, rebate: &str| {
+        affiliate_swap.update_config(
+            (deps, mock_env(), mock_info(OWNER, &[])),
+            None,
+            None,
+            None,
+            None,
+            Some(Decimal::from_str(rebate).unwrap()),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    };
+
+    let err = set_rebate(deps.as_mut(), "100.01").unwrap_err();
+    assert_eq!(err, ContractError::InvalidRebatePercentage {});
+    set_rebate(deps.as_mut(), "25").unwrap();
+
+    // A quarter of the 10uosmo fee, rounded down, goes back to the swapper with the output
+    simple_execute(deps.as_mut(), 1000, Some(Decimal::one()));
+    let res = simple_reply(deps.as_mut(), 900);
+    assert_eq!(res.messages.len(), 2);
+    assert!(is_valid_bank_send_msg(
+        &res.messages[0].msg,
+        COLLECTOR,
+        8u128.into(),
+        "uosmo"
+    ));
+    assert_eq!(
+        res.messages[1].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: SENDER.to_string(),
+            amount: vec![Coin::new(900, "uion"), Coin::new(2, "uosmo")],
+        })
+    );
+    let swap_event = res
+        .events
+        .iter()
+        .find(|e| e.ty == "affiliate_swap")
+        .unwrap();
+    assert!(swap_event
+        .attributes
+        .iter()
+        .any(|a| a.key == "rebate" && a.value == "2uosmo"));
+    assert_eq!(
+        fee_shares(&res),
+        vec![
+            (
+                "fee_collector".to_string(),
+                COLLECTOR.to_string(),
+                "8uosmo".to_string()
+            ),
+            (
+                "rebate".to_string(),
+                SENDER.to_string(),
+                "2uosmo".to_string()
+            ),
+        ]
+    );
+
+    // The rebate is escrowed with the output if the transfer fails
+    reply(
+        deps.as_mut(),
+        mock_env(),
+        Reply {
+            id: ReplyKind::OutputTransfer.reply_id(1),
+            result: SubMsgResult::Err("blocked address".to_string()),
+        },
+    )
+    .unwrap();
+    let claimable = affiliate_swap
+        .get_claimable((deps.as_ref(), mock_env()), SENDER.to_string())
+        .unwrap();
+    assert_eq!(
+        claimable.coins,
+        vec![Coin::new(900, "uion"), Coin::new(2, "uosmo")]
+    );
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_surplus_sharing() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    let set_sharing =
+        |deps: DepsMut, sender: &str, shares: (&str, &str), treasury: Option<&str>| {
+            affiliate_swap.set_surplus_sharing(
+                (deps, mock_env(), mock_info(sender, &[])),
+                Some(Decimal::from_str(shares.0).unwrap()),
+                Some(Decimal::from_str(shares.1).unwrap()),
+                treasury.map(str::to_string),
+            )
+        };
+    let swap = |deps: DepsMut, expected_out: Option<u128>| {
+        execute(
+            deps,
+            mock_env(),
+            mock_info(SENDER, &[Coin::new(1000, "uosmo")]),
+            ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+                routes: vec![SwapAmountInRoute {
+                    pool_id: 1,
+                    token_out_denom: "uion".to_string(),
+                }],
+                token_out_min_amount: Some(Coin::new(1, "uion")),
+                fee_percentage: None,
+                fee_collector: Some(COLLECTOR.to_string()),
+                on_failure: None,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote: None,
+                expected_out: expected_out.map(Uint128::new),
+                slippage_tolerance: None,
+                max_price_impact: None,
+                response_data: None,
+                with_data: None,
+                after: None,
+                client_id: None,
+                channel: None,
+                fee_override: None,
+            }),
+        )
+        .unwrap()
+    };
+
+    let err = set_sharing(deps.as_mut(), SENDER, ("10", "20"), Some("treasury")).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    let err = set_sharing(deps.as_mut(), OWNER, ("60", "50"), Some("treasury")).unwrap_err();
+    assert_eq!(err, ContractError::InvalidSurplusSharing {});
+    let err = set_sharing(deps.as_mut(), OWNER, ("10", "20"), None).unwrap_err();
+    assert_eq!(err, ContractError::InvalidSurplusSharing {});
+    set_sharing(deps.as_mut(), OWNER, ("10", "20"), Some("treasury")).unwrap();
+
+    // 100uion above the expected output: 10% to the fee collector, 20% to the treasury
+    swap(deps.as_mut(), Some(800));
+    let res = reply_with_id(deps.as_mut(), 1, 900);
+    assert_eq!(res.messages.len(), 3);
+    assert!(is_valid_bank_send_msg(
+        &res.messages[0].msg,
+        COLLECTOR,
+        10u128.into(),
+        "uion"
+    ));
+    assert!(is_valid_bank_send_msg(
+        &res.messages[1].msg,
+        "treasury",
+        20u128.into(),
+        "uion"
+    ));
+    assert!(is_valid_bank_send_msg(
+        &res.messages[2].msg,
+        SENDER,
+        870u128.into(),
+        "uion"
+    ));
+    let swap_event = res
+        .events
+        .iter()
+        .find(|e| e.ty == "affiliate_swap")
+        .unwrap();
+    let attributes = swap_event
+        .attributes
+        .iter()
+        .map(|a| (a.key.clone(), a.value.clone()))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(attributes["surplus_fee_collector"], "10uion");
+    assert_eq!(attributes["surplus_treasury"], "20uion");
+    assert_eq!(attributes["token_out"], "900uion");
+    // without a fee, only the surplus is split
+    assert_eq!(
+        fee_shares(&res),
+        vec![
+            (
+                "surplus_fee_collector".to_string(),
+                COLLECTOR.to_string(),
+                "10uion".to_string()
+            ),
+            (
+                "surplus_treasury".to_string(),
+                "treasury".to_string(),
+                "20uion".to_string()
+            ),
+        ]
+    );
+
+    assert_eq!(res.messages[0].reply_on, ReplyOn::Always);
+    assert_eq!(res.messages[0].id, ReplyKind::SurplusTransfer.reply_id(1));
+    assert_eq!(res.messages[1].reply_on, ReplyOn::Always);
+    assert_eq!(res.messages[1].id, ReplyKind::TreasuryTransfer.reply_id(1));
+
+    // The collector cannot receive funds: its share is escrowed for it instead of reverting
+    // the swap
+    let res = reply(
+        deps.as_mut(),
+        mock_env(),
+        Reply {
+            id: ReplyKind::SurplusTransfer.reply_id(1),
+            result: SubMsgResult::Err("blocked address".to_string()),
+        },
+    )
+    .unwrap();
+    let event = res
+        .events
+        .iter()
+        .find(|e| e.ty == "affiliate_fee_share_escrowed")
+        .unwrap();
+    assert!(event
+        .attributes
+        .iter()
+        .any(|a| a.key == "share" && a.value == "surplus_fee_collector"));
+    assert!(event
+        .attributes
+        .iter()
+        .any(|a| a.key == "amount" && a.value == "10uion"));
+    let claimable = affiliate_swap
+        .get_claimable((deps.as_ref(), mock_env()), COLLECTOR.to_string())
+        .unwrap();
+    assert_eq!(claimable.coins, vec![Coin::new(10, "uion")]);
+
+    // so is the treasury's
+    let res = reply(
+        deps.as_mut(),
+        mock_env(),
+        Reply {
+            id: ReplyKind::TreasuryTransfer.reply_id(1),
+            result: SubMsgResult::Err("blocked address".to_string()),
+        },
+    )
+    .unwrap();
+    assert!(res
+        .events
+        .iter()
+        .any(|e| e.ty == "affiliate_fee_share_escrowed"));
+    let claimable = affiliate_swap
+        .get_claimable((deps.as_ref(), mock_env()), "treasury".to_string())
+        .unwrap();
+    assert_eq!(claimable.coins, vec![Coin::new(20, "uion")]);
+    assert!(affiliate_swap.pending_shares.is_empty(&deps.storage));
+
+    // Nothing is shared below the expected output, or without one
+    for (swap_id, expected_out) in [(2, Some(1000)), (3, None)] {
+        swap(deps.as_mut(), expected_out);
+        let res = reply_with_id(deps.as_mut(), swap_id, 900);
+        assert_eq!(res.messages.len(), 1);
+        assert!(fee_shares(&res).is_empty());
+        assert!(is_valid_bank_send_msg(
+            &res.messages[0].msg,
+            SENDER,
+            900u128.into(),
+            "uion"
+        ));
+    }
+}
+
+/// Answers the pool manager swap estimate and the gamm spot prices, which `MockQuerier` cannot
+struct EstimateQuerier {
+    base: MockQuerier,
+    token_out_amount: u128,
+    spot_price: &'static str,
+}
+
+impl Querier for EstimateQuerier {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+        let response = match from_slice(bin_request) {
+            Ok(QueryRequest::<Empty>::Stargate { path, data }) => match path.as_str() {
+                "/osmosis.poolmanager.v1beta1.Query/EstimateSwapExactAmountIn" => {
+                    let request: EstimateSwapExactAmountInRequest = data.try_into().unwrap();
+                    // swaps are estimated after the fee. Fees are settled through pool 3
+                    if request.token_in.ends_with("uosmo") && request.pool_id != 3 {
+                        assert_eq!(request.token_in, "990uosmo");
+                    }
+                    to_binary(&EstimateSwapExactAmountInResponse {
+                        token_out_amount: self.token_out_amount.to_string(),
+                    })
+                }
+                "/osmosis.gamm.v2.Query/SpotPrice" => to_binary(&QuerySpotPriceResponse {
+                    spot_price: self.spot_price.to_string(),
+                }),
+                _ => panic!("unexpected stargate query {path}"),
+            },
+            _ => return self.base.raw_query(bin_request),
+        };
+        SystemResult::Ok(ContractResult::Ok(response.unwrap()))
+    }
+}
+
+fn setup_estimate(
+    token_out_amount: u128,
+    spot_price: &'static str,
+) -> OwnedDeps<MockStorage, MockApi, EstimateQuerier, Empty> {
+    let deps = setup_unit(None);
+    OwnedDeps {
+        storage: deps.storage,
+        api: deps.api,
+        querier: EstimateQuerier {
+            base: deps.querier,
+            token_out_amount,
+            spot_price,
+        },
+        custom_query_type: deps.custom_query_type,
+    }
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_slippage_tolerance() {
+    let mut deps = setup_estimate(1000, "1");
+    let swap = |deps: DepsMut, min: Option<u128>, tolerance: Option<&str>| {
+        execute(
+            deps,
+            mock_env(),
+            mock_info(SENDER, &[Coin::new(1000, "uosmo")]),
+            ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+                routes: vec![SwapAmountInRoute {
+                    pool_id: 1,
+                    token_out_denom: "uion".to_string(),
+                }],
+                token_out_min_amount: min.map(|min| Coin::new(min, "uion")),
+                fee_percentage: Some(Decimal::one()),
+                fee_collector: Some(COLLECTOR.to_string()),
+                on_failure: None,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote: None,
+                expected_out: None,
+                slippage_tolerance: tolerance.map(|t| Decimal::from_str(t).unwrap()),
+                max_price_impact: None,
+                response_data: None,
+                with_data: None,
+                after: None,
+                client_id: None,
+                channel: None,
+                fee_override: None,
+            }),
+        )
+    };
+    let min_out = |res: Response| match &res.messages[0].msg {
+        CosmosMsg::Stargate { value, .. } => {
+            let swap_msg: MsgSwapExactAmountIn = value.clone().try_into().unwrap();
+            swap_msg.token_out_min_amount
+        }
+        _ => panic!("expected a swap message"),
+    };
+
+    let err = swap(deps.as_mut(), None, None).unwrap_err();
+    assert_eq!(err, ContractError::MinOutputRequired {});
+    let err = swap(deps.as_mut(), None, Some("100")).unwrap_err();
+    assert_eq!(err, ContractError::InvalidSlippageTolerance {});
+
+    // 1.5% below the estimate of the input left after the fee
+    let res = swap(deps.as_mut(), None, Some("1.5")).unwrap();
+    assert_eq!(min_out(res), "985");
+    // the stricter of both applies
+    let res = swap(deps.as_mut(), Some(990), Some("1.5")).unwrap();
+    assert_eq!(min_out(res), "990");
+    let res = swap(deps.as_mut(), Some(900), Some("1.5")).unwrap();
+    assert_eq!(min_out(res), "985");
+    let res = swap(deps.as_mut(), Some(900), None).unwrap();
+    assert_eq!(min_out(res), "900");
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_max_price_impact() {
+    assert_eq!(
+        price_impact(1000u128.into(), 900u128.into(), Decimal::one()).unwrap(),
+        Decimal::percent(1000)
+    );
+    assert_eq!(
+        price_impact(1000u128.into(), 2000u128.into(), Decimal::percent(200)).unwrap(),
+        Decimal::zero()
+    );
+    assert_eq!(
+        price_impact(1000u128.into(), 1100u128.into(), Decimal::one()).unwrap(),
+        Decimal::zero()
+    );
+
+    // 1782uion for the 990uosmo left after the fee, at a spot price of 2: a 10% impact
+    let mut deps = setup_estimate(1782, "2");
+    let swap = |deps: DepsMut, max_price_impact: &str| {
+        execute(
+            deps,
+            mock_env(),
+            mock_info(SENDER, &[Coin::new(1000, "uosmo")]),
+            ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+                routes: vec![SwapAmountInRoute {
+                    pool_id: 1,
+                    token_out_denom: "uion".to_string(),
+                }],
+                token_out_min_amount: Some(Coin::new(1, "uion")),
+                fee_percentage: Some(Decimal::one()),
+                fee_collector: Some(COLLECTOR.to_string()),
+                on_failure: None,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote: None,
+                expected_out: None,
+                slippage_tolerance: None,
+                max_price_impact: Some(Decimal::from_str(max_price_impact).unwrap()),
+                response_data: None,
+                with_data: None,
+                after: None,
+                client_id: None,
+                channel: None,
+                fee_override: None,
+            }),
+        )
+    };
+
+    let err = swap(deps.as_mut(), "9.5").unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::PriceImpactTooHigh {
+            impact: "10".to_string(),
+            max: "9.5".to_string()
+        }
+    );
+    swap(deps.as_mut(), "10").unwrap();
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_solvency() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+
+    // 98uion escrowed for the sender, and 100uosmo of an active swap
+    simple_execute(deps.as_mut(), 100, None);
+    simple_reply(deps.as_mut(), 98);
+    reply(
+        deps.as_mut(),
+        mock_env(),
+        Reply {
+            id: ReplyKind::OutputTransfer.reply_id(1),
+            result: SubMsgResult::Err("blocked address".to_string()),
+        },
+    )
+    .unwrap();
+    simple_execute(deps.as_mut(), 100, None);
+
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin::new(98, "uion"), Coin::new(100, "uosmo")],
+    );
+    let solvency = affiliate_swap
+        .get_solvency((deps.as_ref(), mock_env()))
+        .unwrap();
+    assert!(solvency.solvent);
+    assert_eq!(
+        solvency.denoms,
+        vec![
+            DenomSolvency {
+                denom: "uion".to_string(),
+                balance: Uint128::new(98),
+                liabilities: Uint128::new(98),
+                surplus: Uint128::zero(),
+                deficit: Uint128::zero(),
+            },
+            DenomSolvency {
+                denom: "uosmo".to_string(),
+                balance: Uint128::new(100),
+                liabilities: Uint128::new(100),
+                surplus: Uint128::zero(),
+                deficit: Uint128::zero(),
+            },
+        ]
+    );
+
+    // uion is missing entirely, and uatom was sent to the contract by mistake
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin::new(5, "uatom"), Coin::new(90, "uosmo")],
+    );
+    let solvency = affiliate_swap
+        .get_solvency((deps.as_ref(), mock_env()))
+        .unwrap();
+    assert!(!solvency.solvent);
+    let by_denom = solvency
+        .denoms
+        .into_iter()
+        .map(|d| (d.denom.clone(), (d.surplus.u128(), d.deficit.u128())))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(by_denom["uatom"], (5, 0));
+    assert_eq!(by_denom["uion"], (0, 98));
+    assert_eq!(by_denom["uosmo"], (0, 10));
+}
+
+#[test]
+#[cfg(not(any(feature = "stats", feature = "astroport")))]
+fn test_stats_disabled() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    simple_execute(deps.as_mut(), 100, None);
+    simple_reply(deps.as_mut(), 98);
+    assert!(affiliate_swap.daily_stats.is_empty(&deps.storage));
+    let err = affiliate_swap
+        .list_daily_stats((deps.as_ref(), mock_env()), None, None, None, None, None)
+        .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::FeatureDisabled {
+            feature: "stats".to_string()
+        }
+    );
+    let err = affiliate_swap
+        .list_channel_stats(
+            (deps.as_ref(), mock_env()),
+            "mobile".to_string(),
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::FeatureDisabled {
+            feature: "stats".to_string()
+        }
+    );
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_export_state() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    for denom in ["uatom", "ujuno", "uosmo"] {
+        affiliate_swap
+            .set_pair_fee(
+                (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+                denom.to_string(),
+                "uion".to_string(),
+                Some(PairFee::Fixed {
+                    fee_percentage: Decimal::one(),
+                }),
+            )
+            .unwrap();
+    }
+    let export = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier, Empty>,
+                  section: StateSection,
+                  start_after: Option<Binary>| {
+        affiliate_swap
+            .export_state((deps.as_ref(), mock_env()), section, start_after, Some(2))
+            .unwrap()
+    };
+    let pair_fee = |denom: &str| StateEntry::PairFee {
+        token_in_denom: denom.to_string(),
+        token_out_denom: "uion".to_string(),
+        pair_fee: PairFee::Fixed {
+            fee_percentage: Decimal::one(),
+        },
+    };
+
+    let page = export(&deps, StateSection::PairFees, None);
+    assert_eq!(page.entries, vec![pair_fee("uatom"), pair_fee("ujuno")]);
+    let page = export(&deps, StateSection::PairFees, page.next_start_after);
+    assert_eq!(page.entries, vec![pair_fee("uosmo")]);
+    assert_eq!(page.next_start_after, None);
+
+    let page = export(&deps, StateSection::Config, None);
+    assert_eq!(
+        page.entries,
+        vec![StateEntry::Config(
+            affiliate_swap.config.load(&deps.storage).unwrap()
+        )]
+    );
+
+    simple_execute(deps.as_mut(), 100, None);
+    simple_reply(deps.as_mut(), 98);
+    let page = export(&deps, StateSection::Volumes, None);
+    assert_eq!(
+        page.entries,
+        vec![StateEntry::Volume {
+            address: Addr::unchecked(SENDER),
+            denom: "uosmo".to_string(),
+            amount: Uint128::new(100),
+        }]
+    );
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_import_state() {
+    let affiliate_swap = AffiliateSwap::new();
+    let sections = [
+        StateSection::Config,
+        StateSection::PairFees,
+        StateSection::VolumeDiscounts,
+        StateSection::Volumes,
+        StateSection::DailyStats,
+        StateSection::Claimable,
+        StateSection::Referrals,
+        StateSection::DeniedPools,
+        StateSection::InputLimits,
+        StateSection::RollingVolumes,
+        StateSection::AllowedSenders,
+        StateSection::FeeHolidays,
+        StateSection::CallbackTargets,
+        StateSection::SettlementRoutes,
+        StateSection::ChannelStats,
+        StateSection::DenomMetadata,
+        StateSection::ClaimOperators,
+        StateSection::CollectorStats,
+    ];
+    let export_all = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier, Empty>| {
+        sections
+            .iter()
+            .flat_map(|section| {
+                affiliate_swap
+                    .export_state((deps.as_ref(), mock_env()), section.clone(), None, None)
+                    .unwrap()
+                    .entries
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // a previous instance with a pair fee, volume, stats, a claim operator and 98uion escrowed
+    // for the sender
+    let mut old = setup_unit(None);
+    affiliate_swap
+        .set_claim_operator(
+            (old.as_mut(), mock_env(), mock_info(SENDER, &[])),
+            "bot".to_string(),
+            true,
+        )
+        .unwrap();
+    affiliate_swap
+        .set_pair_fee(
+            (old.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            "uosmo".to_string(),
+            "uion".to_string(),
+            Some(PairFee::Fixed {
+                fee_percentage: Decimal::one(),
+            }),
+        )
+        .unwrap();
+    simple_execute(old.as_mut(), 100, None);
+    simple_reply(old.as_mut(), 98);
+    reply(
+        old.as_mut(),
+        mock_env(),
+        Reply {
+            id: ReplyKind::OutputTransfer.reply_id(1),
+            result: SubMsgResult::Err("blocked address".to_string()),
+        },
+    )
+    .unwrap();
+    let entries = export_all(&old);
+
+    let mut new = setup_unit(None);
+    let import = |deps: DepsMut, sender: &str, funds: &[Coin]| {
+        affiliate_swap.import_state(
+            (deps, mock_env(), mock_info(sender, funds)),
+            entries.clone(),
+        )
+    };
+    let err = import(new.as_mut(), SENDER, &[Coin::new(98, "uion")]).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    let err = import(new.as_mut(), OWNER, &[]).unwrap_err();
+    assert_eq!(err, ContractError::ImportFundsMismatch {});
+    let err = import(new.as_mut(), OWNER, &[Coin::new(99, "uion")]).unwrap_err();
+    assert_eq!(err, ContractError::ImportFundsMismatch {});
+    import(new.as_mut(), OWNER, &[Coin::new(98, "uion")]).unwrap();
+    assert_eq!(export_all(&new), entries);
+
+    simple_execute(new.as_mut(), 100, None);
+    let err = import(new.as_mut(), OWNER, &[Coin::new(98, "uion")]).unwrap_err();
+    assert_eq!(err, ContractError::ImportClosed {});
+}
+
+#[test]
+fn test_import_state_validation() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    let config = affiliate_swap.config.load(&deps.storage).unwrap();
+    let holiday = |start: u64, end: u64| {
+        StateEntry::FeeHoliday(FeeHoliday {
+            start: Timestamp::from_seconds(start),
+            end: Timestamp::from_seconds(end),
+        })
+    };
+    let mut import = |entries: Vec<StateEntry>| {
+        affiliate_swap.import_state((deps.as_mut(), mock_env(), mock_info(OWNER, &[])), entries)
+    };
+
+    let err = import(vec![StateEntry::Config(Config {
+        max_fee_bps: 10_000,
+        ..config.clone()
+    })])
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidMaxFeePercentage {
+            true_max_fee: TRUE_MAX_FEE.to_string()
+        }
+    );
+    let err = import(vec![StateEntry::Config(Config {
+        max_route_length: 0,
+        ..config.clone()
+    })])
+    .unwrap_err();
+    assert_eq!(err, ContractError::InvalidMaxRouteLength {});
+    let err = import(vec![StateEntry::Config(Config {
+        router_pubkey: Some(Binary::from(vec![1; 33])),
+        ..config.clone()
+    })])
+    .unwrap_err();
+    assert_eq!(err, ContractError::InvalidRouterPubkey {});
+
+    // past holidays are kept, overlapping ones are not
+    import(vec![holiday(100, 200), holiday(200, 300)]).unwrap();
+    let err = import(vec![holiday(250, 400)]).unwrap_err();
+    assert_eq!(err, ContractError::FeeHolidayOverlap {});
+    let err = import(vec![holiday(400, 400)]).unwrap_err();
+    assert_eq!(err, ContractError::InvalidFeeHoliday {});
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_sticky_referral() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    let swap = |deps: DepsMut, env: Env, fee_collector: Option<&str>| {
+        execute(
+            deps,
+            env,
+            mock_info(SENDER, &[Coin::new(100, "uosmo")]),
+            ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+                routes: vec![SwapAmountInRoute {
+                    pool_id: 1,
+                    token_out_denom: "uion".to_string(),
+                }],
+                token_out_min_amount: Some(Coin::new(1, "uion")),
+                fee_percentage: None,
+                fee_collector: fee_collector.map(str::to_string),
+                on_failure: None,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote: None,
+                expected_out: None,
+                slippage_tolerance: None,
+                max_price_impact: None,
+                response_data: None,
+                with_data: None,
+                after: None,
+                client_id: None,
+                channel: None,
+                fee_override: None,
+            }),
+        )
+    };
+    // failed swaps still use up an id in unit tests, as their storage writes are not reverted
+    let last_id = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier, Empty>| {
+        affiliate_swap.swap_count.load(&deps.storage).unwrap()
+    };
+    let fee_collector = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier, Empty>| {
+        affiliate_swap
+            .active_swaps
+            .load(&deps.storage, last_id(deps))
+            .unwrap()
+            .fee_collector
+    };
+    let reply_last = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier, Empty>| {
+        let id = last_id(deps);
+        reply_with_id(deps.as_mut(), id, 98);
+    };
+
+    // disabled by default
+    simple_execute(deps.as_mut(), 100, None);
+    simple_reply(deps.as_mut(), 98);
+    let err = swap(deps.as_mut(), mock_env(), None).unwrap_err();
+    assert_eq!(err, ContractError::FeeCollectorRequired {});
+
+    affiliate_swap
+        .update_config(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(100),
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+    swap(deps.as_mut(), mock_env(), Some(COLLECTOR)).unwrap();
+    reply_last(&mut deps);
+    let referral = affiliate_swap
+        .get_referral((deps.as_ref(), mock_env()), SENDER.to_string())
+        .unwrap();
+    assert_eq!(referral.fee_collector, Some(Addr::unchecked(COLLECTOR)));
+    assert_eq!(
+        referral.expires_at,
+        Some(mock_env().block.time.plus_seconds(100))
+    );
+
+    // later swaps without a fee collector are attributed to the referrer, and another fee
+    // collector does not replace it
+    swap(deps.as_mut(), mock_env(), None).unwrap();
+    assert_eq!(fee_collector(&deps), COLLECTOR);
+    reply_last(&mut deps);
+    swap(deps.as_mut(), mock_env(), Some("other")).unwrap();
+    reply_last(&mut deps);
+    swap(deps.as_mut(), mock_env(), None).unwrap();
+    assert_eq!(fee_collector(&deps), COLLECTOR);
+    reply_last(&mut deps);
+
+    // the referral expires after 100 seconds
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(100);
+    let err = swap(deps.as_mut(), env.clone(), None).unwrap_err();
+    assert_eq!(err, ContractError::FeeCollectorRequired {});
+    let referral = affiliate_swap
+        .get_referral((deps.as_ref(), env.clone()), SENDER.to_string())
+        .unwrap();
+    assert_eq!(referral.fee_collector, None);
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_expiring_referrals() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    let set_duration = |deps: DepsMut, duration: u64| {
+        affiliate_swap
+            .update_config(
+                (deps, mock_env(), mock_info(OWNER, &[])),
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(duration),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap()
+    };
+    let clear = |deps: DepsMut, env: Env| {
+        let res = affiliate_swap
+            .clear_expired_referrals((deps, env, mock_info("anyone", &[])), None)
+            .unwrap();
+        res.attributes
+            .iter()
+            .find(|a| a.key == "cleared")
+            .unwrap()
+            .value
+            .clone()
+    };
+
+    set_duration(deps.as_mut(), 100);
+    simple_execute(deps.as_mut(), 100, None);
+    simple_reply(deps.as_mut(), 98);
+    let referral = affiliate_swap
+        .referrals
+        .load(&deps.storage, &Addr::unchecked(SENDER))
+        .unwrap();
+    assert_eq!(referral.expires_at, mock_env().block.time.plus_seconds(100));
+
+    // a longer duration only applies to new referrals
+    set_duration(deps.as_mut(), 1000);
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(100);
+    let referral = affiliate_swap
+        .get_referral((deps.as_ref(), env.clone()), SENDER.to_string())
+        .unwrap();
+    assert_eq!(referral.fee_collector, None);
+
+    // expired referrals are cleared once their second has passed
+    assert_eq!(clear(deps.as_mut(), env.clone()), "0");
+    env.block.time = env.block.time.plus_seconds(1);
+    assert_eq!(clear(deps.as_mut(), env.clone()), "1");
+    assert!(affiliate_swap
+        .referrals
+        .may_load(&deps.storage, &Addr::unchecked(SENDER))
+        .unwrap()
+        .is_none());
+    assert_eq!(clear(deps.as_mut(), env), "0");
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_pool_denylist() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    let set_denied = |deps: DepsMut, sender: &str, pool_id: u64, denied: bool| {
+        affiliate_swap.set_pool_denied((deps, mock_env(), mock_info(sender, &[])), pool_id, denied)
+    };
+    // uosmo -> uion through pool 1, then uion -> uatom through pool 2
+    let swap = |deps: DepsMut| {
+        execute(
+            deps,
+            mock_env(),
+            mock_info(SENDER, &[Coin::new(100, "uosmo")]),
+            ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+                routes: vec![
+                    SwapAmountInRoute {
+                        pool_id: 1,
+                        token_out_denom: "uion".to_string(),
+                    },
+                    SwapAmountInRoute {
+                        pool_id: 2,
+                        token_out_denom: "uatom".to_string(),
+                    },
+                ],
+                token_out_min_amount: Some(Coin::new(1, "uatom")),
+                fee_percentage: None,
+                fee_collector: Some(COLLECTOR.to_string()),
+                on_failure: None,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote: None,
+                expected_out: None,
+                slippage_tolerance: None,
+                max_price_impact: None,
+                response_data: None,
+                with_data: None,
+                after: None,
+                client_id: None,
+                channel: None,
+                fee_override: None,
+            }),
+        )
+    };
+
+    let err = set_denied(deps.as_mut(), SENDER, 2, true).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    set_denied(deps.as_mut(), OWNER, 2, true).unwrap();
+    set_denied(deps.as_mut(), OWNER, 7, true).unwrap();
+    let denied = affiliate_swap
+        .list_denied_pools((deps.as_ref(), mock_env()), None, None)
+        .unwrap();
+    assert_eq!(denied.pool_ids, vec![2, 7]);
+    let denied = affiliate_swap
+        .list_denied_pools((deps.as_ref(), mock_env()), Some(2), None)
+        .unwrap();
+    assert_eq!(denied.pool_ids, vec![7]);
+
+    // any hop through a denied pool is rejected
+    let err = swap(deps.as_mut()).unwrap_err();
+    assert_eq!(err, ContractError::PoolDenied { pool_id: 2 });
+
+    set_denied(deps.as_mut(), OWNER, 2, false).unwrap();
+    swap(deps.as_mut()).unwrap();
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_input_limits() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    let set_limits =
+        |deps: DepsMut, sender: &str, min_input: Option<u128>, max_input: Option<u128>| {
+            affiliate_swap.set_input_limits(
+                (deps, mock_env(), mock_info(sender, &[])),
+                "uosmo".to_string(),
+                min_input.map(Uint128::new),
+                max_input.map(Uint128::new),
+                None,
+            )
+        };
+    let swap = |deps: DepsMut, amount: u128| {
+        execute(
+            deps,
+            mock_env(),
+            mock_info(SENDER, &[Coin::new(amount, "uosmo")]),
+            ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+                routes: vec![SwapAmountInRoute {
+                    pool_id: 1,
+                    token_out_denom: "uion".to_string(),
+                }],
+                token_out_min_amount: Some(Coin::new(1, "uion")),
+                fee_percentage: None,
+                fee_collector: Some(COLLECTOR.to_string()),
+                on_failure: None,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote: None,
+                expected_out: None,
+                slippage_tolerance: None,
+                max_price_impact: None,
+                response_data: None,
+                with_data: None,
+                after: None,
+                client_id: None,
+                channel: None,
+                fee_override: None,
+            }),
+        )
+    };
+
+    let err = set_limits(deps.as_mut(), SENDER, None, Some(100)).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    let err = set_limits(deps.as_mut(), OWNER, Some(101), Some(100)).unwrap_err();
+    assert_eq!(err, ContractError::InvalidInputLimits {});
+    set_limits(deps.as_mut(), OWNER, Some(10), Some(100)).unwrap();
+    let limits = affiliate_swap
+        .get_input_limits((deps.as_ref(), mock_env()), "uosmo".to_string())
+        .unwrap();
+    assert_eq!(limits.min_input, Some(Uint128::new(10)));
+    assert_eq!(limits.max_input, Some(Uint128::new(100)));
+
+    simple_execute(deps.as_mut(), 10, None);
+    let err = swap(deps.as_mut(), 9).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InputTooSmall {
+            min: "10uosmo".to_string()
+        }
+    );
+
+    // the full input counts, fee included
+    simple_execute(deps.as_mut(), 100, None);
+    let err = swap(deps.as_mut(), 101).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InputTooLarge {
+            max: "100uosmo".to_string()
+        }
+    );
+
+    set_limits(deps.as_mut(), OWNER, None, None).unwrap();
+    assert!(affiliate_swap
+        .input_limits
+        .may_load(&deps.storage, "uosmo")
+        .unwrap()
+        .is_none());
+    simple_execute(deps.as_mut(), 101, None);
+    simple_execute(deps.as_mut(), 9, None);
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_daily_volume_cap() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    let swap_with = |deps: DepsMut, env: Env, amount: u128, on_failure: Option<OnFailure>| {
+        execute(
+            deps,
+            env,
+            mock_info(SENDER, &[Coin::new(amount, "uosmo")]),
+            ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+                routes: vec![SwapAmountInRoute {
+                    pool_id: 1,
+                    token_out_denom: "uion".to_string(),
+                }],
+                token_out_min_amount: Some(Coin::new(1, "uion")),
+                fee_percentage: None,
+                fee_collector: Some(COLLECTOR.to_string()),
+                on_failure,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote: None,
+                expected_out: None,
+                slippage_tolerance: None,
+                max_price_impact: None,
+                response_data: None,
+                with_data: None,
+                after: None,
+                client_id: None,
+                channel: None,
+                fee_override: None,
+            }),
+        )
+    };
+    let swap = |deps: DepsMut, env: Env, amount: u128| swap_with(deps, env, amount, None);
+    // replied in the block of the swap, as on chain
+    let swap_reply = |deps: DepsMut, env: Env, swap_id: u64, result: SubMsgResult| {
+        reply(
+            deps,
+            env,
+            Reply {
+                id: ReplyKind::Swap.reply_id(swap_id),
+                result,
+            },
+        )
+    };
+    let succeed = |deps: DepsMut, env: Env, swap_id: u64| {
+        let result = SubMsgResult::Ok(SubMsgResponse {
+            events: vec![],
+            data: Some(
+                MsgSwapExactAmountInResponse {
+                    token_out_amount: "1".to_string(),
+                }
+                .into(),
+            ),
+        });
+        swap_reply(deps, env, swap_id, result).unwrap();
+    };
+    let rolling_volume = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier, Empty>, env: Env| {
+        affiliate_swap
+            .get_rolling_volume(
+                (deps.as_ref(), env),
+                SENDER.to_string(),
+                "uosmo".to_string(),
+            )
+            .unwrap()
+            .volume
+            .u128()
+    };
+
+    // untracked without a max daily volume
+    swap(deps.as_mut(), mock_env(), 500).unwrap();
+    succeed(deps.as_mut(), mock_env(), 1);
+    assert_eq!(rolling_volume(&deps, mock_env()), 0);
+
+    affiliate_swap
+        .set_input_limits(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            "uosmo".to_string(),
+            None,
+            None,
+            Some(Uint128::new(100)),
+        )
+        .unwrap();
+    let limits = affiliate_swap
+        .get_input_limits((deps.as_ref(), mock_env()), "uosmo".to_string())
+        .unwrap();
+    assert_eq!(limits.max_daily_volume, Some(Uint128::new(100)));
+
+    let mut env = mock_env();
+    swap(deps.as_mut(), env.clone(), 60).unwrap();
+    succeed(deps.as_mut(), env.clone(), 2);
+    env.block.time = env.block.time.plus_seconds(2 * 3_600);
+    swap(deps.as_mut(), env.clone(), 30).unwrap();
+    succeed(deps.as_mut(), env.clone(), 3);
+    assert_eq!(rolling_volume(&deps, env.clone()), 90);
+    // refunded swaps are not counted
+    let refund = OnFailure::RefundAndAck {
+        refund_address: None,
+    };
+    swap_with(deps.as_mut(), env.clone(), 10, Some(refund)).unwrap();
+    swap_reply(
+        deps.as_mut(),
+        env.clone(),
+        4,
+        SubMsgResult::Err("no route".to_string()),
+    )
+    .unwrap();
+    assert_eq!(rolling_volume(&deps, env.clone()), 90);
+    let err = swap(deps.as_mut(), env.clone(), 11).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::DailyVolumeExceeded {
+            remaining: "10uosmo".to_string()
+        }
+    );
+    swap(deps.as_mut(), env.clone(), 10).unwrap();
+    succeed(deps.as_mut(), env.clone(), 5);
+
+    // other senders have their own volume
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("other", &[Coin::new(100, "uosmo")]),
+        ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+            routes: vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uion".to_string(),
+            }],
+            token_out_min_amount: Some(Coin::new(1, "uion")),
+            fee_percentage: None,
+            fee_collector: Some(COLLECTOR.to_string()),
+            on_failure: None,
+            token_in_denom: None,
+            allow_cyclic: None,
+            receiver: None,
+            ibc_origin: None,
+            quote: None,
+            expected_out: None,
+            slippage_tolerance: None,
+            max_price_impact: None,
+            response_data: None,
+            with_data: None,
+            after: None,
+            client_id: None,
+            channel: None,
+            fee_override: None,
+        }),
+    )
+    .unwrap();
+    succeed(deps.as_mut(), env.clone(), 6);
+
+    // the first swap leaves the window 24 hours after its hour
+    env.block.time = mock_env().block.time.plus_seconds(24 * 3_600);
+    assert_eq!(rolling_volume(&deps, env.clone()), 40);
+    swap(deps.as_mut(), env.clone(), 60).unwrap();
+    succeed(deps.as_mut(), env.clone(), 7);
+    let buckets = affiliate_swap
+        .rolling_volumes
+        .load(&deps.storage, (&Addr::unchecked(SENDER), "uosmo"))
+        .unwrap();
+    assert_eq!(buckets.len(), 2);
+    assert_eq!(buckets[0].1, Uint128::new(40));
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_sender_allowlist() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    let set_allowed = |deps: DepsMut, sender: &str, address: &str, allowed: bool| {
+        affiliate_swap.set_sender_allowed(
+            (deps, mock_env(), mock_info(sender, &[])),
+            address.to_string(),
+            allowed,
+        )
+    };
+    let enable = |deps: DepsMut, enabled: bool| {
+        affiliate_swap.update_config(
+            (deps, mock_env(), mock_info(OWNER, &[])),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(enabled),
+            None,
+            None,
+            None,
+        )
+    };
+    let swap = |deps: DepsMut, sender: &str| {
+        execute(
+            deps,
+            mock_env(),
+            mock_info(sender, &[Coin::new(100, "uosmo")]),
+            ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+                routes: vec![SwapAmountInRoute {
+                    pool_id: 1,
+                    token_out_denom: "uion".to_string(),
+                }],
+                token_out_min_amount: Some(Coin::new(1, "uion")),
+                fee_percentage: None,
+                fee_collector: Some(COLLECTOR.to_string()),
+                on_failure: None,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote: None,
+                expected_out: None,
+                slippage_tolerance: None,
+                max_price_impact: None,
+                response_data: None,
+                with_data: None,
+                after: None,
+                client_id: None,
+                channel: None,
+                fee_override: None,
+            }),
+        )
+    };
+
+    let err = set_allowed(deps.as_mut(), SENDER, SENDER, true).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    set_allowed(deps.as_mut(), OWNER, SENDER, true).unwrap();
+    set_allowed(deps.as_mut(), OWNER, "other", true).unwrap();
+    let allowed = affiliate_swap
+        .get_sender_allowed((deps.as_ref(), mock_env()), SENDER.to_string())
+        .unwrap();
+    assert!(allowed.allowed);
+    let senders = affiliate_swap
+        .list_allowed_senders((deps.as_ref(), mock_env()), None, None)
+        .unwrap();
+    assert_eq!(
+        senders.addresses,
+        vec![Addr::unchecked("other"), Addr::unchecked(SENDER)]
+    );
+    let senders = affiliate_swap
+        .list_allowed_senders((deps.as_ref(), mock_env()), Some("other".to_string()), None)
+        .unwrap();
+    assert_eq!(senders.addresses, vec![Addr::unchecked(SENDER)]);
+
+    // anyone swaps while the allowlist is disabled
+    swap(deps.as_mut(), "stranger").unwrap();
+
+    enable(deps.as_mut(), true).unwrap();
+    let config = affiliate_swap
+        .get_config((deps.as_ref(), mock_env()))
+        .unwrap();
+    assert!(config.sender_allowlist);
+    let err = swap(deps.as_mut(), "stranger").unwrap_err();
+    assert_eq!(err, ContractError::SenderNotAllowed {});
+    swap(deps.as_mut(), SENDER).unwrap();
+
+    set_allowed(deps.as_mut(), OWNER, SENDER, false).unwrap();
+    let err = swap(deps.as_mut(), SENDER).unwrap_err();
+    assert_eq!(err, ContractError::SenderNotAllowed {});
+
+    enable(deps.as_mut(), false).unwrap();
+    swap(deps.as_mut(), SENDER).unwrap();
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_fee_holidays() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    let now = mock_env().block.time;
+    let schedule = |deps: DepsMut, sender: &str, start: u64, end: u64| {
+        affiliate_swap.schedule_fee_holiday(
+            (deps, mock_env(), mock_info(sender, &[])),
+            now.plus_seconds(start),
+            now.plus_seconds(end),
+        )
+    };
+    // checks the fee of a 1000uosmo swap at 1% made `at` seconds from now
+    let assert_fee = |deps: DepsMut, at: u64, fee: u128| {
+        let mut env = mock_env();
+        env.block.time = now.plus_seconds(at);
+        let res = execute(
+            deps,
+            env,
+            mock_info(SENDER, &[Coin::new(1000, "uosmo")]),
+            ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+                routes: vec![SwapAmountInRoute {
+                    pool_id: 1,
+                    token_out_denom: "uion".to_string(),
+                }],
+                token_out_min_amount: Some(Coin::new(1, "uion")),
+                fee_percentage: Some(Decimal::one()),
+                fee_collector: Some(COLLECTOR.to_string()),
+                on_failure: None,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote: None,
+                expected_out: None,
+                slippage_tolerance: None,
+                max_price_impact: None,
+                response_data: None,
+                with_data: None,
+                after: None,
+                client_id: None,
+                channel: None,
+                fee_override: None,
+            }),
+        )
+        .unwrap();
+        assert!(is_valid_swap_msg(
+            &res.messages[0].msg,
+            Coin::new(1000 - fee, "uosmo")
+        ));
+    };
+
+    let err = schedule(deps.as_mut(), SENDER, 100, 200).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    let err = schedule(deps.as_mut(), OWNER, 200, 100).unwrap_err();
+    assert_eq!(err, ContractError::InvalidFeeHoliday {});
+    schedule(deps.as_mut(), OWNER, 100, 200).unwrap();
+    schedule(deps.as_mut(), OWNER, 300, 400).unwrap();
+    let err = schedule(deps.as_mut(), OWNER, 150, 250).unwrap_err();
+    assert_eq!(err, ContractError::FeeHolidayOverlap {});
+    let err = schedule(deps.as_mut(), OWNER, 250, 350).unwrap_err();
+    assert_eq!(err, ContractError::FeeHolidayOverlap {});
+    // windows may touch, the end being excluded
+    schedule(deps.as_mut(), OWNER, 200, 300).unwrap();
+    let holidays = affiliate_swap
+        .list_fee_holidays(
+            (deps.as_ref(), mock_env()),
+            Some(now.plus_seconds(100)),
+            None,
+        )
+        .unwrap();
+    assert_eq!(
+        holidays.fee_holidays,
+        vec![
+            FeeHoliday {
+                start: now.plus_seconds(200),
+                end: now.plus_seconds(300),
+            },
+            FeeHoliday {
+                start: now.plus_seconds(300),
+                end: now.plus_seconds(400),
+            },
+        ]
+    );
+
+    assert_fee(deps.as_mut(), 99, 10);
+    assert_fee(deps.as_mut(), 100, 0);
+    assert_fee(deps.as_mut(), 399, 0);
+    assert_fee(deps.as_mut(), 400, 10);
+
+    let mut env = mock_env();
+    env.block.time = now.plus_seconds(150);
+    let max_fee = affiliate_swap
+        .get_max_fee_percentage((deps.as_ref(), env))
+        .unwrap();
+    assert_eq!(max_fee.max_fee_percentage, Decimal::zero());
+
+    affiliate_swap
+        .cancel_fee_holiday(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            now.plus_seconds(100),
+        )
+        .unwrap();
+    assert_fee(deps.as_mut(), 150, 10);
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_sudo_sync_params() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    let sync = |deps: DepsMut, max_fee_percentage: &str, paused: bool| {
+        affiliate_swap.sudo(
+            (deps, mock_env()),
+            SudoMsg::SyncParams {
+                max_fee_percentage: Decimal::from_str(max_fee_percentage).unwrap(),
+                paused,
+            },
+        )
+    };
+    let is_paused = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier, Empty>| {
+        affiliate_swap
+            .is_paused((deps.as_ref(), mock_env()))
+            .unwrap()
+    };
+
+    let err = sync(deps.as_mut(), "10.01", false).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidMaxFeePercentage {
+            true_max_fee: TRUE_MAX_FEE.to_string()
+        }
+    );
+
+    sync(deps.as_mut(), "0.5", true).unwrap();
+    let max_fee = affiliate_swap
+        .get_max_fee_percentage((deps.as_ref(), mock_env()))
+        .unwrap();
+    assert_eq!(
+        max_fee.max_fee_percentage,
+        Decimal::from_str("0.5").unwrap()
+    );
+    // paused by governance, on behalf of the contract itself
+    assert_eq!(
+        is_paused(&deps),
+        IsPausedResponse {
+            paused: true,
+            paused_by: Some(mock_env().contract.address),
+            paused_at: Some(mock_env().block.time),
+        }
+    );
+    // syncing is idempotent
+    sync(deps.as_mut(), "0.5", true).unwrap();
+
+    sync(deps.as_mut(), "0.5", false).unwrap();
+    assert!(!is_paused(&deps).paused);
+    simple_execute(deps.as_mut(), 100, None);
+
+    // an owner pause is lifted by governance too
+    affiliate_swap
+        .pause((deps.as_mut(), mock_env(), mock_info(OWNER, &[])))
+        .unwrap();
+    sync(deps.as_mut(), "0.5", true).unwrap();
+    assert_eq!(is_paused(&deps).paused_by, Some(Addr::unchecked(OWNER)));
+    sync(deps.as_mut(), "0.5", false).unwrap();
+    assert!(!is_paused(&deps).paused);
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_effective_fee() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    let owner = || mock_info(OWNER, &[]);
+    let effective_fee = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier, Empty>,
+                         token_out_denom: Option<&str>,
+                         fee_percentage: &str,
+                         fee_collector: Option<&str>| {
+        affiliate_swap
+            .get_effective_fee(
+                (deps.as_ref(), mock_env()),
+                SENDER.to_string(),
+                Uint128::new(1000),
+                "uosmo".to_string(),
+                token_out_denom.map(str::to_string),
+                Some(Decimal::from_str(fee_percentage).unwrap()),
+                fee_collector.map(str::to_string),
+            )
+            .unwrap()
+    };
+
+    // the requested fee, capped by the max fee
+    let fee = effective_fee(&deps, None, "1", Some(COLLECTOR));
+    assert_eq!(
+        fee,
+        EffectiveFeeResponse {
+            fee_percentage: Decimal::one(),
+            fee: Coin::new(10, "uosmo"),
+            rebate: Coin::new(0, "uosmo"),
+            fee_collector: Some(Addr::unchecked(COLLECTOR)),
+        }
+    );
+    let max_fee = affiliate_swap
+        .get_max_fee_percentage((deps.as_ref(), mock_env()))
+        .unwrap()
+        .max_fee_percentage;
+    let fee = effective_fee(&deps, None, "10", Some(COLLECTOR));
+    assert_eq!(fee.fee_percentage, max_fee);
+
+    // a fixed pair fee replaces the requested one, for that pair only
+    affiliate_swap
+        .set_pair_fee(
+            (deps.as_mut(), mock_env(), owner()),
+            "uosmo".to_string(),
+            "uion".to_string(),
+            Some(PairFee::Fixed {
+                fee_percentage: Decimal::from_str("2").unwrap(),
+            }),
+        )
+        .unwrap();
+    assert_eq!(
+        effective_fee(&deps, Some("uion"), "1", Some(COLLECTOR)).fee,
+        Coin::new(20, "uosmo")
+    );
+    assert_eq!(
+        effective_fee(&deps, None, "1", Some(COLLECTOR)).fee,
+        Coin::new(10, "uosmo")
+    );
+
+    // volume discounts, then the rebate out of the discounted fee
+    affiliate_swap
+        .set_volume_discounts(
+            (deps.as_mut(), mock_env(), owner()),
+            "uosmo".to_string(),
+            vec![VolumeDiscount {
+                min_volume: Uint128::new(1000),
+                discount: Decimal::from_str("0.5").unwrap(),
+            }],
+        )
+        .unwrap();
+    affiliate_swap
+        .volumes
+        .save(
+            &mut deps.storage,
+            (&Addr::unchecked(SENDER), "uosmo"),
+            &Uint128::new(1000),
+        )
+        .unwrap();
+    affiliate_swap
+        .update_config(
+            (deps.as_mut(), mock_env(), owner()),
+            None,
+            None,
+            None,
+            None,
+            Some(Decimal::from_ratio(50u64, 1u64)),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+    let fee = effective_fee(&deps, Some("uion"), "1", Some(COLLECTOR));
+    assert_eq!(fee.fee_percentage, Decimal::one());
+    assert_eq!(fee.fee, Coin::new(10, "uosmo"));
+    assert_eq!(fee.rebate, Coin::new(5, "uosmo"));
+
+    // the swap charges the same fee
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(SENDER, &[Coin::new(1000, "uosmo")]),
+        ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+            routes: vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uion".to_string(),
+            }],
+            token_out_min_amount: Some(Coin::new(1, "uion")),
+            fee_percentage: Some(Decimal::one()),
+            fee_collector: Some(COLLECTOR.to_string()),
+            on_failure: None,
+            token_in_denom: None,
+            allow_cyclic: None,
+            receiver: None,
+            ibc_origin: None,
+            quote: None,
+            expected_out: None,
+            slippage_tolerance: None,
+            max_price_impact: None,
+            response_data: None,
+            with_data: None,
+            after: None,
+            client_id: None,
+            channel: None,
+            fee_override: None,
+        }),
+    )
+    .unwrap();
+    assert!(is_valid_swap_msg(
+        &res.messages[0].msg,
+        Coin::new(990, "uosmo")
+    ));
+
+    // no fee during a fee holiday
+    affiliate_swap
+        .schedule_fee_holiday(
+            (deps.as_mut(), mock_env(), owner()),
+            mock_env().block.time,
+            mock_env().block.time.plus_seconds(60),
+        )
+        .unwrap();
+    let fee = effective_fee(&deps, Some("uion"), "1", Some(COLLECTOR));
+    assert_eq!(fee.fee_percentage, Decimal::zero());
+    assert_eq!(fee.fee, Coin::new(0, "uosmo"));
+
+    // without a fee collector, the swap would require one
+    assert_eq!(effective_fee(&deps, None, "1", None).fee_collector, None);
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_swap_estimate() {
+    // every hop outputs 1782 at a spot price of 2
+    let deps = setup_estimate(1782, "2");
+    let affiliate_swap = AffiliateSwap::new();
+    let estimate = affiliate_swap
+        .get_swap_estimate(
+            (deps.as_ref(), mock_env()),
+            vec![
+                SwapAmountInRoute {
+                    pool_id: 1,
+                    token_out_denom: "uion".to_string(),
+                },
+                SwapAmountInRoute {
+                    pool_id: 2,
+                    token_out_denom: "uatom".to_string(),
+                },
+            ],
+            Coin::new(990, "uosmo"),
+        )
+        .unwrap();
+    assert_eq!(
+        estimate,
+        SwapEstimateResponse {
+            token_out: Coin::new(1782, "uatom"),
+            spot_price: Decimal::percent(400),
+            // 1.8 instead of 4
+            price_impact: Decimal::percent(5500),
+            hops: vec![
+                HopEstimate {
+                    pool_id: 1,
+                    token_out: Coin::new(1782, "uion"),
+                    spot_price: Decimal::percent(200),
+                    // 1.8 instead of 2
+                    price_impact: Decimal::percent(1000),
+                },
+                HopEstimate {
+                    pool_id: 2,
+                    token_out: Coin::new(1782, "uatom"),
+                    spot_price: Decimal::percent(200),
+                    // 1 instead of 2
+                    price_impact: Decimal::percent(5000),
+                },
+            ],
+        }
+    );
+
+    let err = affiliate_swap
+        .get_swap_estimate((deps.as_ref(), mock_env()), vec![], Coin::new(990, "uosmo"))
+        .unwrap_err();
+    assert_eq!(err, ContractError::EmptyRoutes {});
+    // the backend rejects them too, e.g. for a settlement swap
+    let err = OsmosisBackend
+        .estimate_swap(
+            &deps.as_ref().querier,
+            &Addr::unchecked("contract"),
+            &[],
+            &Coin::new(990, "uosmo"),
+        )
+        .unwrap_err();
+    assert_eq!(err, ContractError::EmptyRoutes {});
+}
+
+#[test]
+fn test_callback_targets() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    let set_target = |deps: DepsMut, sender: &str, address: &str, gas_limit: Option<u64>| {
+        affiliate_swap.set_callback_target(
+            (deps, mock_env(), mock_info(sender, &[])),
+            address.to_string(),
+            gas_limit.map(|gas_limit| CallbackPolicy {
+                gas_limit,
+                allow_funds: true,
+            }),
+        )
+    };
+
+    let err = set_target(deps.as_mut(), SENDER, "vault", Some(100_000)).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    for gas_limit in [0, MAX_CALLBACK_GAS_LIMIT + 1] {
+        let err = set_target(deps.as_mut(), OWNER, "vault", Some(gas_limit)).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InvalidCallbackGasLimit {
+                max: MAX_CALLBACK_GAS_LIMIT
+            }
+        );
+    }
+    let contract = mock_env().contract.address;
+    let err = set_target(deps.as_mut(), OWNER, contract.as_str(), Some(100_000)).unwrap_err();
+    assert_eq!(err, ContractError::InvalidCallbackTarget {});
+
+    set_target(deps.as_mut(), OWNER, "vault", Some(100_000)).unwrap();
+    set_target(deps.as_mut(), OWNER, "bridge", Some(MAX_CALLBACK_GAS_LIMIT)).unwrap();
+    let target = affiliate_swap
+        .get_callback_target((deps.as_ref(), mock_env()), "vault".to_string())
+        .unwrap();
+    assert_eq!(
+        target.policy,
+        Some(CallbackPolicy {
+            gas_limit: 100_000,
+            allow_funds: true,
+        })
+    );
+    let targets = affiliate_swap
+        .list_callback_targets(
+            (deps.as_ref(), mock_env()),
+            Some("bridge".to_string()),
+            None,
+        )
+        .unwrap();
+    assert_eq!(
+        targets.targets,
+        vec![CallbackTargetEntry {
+            address: Addr::unchecked("vault"),
+            policy: CallbackPolicy {
+                gas_limit: 100_000,
+                allow_funds: true,
+            },
+        }]
+    );
+
+    set_target(deps.as_mut(), OWNER, "vault", None).unwrap();
+    let target = affiliate_swap
+        .get_callback_target((deps.as_ref(), mock_env()), "vault".to_string())
+        .unwrap();
+    assert_eq!(target.policy, None);
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_after_swap() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+    let set_target = |deps: DepsMut, allow_funds: bool| {
+        affiliate_swap
+            .set_callback_target(
+                (deps, mock_env(), mock_info(OWNER, &[])),
+                "vault".to_string(),
+                Some(CallbackPolicy {
+                    gas_limit: 100_000,
+                    allow_funds,
+                }),
+            )
+            .unwrap()
+    };
+    let swap = |deps: DepsMut| {
+        execute(
+            deps,
+            mock_env(),
+            mock_info(SENDER, &[Coin::new(1000, "uosmo")]),
+            ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+                routes: vec![SwapAmountInRoute {
+                    pool_id: 1,
+                    token_out_denom: "uion".to_string(),
+                }],
+                token_out_min_amount: Some(Coin::new(1, "uion")),
+                fee_percentage: Some(Decimal::one()),
+                fee_collector: Some(COLLECTOR.to_string()),
+                on_failure: None,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: None,
+                ibc_origin: None,
+                quote: None,
+                expected_out: None,
+                slippage_tolerance: None,
+                max_price_impact: None,
+                response_data: None,
+                with_data: None,
+                after: Some(AfterSwap::Execute {
+                    contract: "vault".to_string(),
+                    msg: Binary::from(br#"{"deposit":{}}"#),
+                }),
+                client_id: None,
+                channel: None,
+                fee_override: None,
+            }),
+        )
+    };
+
+    // only callback targets allowing funds can receive the output
+    let err = swap(deps.as_mut()).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::CallbackNotAllowed {
+            address: "vault".to_string()
+        }
+    );
+    set_target(deps.as_mut(), false);
+    swap(deps.as_mut()).unwrap_err();
+    set_target(deps.as_mut(), true);
+    swap(deps.as_mut()).unwrap();
+
+    // the output is sent with the execution, bounded by the gas limit of the target
+    let res = simple_reply(deps.as_mut(), 900);
+    let output = res
+        .messages
+        .iter()
+        .find(|m| m.id == ReplyKind::OutputTransfer.reply_id(1))
+        .unwrap();
+    assert_eq!(output.gas_limit, Some(100_000));
+    assert_eq!(
+        output.msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "vault".to_string(),
+            msg: Binary::from(br#"{"deposit":{}}"#),
+            funds: vec![Coin::new(900, "uion")],
+        })
+    );
+    let swap_event = res
+        .events
+        .iter()
+        .find(|e| e.ty == "affiliate_swap")
+        .unwrap();
+    assert!(swap_event
+        .attributes
+        .iter()
+        .any(|a| a.key == "after_execute" && a.value == "vault"));
+
+    // a failed execution escrows the output for the sender
+    reply(
+        deps.as_mut(),
+        mock_env(),
+        Reply {
+            id: ReplyKind::OutputTransfer.reply_id(1),
+            result: SubMsgResult::Err("out of gas".to_string()),
+        },
+    )
+    .unwrap();
+    let claimable = affiliate_swap
+        .get_claimable((deps.as_ref(), mock_env()), SENDER.to_string())
+        .unwrap();
+    assert_eq!(claimable.coins, vec![Coin::new(900, "uion")]);
+
+    // the rebate is sent to the sender apart, and escrowed for it if the transfer fails
+    affiliate_swap
+        .update_config(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            None,
+            None,
+            None,
+            None,
+            Some(Decimal::from_str("25").unwrap()),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+    swap(deps.as_mut()).unwrap();
+    let res = reply_with_id(deps.as_mut(), 2, 900);
+    let rebate = res
+        .messages
+        .iter()
+        .find(|m| m.id == ReplyKind::RebateTransfer.reply_id(2))
+        .unwrap();
+    assert_eq!(rebate.reply_on, ReplyOn::Always);
+    assert!(is_valid_bank_send_msg(
+        &rebate.msg,
+        SENDER,
+        2u128.into(),
+        "uosmo"
+    ));
+    let res = reply(
+        deps.as_mut(),
+        mock_env(),
+        Reply {
+            id: ReplyKind::RebateTransfer.reply_id(2),
+            result: SubMsgResult::Err("blocked address".to_string()),
+        },
+    )
+    .unwrap();
+    assert!(res
+        .events
+        .iter()
+        .any(|e| e.ty == "affiliate_fee_share_escrowed"));
+    let claimable = affiliate_swap
+        .get_claimable((deps.as_ref(), mock_env()), SENDER.to_string())
+        .unwrap();
+    assert_eq!(
+        claimable.coins,
+        vec![Coin::new(900, "uion"), Coin::new(2, "uosmo")]
+    );
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_after_swap_vault_deposit() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+    deps.querier.update_wasm(|query| {
+        let WasmQuery::Smart { contract_addr, msg } = query else {
+            panic!("unexpected query {query:?}");
+        };
+        let VaultQueryMsg::Info {} = from_binary(msg).unwrap();
+        let base_token = match contract_addr.as_str() {
+            "vault" => "uion",
+            _ => "uatom",
+        };
+        SystemResult::Ok(ContractResult::Ok(
+            to_binary(&serde_json::json!({
+                "base_token": base_token,
+                "vault_token": format!("factory/{contract_addr}/shares"),
+            }))
+            .unwrap(),
+        ))
+    });
+    for vault in ["vault", "atom_vault"] {
+        affiliate_swap
+            .set_callback_target(
+                (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+                vault.to_string(),
+                Some(CallbackPolicy {
+                    gas_limit: 500_000,
+                    allow_funds: true,
+                }),
+            )
+            .unwrap();
+    }
+    let swap = |deps: DepsMut, vault: &str| {
+        execute(
+            deps,
+            mock_env(),
+            mock_info(SENDER, &[Coin::new(1000, "uosmo")]),
+            ContractExecMsg::AffiliateSwapInterface(ExecMsg::Swap {
+                routes: vec![SwapAmountInRoute {
+                    pool_id: 1,
+                    token_out_denom: "uion".to_string(),
+                }],
+                token_out_min_amount: Some(Coin::new(1, "uion")),
+                fee_percentage: Some(Decimal::one()),
+                fee_collector: Some(COLLECTOR.to_string()),
+                on_failure: None,
+                token_in_denom: None,
+                allow_cyclic: None,
+                receiver: Some("receiver".to_string()),
+                ibc_origin: None,
+                quote: None,
+                expected_out: None,
+                slippage_tolerance: None,
+                max_price_impact: None,
+                response_data: None,
+                with_data: None,
+                after: Some(AfterSwap::VaultDeposit {
+                    vault: vault.to_string(),
+                }),
+                client_id: None,
+                channel: None,
+                fee_override: None,
+            }),
+        )
+    };
+
+    // the vault must take deposits in the output denom
+    let err = swap(deps.as_mut(), "atom_vault").unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::VaultDenomMismatch {
+            base_token: "uatom".to_string()
+        }
+    );
+    swap(deps.as_mut(), "vault").unwrap();
+
+    // the output is deposited, the shares minted to the receiver
+    let res = simple_reply(deps.as_mut(), 900);
+    let output = res
+        .messages
+        .iter()
+        .find(|m| m.id == ReplyKind::OutputTransfer.reply_id(1))
+        .unwrap();
+    assert_eq!(output.gas_limit, Some(500_000));
+    assert_eq!(
+        output.msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "vault".to_string(),
+            msg: to_binary(&VaultExecuteMsg::Deposit {
+                amount: Uint128::new(900),
+                recipient: Some("receiver".to_string()),
+            })
+            .unwrap(),
+            funds: vec![Coin::new(900, "uion")],
+        })
+    );
+    let swap_event = res
+        .events
+        .iter()
+        .find(|e| e.ty == "affiliate_swap")
+        .unwrap();
+    assert!(swap_event
+        .attributes
+        .iter()
+        .any(|a| a.key == "after_vault_deposit" && a.value == "vault"));
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_fee_settlement() {
+    let affiliate_swap = AffiliateSwap::new();
+    // the settlement swap of the fee is estimated to 2500uusdc
+    let mut deps = setup_estimate(2500, "1");
+    let set_route = |deps: DepsMut, routes: Option<Vec<(u64, &str)>>| {
+        affiliate_swap.set_settlement_route(
+            (deps, mock_env(), mock_info(COLLECTOR, &[])),
+            "uosmo".to_string(),
+            routes.map(|routes| {
+                routes
+                    .into_iter()
+                    .map(|(pool_id, denom)| SwapAmountInRoute {
+                        pool_id,
+                        token_out_denom: denom.to_string(),
+                    })
+                    .collect()
+            }),
+        )
+    };
+    let min_out = |msg: &CosmosMsg| match msg {
+        CosmosMsg::Stargate { value, .. } => {
+            let swap: MsgSwapExactAmountIn = value.clone().try_into().unwrap();
+            swap.token_out_min_amount
+        }
+        _ => panic!("not a swap"),
+    };
+    let settlement_reply = |deps: DepsMut, swap_id: u64, result: SubMsgResult| {
+        reply(
+            deps,
+            mock_env(),
+            Reply {
+                id: ReplyKind::FeeSettlement.reply_id(swap_id),
+                result,
+            },
+        )
+        .unwrap()
+    };
+
+    let err = set_route(deps.as_mut(), Some(vec![])).unwrap_err();
+    assert_eq!(err, ContractError::EmptyRoutes {});
+    let err = set_route(deps.as_mut(), Some(vec![(1, "uion"), (2, "uosmo")])).unwrap_err();
+    assert_eq!(err, ContractError::CyclicSettlementRoute {});
+    affiliate_swap
+        .set_pool_denied((deps.as_mut(), mock_env(), mock_info(OWNER, &[])), 9, true)
+        .unwrap();
+    let err = set_route(deps.as_mut(), Some(vec![(9, "uusdc")])).unwrap_err();
+    assert_eq!(err, ContractError::PoolDenied { pool_id: 9 });
+    set_route(deps.as_mut(), Some(vec![(3, "uusdc")])).unwrap();
+    let route = affiliate_swap
+        .get_settlement_route(
+            (deps.as_ref(), mock_env()),
+            COLLECTOR.to_string(),
+            "uosmo".to_string(),
+        )
+        .unwrap();
+    assert_eq!(
+        route.routes,
+        Some(vec![SwapAmountInRoute {
+            pool_id: 3,
+            token_out_denom: "uusdc".to_string(),
+        }])
+    );
+
+    // the 10uosmo fee is swapped into uusdc, for at least its estimate minus 1%, then paid
+    simple_execute(deps.as_mut(), 1000, Some(Decimal::one()));
+    let res = simple_reply(deps.as_mut(), 900);
+    assert_eq!(res.messages[0].id, ReplyKind::FeeSettlement.reply_id(1));
+    assert_eq!(res.messages[0].reply_on, ReplyOn::Always);
+    assert!(is_valid_swap_msg(
+        &res.messages[0].msg,
+        Coin::new(10, "uosmo")
+    ));
+    assert_eq!(min_out(&res.messages[0].msg), "2475");
+    let res = settlement_reply(
+        deps.as_mut(),
+        1,
+        SubMsgResult::Ok(SubMsgResponse {
+            events: vec![],
+            data: Some(
+                MsgSwapExactAmountInResponse {
+                    token_out_amount: "2490".to_string(),
+                }
+                .into(),
+            ),
+        }),
+    );
+    assert!(is_valid_bank_send_msg(
+        &res.messages[0].msg,
+        COLLECTOR,
+        2490u128.into(),
+        "uusdc"
+    ));
+
+    // a failed settlement, e.g. below its min output, pays the fee as is
+    affiliate_swap
+        .update_config(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(Decimal::from_str("5").unwrap()),
+        )
+        .unwrap();
+    simple_execute(deps.as_mut(), 1000, Some(Decimal::one()));
+    let res = reply_with_id(deps.as_mut(), 2, 900);
+    assert_eq!(min_out(&res.messages[0].msg), "2375");
+    let res = settlement_reply(
+        deps.as_mut(),
+        2,
+        SubMsgResult::Err("insufficient liquidity".to_string()),
+    );
+    assert!(is_valid_bank_send_msg(
+        &res.messages[0].msg,
+        COLLECTOR,
+        10u128.into(),
+        "uosmo"
+    ));
+    assert!(affiliate_swap.pending_settlements.is_empty(&deps.storage));
+
+    // the fee is paid directly once the route goes through a denied pool
+    affiliate_swap
+        .set_pool_denied((deps.as_mut(), mock_env(), mock_info(OWNER, &[])), 3, true)
+        .unwrap();
+    simple_execute(deps.as_mut(), 1000, Some(Decimal::one()));
+    let res = reply_with_id(deps.as_mut(), 3, 900);
+    assert!(is_valid_bank_send_msg(
+        &res.messages[0].msg,
+        COLLECTOR,
+        10u128.into(),
+        "uosmo"
+    ));
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_balance_checks() {
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(Some(Decimal::from_str("5").unwrap()));
+    affiliate_swap
+        .update_config(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(true),
+            None,
+            None,
+        )
+        .unwrap();
+    let swap_reply = |deps: DepsMut, swap_id: u64| {
+        reply(
+            deps,
+            mock_env(),
+            Reply {
+                id: ReplyKind::Swap.reply_id(swap_id),
+                result: SubMsgResult::Ok(SubMsgResponse {
+                    events: vec![],
+                    data: Some(
+                        MsgSwapExactAmountInResponse {
+                            token_out_amount: "98".to_string(),
+                        }
+                        .into(),
+                    ),
+                }),
+            },
+        )
+    };
+    // the contract already holds 50uion escrowed for someone else
+    let before = vec![Coin::new(100, "uosmo"), Coin::new(50, "uion")];
+
+    // The balances moved by the reported amounts
+    deps.querier
+        .update_balance(MOCK_CONTRACT_ADDR, before.clone());
+    simple_execute(deps.as_mut(), 100, Some(Decimal::from_str("1").unwrap()));
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin::new(1, "uosmo"), Coin::new(148, "uion")],
+    );
+    let res = swap_reply(deps.as_mut(), 1).unwrap();
+    assert!(!res
+        .events
+        .iter()
+        .any(|e| e.ty == "affiliate_swap_balance_surplus"));
+
+    // The pool reported more than it paid: the output would be taken from the escrow
+    deps.querier
+        .update_balance(MOCK_CONTRACT_ADDR, before.clone());
+    simple_execute(deps.as_mut(), 100, Some(Decimal::from_str("1").unwrap()));
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin::new(1, "uosmo"), Coin::new(140, "uion")],
+    );
+    let err = swap_reply(deps.as_mut(), 2).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::BalanceMismatch {
+            expected: "148uion".to_string(),
+            actual: "140uion".to_string(),
+        }
+    );
+
+    // The pool paid more than it reported: the surplus is only reported
+    deps.querier.update_balance(MOCK_CONTRACT_ADDR, before);
+    simple_execute(deps.as_mut(), 100, Some(Decimal::from_str("1").unwrap()));
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin::new(1, "uosmo"), Coin::new(150, "uion")],
+    );
+    let res = swap_reply(deps.as_mut(), 3).unwrap();
+    let event = res
+        .events
+        .iter()
+        .find(|e| e.ty == "affiliate_swap_balance_surplus")
+        .unwrap();
+    let event_attributes = event
+        .attributes
+        .iter()
+        .map(|a| (a.key.clone(), a.value.clone()))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(event_attributes["swap_id"], "3");
+    assert_eq!(event_attributes["expected"], "148uion");
+    assert_eq!(event_attributes["actual"], "150uion");
+}
+
+#[test]
+#[cfg(not(feature = "astroport"))]
+fn test_swap_simulation() {
+    // 1782uion for the 990uosmo left after the fee, at a spot price of 2: a 10% impact
+    let deps = setup_estimate(1782, "2");
+    let affiliate_swap = AffiliateSwap::new();
+    let swap_msg = |min: u128, max_price_impact: Option<Decimal>| ExecMsg::Swap {
+        routes: vec![SwapAmountInRoute {
+            pool_id: 1,
+            token_out_denom: "uion".to_string(),
+        }],
+        token_out_min_amount: Some(Coin::new(min, "uion")),
+        fee_percentage: Some(Decimal::one()),
+        fee_collector: Some(COLLECTOR.to_string()),
+        on_failure: None,
+        token_in_denom: None,
+        allow_cyclic: None,
+        receiver: None,
+        ibc_origin: None,
+        quote: None,
+        expected_out: None,
+        slippage_tolerance: None,
+        max_price_impact,
+        response_data: None,
+        with_data: None,
+        after: None,
+        client_id: None,
+        channel: None,
+        fee_override: None,
+    };
+    let simulate = |funds: Vec<Coin>, msg: ExecMsg| {
+        affiliate_swap.get_swap_simulation(
+            (deps.as_ref(), mock_env()),
+            SENDER.to_string(),
+            funds,
+            msg,
+        )
+    };
+
+    let simulation = simulate(vec![Coin::new(1000, "uosmo")], swap_msg(1700, None)).unwrap();
+    assert_eq!(
+        simulation,
+        SwapSimulationResponse {
+            fee_percentage: Decimal::one(),
+            fee: Coin::new(10, "uosmo"),
+            rebate: Coin::new(0, "uosmo"),
+            fee_collector: Addr::unchecked(COLLECTOR),
+            token_in: Coin::new(990, "uosmo"),
+            token_out: Coin::new(1782, "uion"),
+            price_impact: Decimal::percent(1000),
+            token_out_min: Coin::new(1700, "uion"),
+            min_output_met: true,
+        }
+    );
+    let simulation = simulate(vec![Coin::new(1000, "uosmo")], swap_msg(1800, None)).unwrap();
+    assert!(!simulation.min_output_met);
+
+    // fails where the swap would, before reaching the pools
+    let err = simulate(
+        vec![Coin::new(1000, "uosmo")],
+        swap_msg(1700, Some(Decimal::percent(500))),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::PriceImpactTooHigh {
+            impact: "10".to_string(),
+            max: "5".to_string(),
+        }
+    );
+    let err = simulate(vec![], swap_msg(1700, None)).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::Payment(cw_utils::PaymentError::NoFunds {})
+    );
+    let err = simulate(
+        vec![],
+        ExecMsg::Claim {
+            recipient: None,
+            claimant: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::NotASwap {});
+}
+
+// cargo test --features astroport astroport
+#[test]
+#[cfg(feature = "astroport")]
+fn test_astroport_backend() {
+    use crate::astroport::{
+        AssetInfo, AstroportBackend, FactoryQueryMsg, PairQueryMsg, RouterExecuteMsg,
+        RouterQueryMsg, SwapOperation,
+    };
+    use crate::backend::SwapBackend;
+    use cosmwasm_std::Event;
+
+    let affiliate_swap = AffiliateSwap::new();
+    let mut deps = setup_unit(None);
+    let err = AstroportBackend::load(&deps.storage).err().unwrap();
+    assert_eq!(err, ContractError::SwapRouterRequired {});
+    affiliate_swap
+        .update_config(
+            (deps.as_mut(), mock_env(), mock_info(OWNER, &[])),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some("router".to_string()),
+            None,
+        )
+        .unwrap();
+    let backend = AstroportBackend::load(&deps.storage).unwrap();
+    assert_eq!(backend.router, Addr::unchecked("router"));
+
+    let routes = vec![
+        SwapAmountInRoute {
+            pool_id: 0,
+            token_out_denom: "uion".to_string(),
+        },
+        SwapAmountInRoute {
+            pool_id: 0,
+            token_out_denom: "uatom".to_string(),
+        },
+    ];
+    let native = |denom: &str| AssetInfo::NativeToken {
+        denom: denom.to_string(),
+    };
+    let operations = vec![
+        SwapOperation::AstroSwap {
+            offer_asset_info: native("uosmo"),
+            ask_asset_info: native("uion"),
+        },
+        SwapOperation::AstroSwap {
+            offer_asset_info: native("uion"),
+            ask_asset_info: native("uatom"),
+        },
+    ];
+
+    // The router swaps the input along the route and sends the output back
+    let msg = backend
+        .swap_msg(
+            &Addr::unchecked(MOCK_CONTRACT_ADDR),
+            routes.clone(),
+            &Coin::new(99, "uosmo"),
+            Uint128::new(90),
+        )
+        .unwrap();
+    assert_eq!(
+        msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "router".to_string(),
+            msg: to_binary(&RouterExecuteMsg::ExecuteSwapOperations {
+                operations: operations.clone(),
+                minimum_receive: Some(Uint128::new(90)),
+                to: None,
+                max_spread: Some(Decimal::percent(50)),
+            })
+            .unwrap(),
+            funds: vec![Coin::new(99, "uosmo")],
+        })
+    );
+
+    // The output is the return amount of the last hop
+    let swap_event = |return_amount: &str| {
+        Event::new("wasm")
+            .add_attribute("action", "swap")
+            .add_attribute("return_amount", return_amount)
+    };
+    let output = backend
+        .swap_output(SubMsgResult::Ok(SubMsgResponse {
+            events: vec![
+                swap_event("150"),
+                Event::new("wasm").add_attribute("action", "transfer"),
+                swap_event("98"),
+                Event::new("transfer").add_attribute("amount", "98uatom"),
+            ],
+            data: None,
+        }))
+        .unwrap();
+    assert_eq!(output, Uint128::new(98));
+    let err = backend
+        .swap_output(SubMsgResult::Ok(SubMsgResponse {
+            events: vec![],
+            data: None,
+        }))
+        .unwrap_err();
+    assert_eq!(err, ContractError::SwapOutputMissing {});
+
+    // Estimates are simulated by the router, spot prices read from the pair reserves
+    let expected_operations = operations;
+    deps.querier.update_wasm(move |query| {
+        let WasmQuery::Smart { contract_addr, msg } = query else {
+            panic!("unexpected query {query:?}");
+        };
+        let response = match contract_addr.as_str() {
+            "router" => match from_binary(msg).unwrap() {
+                RouterQueryMsg::Config {} => to_binary(&serde_json::json!({
+                    "astroport_factory": "factory",
+                    "astroport_ibc": null,
+                })),
+                RouterQueryMsg::SimulateSwapOperations {
+                    offer_amount,
+                    operations,
+                } => {
+                    assert_eq!(offer_amount, Uint128::new(99));
+                    assert_eq!(operations, expected_operations);
+                    to_binary(&serde_json::json!({ "amount": "95" }))
+                }
+            },
+            "factory" => {
+                let FactoryQueryMsg::Pair { asset_infos } = from_binary(msg).unwrap();
+                let pair = match &asset_infos[1] {
+                    AssetInfo::NativeToken { denom } => format!("pair_{denom}"),
+                    _ => panic!("unexpected asset"),
+                };
+                to_binary(&serde_json::json!({
+                    "asset_infos": asset_infos,
+                    "contract_addr": pair,
+                    "pair_type": { "xyk": {} },
+                }))
+            }
+            pair => {
+                let PairQueryMsg::Pool {} = from_binary(msg).unwrap();
+                // 1uosmo is 2uion, 1uion is 3uatom
+                let reserves = match pair {
+                    "pair_uion" => [("uosmo", "1000"), ("uion", "2000")],
+                    _ => [("uion", "1000"), ("uatom", "3000")],
+                };
+                to_binary(&serde_json::json!({
+                    "assets": reserves.map(|(denom, amount)| serde_json::json!({
+                        "info": { "native_token": { "denom": denom } },
+                        "amount": amount,
+                    })),
+                    "total_share": "1000",
+                }))
+            }
+        };
+        SystemResult::Ok(ContractResult::Ok(response.unwrap()))
+    });
+    let querier = deps.as_ref().querier;
+    let estimate = backend
+        .estimate_swap(
+            &querier,
+            &Addr::unchecked(MOCK_CONTRACT_ADDR),
+            &routes,
+            &Coin::new(99, "uosmo"),
+        )
+        .unwrap();
+    assert_eq!(estimate, Uint128::new(95));
+    let spot_price = backend
+        .route_spot_price(&querier, &routes, "uosmo")
+        .unwrap();
+    assert_eq!(spot_price, Decimal::percent(600));
 }
@@ -0,0 +1,27 @@
+//! Messages of the cw-vault-standard used by the `vault_deposit` after-swap action.
+//!
+//! Only the deposit and the base token query are needed, so they are declared here rather than
+//! depending on the standard crate. Vaults mint their shares to the recipient of the deposit.
+
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::Uint128;
+use serde::Deserialize;
+
+#[cw_serde]
+pub enum VaultExecuteMsg {
+    Deposit {
+        amount: Uint128,
+        recipient: Option<String>,
+    },
+}
+
+#[cw_serde]
+pub enum VaultQueryMsg {
+    Info {},
+}
+
+// Only the base token is read, the denom the vault accepts deposits in
+#[derive(Deserialize)]
+pub struct VaultInfoResponse {
+    pub base_token: String,
+}